@@ -46,6 +46,8 @@ mod codes {
     pub const ERR_REQUEST_TIMEOUT: i32 = -17;
     pub const ERR_CONFIG_FILE: i32 = -18;
     pub const ERR_IO: i32 = -19;
+    pub const ERR_INVALID_LINK: i32 = -20;
+    pub const ERR_INVALID_SNAPSHOT: i32 = -21;
 
     // routing Client errors
     pub const ERR_ACCESS_DENIED: i32 = -100;
@@ -82,6 +84,10 @@ mod codes {
     pub const ERR_FILE_EXISTS: i32 = -300;
     pub const ERR_FILE_NOT_FOUND: i32 = -301;
     pub const ERR_INVALID_RANGE: i32 = -302;
+    pub const ERR_DIRECTORY_NOT_EMPTY: i32 = -303;
+    pub const ERR_INVALID_PATH: i32 = -304;
+    pub const ERR_CONFLICT: i32 = -305;
+    pub const ERR_COMPRESSED_FILE: i32 = -306;
 
     // App errors
     pub const ERR_NO_SUCH_CONTAINER: i32 = -1002;
@@ -101,6 +107,8 @@ mod codes {
     pub const ERR_INVALID_FILE_MODE: i32 = -1016;
     pub const ERR_INVALID_SIGN_SEC_KEY_HANDLE: i32 = -1017;
     pub const ERR_UNREGISTERED_CLIENT_ACCESS: i32 = -1018;
+    pub const ERR_INVALID_DIR_WATCHER_HANDLE: i32 = -1019;
+    pub const ERR_INVALID_MDATA_ENTRIES_ITER_HANDLE: i32 = -1020;
 
     pub const ERR_UNEXPECTED: i32 = -2000;
 }
@@ -146,6 +154,10 @@ pub enum AppError {
     InvalidSignSecKeyHandle,
     /// Invalid file writer handle.
     InvalidFileContextHandle,
+    /// Invalid directory watcher handle.
+    InvalidDirWatcherHandle,
+    /// Invalid MutableData entries iterator handle.
+    InvalidMDataEntriesIterHandle,
 
     /// Error while self-encrypting data.
     SelfEncryption(SelfEncryptionError<SelfEncryptionStorageError>),
@@ -201,6 +213,12 @@ impl Display for AppError {
             }
             AppError::InvalidEncryptSecKeyHandle => write!(formatter, "Invalid secret key handle"),
             AppError::InvalidFileContextHandle => write!(formatter, "Invalid file context handle"),
+            AppError::InvalidDirWatcherHandle => {
+                write!(formatter, "Invalid directory watcher handle")
+            }
+            AppError::InvalidMDataEntriesIterHandle => {
+                write!(formatter, "Invalid MutableData entries iterator handle")
+            }
             AppError::SelfEncryption(ref error) => {
                 write!(formatter, "Self-encryption error: {}", error)
             }
@@ -345,6 +363,10 @@ impl ErrorCode for AppError {
                 NfsError::FileExists => ERR_FILE_EXISTS,
                 NfsError::FileNotFound => ERR_FILE_NOT_FOUND,
                 NfsError::InvalidRange => ERR_INVALID_RANGE,
+                NfsError::DirectoryNotEmpty => ERR_DIRECTORY_NOT_EMPTY,
+                NfsError::InvalidPath => ERR_INVALID_PATH,
+                NfsError::Conflict(_) => ERR_CONFLICT,
+                NfsError::CompressedFile => ERR_COMPRESSED_FILE,
                 NfsError::EncodeDecodeError(_) => ERR_ENCODE_DECODE_ERROR,
                 NfsError::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
                 NfsError::Unexpected(_) => ERR_UNEXPECTED,
@@ -362,6 +384,8 @@ impl ErrorCode for AppError {
             AppError::InvalidSignSecKeyHandle => ERR_INVALID_SIGN_SEC_KEY_HANDLE,
             AppError::InvalidEncryptSecKeyHandle => ERR_INVALID_ENCRYPT_SEC_KEY_HANDLE,
             AppError::InvalidFileContextHandle => ERR_INVALID_FILE_CONTEXT_HANDLE,
+            AppError::InvalidDirWatcherHandle => ERR_INVALID_DIR_WATCHER_HANDLE,
+            AppError::InvalidMDataEntriesIterHandle => ERR_INVALID_MDATA_ENTRIES_ITER_HANDLE,
             AppError::InvalidFileMode => ERR_INVALID_FILE_MODE,
             AppError::UnregisteredClientAccess => ERR_UNREGISTERED_CLIENT_ACCESS,
             AppError::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
@@ -413,6 +437,8 @@ fn core_error_code(err: &CoreError) -> i32 {
         CoreError::RequestTimeout => ERR_REQUEST_TIMEOUT,
         CoreError::ConfigError(_) => ERR_CONFIG_FILE,
         CoreError::IoError(_) => ERR_IO,
+        CoreError::InvalidLink(_) => ERR_INVALID_LINK,
+        CoreError::InvalidSnapshot(_) => ERR_INVALID_SNAPSHOT,
         CoreError::Unexpected(_) => ERR_UNEXPECTED,
     }
 }
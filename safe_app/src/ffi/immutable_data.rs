@@ -10,14 +10,19 @@
 use crate::cipher_opt::CipherOpt;
 use crate::errors::AppError;
 use crate::ffi::object_cache::{CipherOptHandle, SelfEncryptorReaderHandle, SelfEncryptorWriterHandle};
-use ffi_utils::{catch_unwind_cb, vec_clone_from_raw_parts, FfiResult, OpaqueCtx, FFI_RESULT_OK};
+use ffi_utils::{
+    catch_unwind_cb, from_c_str, vec_clone_from_raw_parts, FfiResult, OpaqueCtx, FFI_RESULT_OK,
+};
 use futures::Future;
 use maidsafe_utilities::serialisation::{deserialise, serialise};
 use routing::XorName;
 use safe_core::ffi::arrays::XorNameArray;
+use safe_core::public_link::{self, PublicLink};
 use safe_core::{immutable_data, Client, FutureExt, SelfEncryptionStorage};
 use self_encryption::{SelfEncryptor, SequentialEncryptor};
-use std::os::raw::c_void;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
 use crate::App;
 
 /// Handle of a Self Encryptor Writer object.
@@ -289,6 +294,94 @@ pub unsafe extern "C" fn idata_read_from_self_encryptor(
     });
 }
 
+/// Store the data as a public file, readable by anyone knowing the link to it. `content_type` may
+/// be null. The link is returned as a URI which can be resolved with `idata_resolve_link`.
+#[no_mangle]
+pub unsafe extern "C" fn idata_publish(
+    app: *const App,
+    data: *const u8,
+    data_len: usize,
+    content_type: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, link: *const c_char),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let data = vec_clone_from_raw_parts(data, data_len);
+        let content_type = if content_type.is_null() {
+            None
+        } else {
+            Some(from_c_str(content_type)?)
+        };
+
+        (*app).send(move |client, _| {
+            public_link::publish(client, &data, content_type)
+                .map_err(AppError::from)
+                .and_then(|link| Ok(CString::new(link.to_uri())?))
+                .then(move |result| {
+                    match result {
+                        Ok(link) => o_cb(user_data.0, FFI_RESULT_OK, link.as_ptr()),
+                        res @ Err(..) => {
+                            call_result_cb!(res, user_data, o_cb);
+                        }
+                    }
+                    Ok(())
+                }).into_box()
+                .into()
+        })
+    });
+}
+
+/// Fetch the content of the public file the link refers to. The content type is null if the link
+/// doesn't specify it.
+#[no_mangle]
+pub unsafe extern "C" fn idata_resolve_link(
+    app: *const App,
+    link: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        data: *const u8,
+        data_len: usize,
+        content_type: *const c_char,
+    ),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let link = PublicLink::parse(&from_c_str(link)?)?;
+        let content_type = match link.content_type {
+            Some(ref content_type) => Some(CString::new(content_type.as_str())?),
+            None => None,
+        };
+
+        (*app).send(move |client, _| {
+            public_link::resolve(client, &link)
+                .map_err(AppError::from)
+                .then(move |result| {
+                    match result {
+                        Ok(data) => o_cb(
+                            user_data.0,
+                            FFI_RESULT_OK,
+                            data.as_ptr(),
+                            data.len(),
+                            content_type
+                                .as_ref()
+                                .map_or(ptr::null(), |content_type| content_type.as_ptr()),
+                        ),
+                        res @ Err(..) => {
+                            call_result_cb!(res, user_data, o_cb);
+                        }
+                    }
+                    Ok(())
+                }).into_box()
+                .into()
+        })
+    });
+}
+
 /// Free Self Encryptor Writer handle.
 #[no_mangle]
 pub unsafe extern "C" fn idata_self_encryptor_writer_free(
@@ -332,9 +425,12 @@ mod tests {
     use super::*;
     use crate::errors::AppError;
     use crate::ffi::cipher_opt::*;
-    use ffi_utils::test_utils::{call_0, call_1, call_vec_u8};
+    use ffi_utils::test_utils::{
+        call_0, call_1, call_vec_u8, send_via_user_data, sender_as_user_data,
+    };
     use ffi_utils::ErrorCode;
-    use safe_core::utils;
+    use safe_core::{utils, CoreError};
+    use std::sync::mpsc;
     use crate::test_utils::create_app;
 
     // Test immutable data operations.
@@ -431,4 +527,67 @@ mod tests {
             unwrap!(call_0(|ud, cb| cipher_opt_free(&app, cipher_opt_h, ud, cb)));
         }
     }
+
+    // Test publishing data and resolving the link to it.
+    #[test]
+    fn publish_and_resolve_link() {
+        let app = create_app();
+
+        let content = unwrap!(utils::generate_random_vector::<u8>(1000));
+        let content_type = unwrap!(CString::new("text/plain"));
+
+        let link: String = unsafe {
+            unwrap!(call_1(|ud, cb| idata_publish(
+                &app,
+                content.as_ptr(),
+                content.len(),
+                content_type.as_ptr(),
+                ud,
+                cb,
+            )))
+        };
+        let parsed = unwrap!(PublicLink::parse(&link));
+        assert_eq!(parsed.content_type, Some("text/plain".to_owned()));
+        assert_eq!(parsed.size, Some(content.len() as u64));
+
+        let link = unwrap!(CString::new(link));
+        let (tx, rx) = mpsc::channel::<Result<(Vec<u8>, Option<String>), i32>>();
+        let mut ud = Default::default();
+        unsafe { idata_resolve_link(&app, link.as_ptr(), sender_as_user_data(&tx, &mut ud), cb) };
+        let (resolved, resolved_content_type) = unwrap!(unwrap!(rx.recv()));
+        assert_eq!(resolved, content);
+        assert_eq!(resolved_content_type, Some("text/plain".to_owned()));
+
+        // Malformed links are rejected.
+        let link = unwrap!(CString::new("safe-idata://1234"));
+        let mut ud = Default::default();
+        unsafe { idata_resolve_link(&app, link.as_ptr(), sender_as_user_data(&tx, &mut ud), cb) };
+        assert_eq!(
+            unwrap!(rx.recv()),
+            Err(AppError::from(CoreError::InvalidLink(String::new())).error_code())
+        );
+
+        extern "C" fn cb(
+            user_data: *mut c_void,
+            res: *const FfiResult,
+            data: *const u8,
+            data_len: usize,
+            content_type: *const c_char,
+        ) {
+            unsafe {
+                let result = if (*res).error_code == 0 {
+                    let content_type = if content_type.is_null() {
+                        None
+                    } else {
+                        Some(unwrap!(from_c_str(content_type)))
+                    };
+                    Ok((vec_clone_from_raw_parts(data, data_len), content_type))
+                } else {
+                    Err((*res).error_code)
+                };
+
+                send_via_user_data(user_data, result);
+            }
+        }
+    }
 }
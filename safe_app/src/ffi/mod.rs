@@ -39,6 +39,7 @@ mod helper;
 #[cfg(test)]
 mod tests;
 
+use self::helper::send_sync;
 use super::errors::AppError;
 use super::App;
 use config_file_handler;
@@ -48,7 +49,7 @@ use maidsafe_utilities::serialisation::deserialise;
 use safe_core::ffi::ipc::resp::AuthGranted;
 use safe_core::ffi::AccountInfo;
 use safe_core::ipc::{AuthGranted as NativeAuthGranted, BootstrapConfig};
-use safe_core::{self, Client, FutureExt};
+use safe_core::{self, Client, DiskCache, FutureExt};
 use std::ffi::{CStr, CString, OsStr};
 use std::os::raw::{c_char, c_void};
 use std::slice;
@@ -129,6 +130,46 @@ pub unsafe extern "C" fn app_reconnect(
     })
 }
 
+/// Keep the `ImmutableData` fetched by the app in an encrypted cache on disk, in the directory
+/// `cache_dir`, up to `capacity` bytes. The cache is kept between sessions, so the data doesn't
+/// have to be fetched from the network again after the app restarts. Pass a null `cache_dir` to
+/// stop using the cache.
+///
+/// The directory should be dedicated to the cache. Files in it which aren't cached chunks are left
+/// untouched.
+///
+/// The cache is encrypted with the app's key, so it's not available to unregistered apps.
+#[no_mangle]
+pub unsafe extern "C" fn app_set_disk_cache(
+    app: *mut App,
+    cache_dir: *const c_char,
+    capacity: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let cache_dir = if cache_dir.is_null() {
+            None
+        } else {
+            Some(from_c_str(cache_dir)?)
+        };
+
+        send_sync(app, user_data, o_cb, move |client, _| {
+            let disk_cache = match cache_dir {
+                Some(cache_dir) => {
+                    let key = client
+                        .secret_symmetric_key()
+                        .ok_or(AppError::UnregisteredClientAccess)?;
+                    Some(DiskCache::open(cache_dir, key, capacity)?)
+                }
+                None => None,
+            };
+            client.set_disk_cache(disk_cache);
+            Ok(())
+        })
+    })
+}
+
 /// Get the account usage statistics (mutations done and mutations available).
 #[no_mangle]
 pub unsafe extern "C" fn app_account_info(
@@ -10,15 +10,18 @@
 //! FFI for mutable data entries, keys and values.
 
 use crate::errors::AppError;
-use crate::ffi::helper::send_sync;
-use crate::ffi::object_cache::MDataEntriesHandle;
+use crate::ffi::helper::{send, send_sync};
+use crate::ffi::object_cache::{MDataEntriesHandle, MDataEntriesIterHandle};
 use ffi_utils::callback::Callback;
 use ffi_utils::{
-    catch_unwind_cb, vec_clone_from_raw_parts, FfiResult, OpaqueCtx, SafePtr, FFI_RESULT_OK,
+    catch_unwind_cb, vec_clone_from_raw_parts, FfiResult, OpaqueCtx, ReprC, SafePtr, FFI_RESULT_OK,
 };
+use futures::Future;
 use routing::{ClientError, Value};
+use safe_core::client::entry_pages::{EntryPages, DEFAULT_PAGE_SIZE};
 use safe_core::ffi::ipc::resp::{MDataEntry, MDataKey, MDataValue};
-use safe_core::CoreError;
+use safe_core::ffi::MDataInfo;
+use safe_core::{CoreError, MDataInfo as NativeMDataInfo};
 use std::collections::BTreeMap;
 use std::os::raw::c_void;
 use crate::App;
@@ -200,6 +203,114 @@ pub unsafe extern "C" fn mdata_entries_free(
     })
 }
 
+/// Start listing the entries of the mutable data page by page, ordered by their keys. Only the
+/// entries whose decrypted key starts with the given prefix are listed; an empty prefix lists all
+/// of them. A `page_size` of 0 means the default page size.
+///
+/// Unlike `mdata_entries`, the keys and values handed out by `mdata_entries_iter_next` are already
+/// decrypted. Deleted entries and the metadata entry are not listed.
+#[no_mangle]
+pub unsafe extern "C" fn mdata_entries_iter_new(
+    app: *const App,
+    info: *const MDataInfo,
+    prefix: *const u8,
+    prefix_len: usize,
+    page_size: usize,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        iter_h: MDataEntriesIterHandle,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let info = NativeMDataInfo::clone_from_repr_c(info)?;
+        let prefix = vec_clone_from_raw_parts(prefix, prefix_len);
+        let page_size = if page_size == 0 {
+            DEFAULT_PAGE_SIZE
+        } else {
+            page_size
+        };
+
+        send(app, user_data, o_cb, move |client, context| {
+            let context = context.clone();
+
+            EntryPages::fetch(client, info, prefix, page_size)
+                .map_err(AppError::from)
+                .map(move |pages| context.object_cache().insert_mdata_entries_iter(pages))
+        })
+    })
+}
+
+/// Return the next page of entries. An empty page means all the entries have been listed.
+#[no_mangle]
+pub unsafe extern "C" fn mdata_entries_iter_next(
+    app: *const App,
+    iter_h: MDataEntriesIterHandle,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        entries: *const MDataEntry,
+        entries_len: usize,
+    ),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data, o_cb, || {
+        (*app).send(move |_client, context| {
+            let page = {
+                let mut pages = try_cb!(
+                    context.object_cache().get_mdata_entries_iter(iter_h),
+                    user_data.0,
+                    o_cb
+                );
+                pages.next_page().map_err(AppError::from)
+            };
+            let page = try_cb!(page, user_data.0, o_cb).unwrap_or_default();
+
+            let entries_vec: Vec<MDataEntry> = page
+                .iter()
+                .map(|&(ref key, ref value)| MDataEntry {
+                    key: MDataKey {
+                        key: key.as_safe_ptr(),
+                        key_len: key.len(),
+                    },
+                    value: MDataValue {
+                        content: value.content.as_safe_ptr(),
+                        content_len: value.content.len(),
+                        entry_version: value.entry_version,
+                    },
+                }).collect();
+
+            o_cb(
+                user_data.0,
+                FFI_RESULT_OK,
+                entries_vec.as_safe_ptr(),
+                entries_vec.len(),
+            );
+
+            None
+        })
+    })
+}
+
+/// Free the entries iterator from memory.
+#[no_mangle]
+pub unsafe extern "C" fn mdata_entries_iter_free(
+    app: *const App,
+    iter_h: MDataEntriesIterHandle,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        send_sync(app, user_data, o_cb, move |_, context| {
+            let _ = context.object_cache().remove_mdata_entries_iter(iter_h)?;
+            Ok(())
+        })
+    })
+}
+
 // -------------- Helpers --------------------------
 
 unsafe fn with_entries<C, F>(
@@ -230,7 +341,7 @@ mod tests {
     use ffi_utils::test_utils::{
         call_0, call_1, call_vec, send_via_user_data, sender_as_user_data,
     };
-    use ffi_utils::vec_clone_from_raw_parts;
+    use ffi_utils::{vec_clone_from_raw_parts, ErrorCode};
     use routing::{Action, PermissionSet, Value};
     use safe_core::ipc::resp::{MDataEntry, MDataKey, MDataValue};
     use safe_core::utils;
@@ -489,4 +600,80 @@ mod tests {
         assert!(values.contains(&value0));
         assert!(values.contains(&value1));
     }
+
+    // Test listing mdata entries page by page, filtered by key prefix.
+    #[test]
+    fn entries_iter() {
+        let app = create_app();
+
+        let value = Value {
+            content: unwrap!(utils::generate_random_vector(10)),
+            entry_version: 0,
+        };
+        let entries = btree_map![
+            b"a1".to_vec() => value.clone(),
+            b"a2".to_vec() => value.clone(),
+            b"a3".to_vec() => value.clone(),
+            b"b1".to_vec() => value.clone()
+        ];
+        let entries_h = run_now(&app, move |_, context| {
+            context.object_cache().insert_mdata_entries(entries)
+        });
+
+        let perms_h: MDataPermissionsHandle =
+            unsafe { unwrap!(call_1(|ud, cb| mdata_permissions_new(&app, ud, cb))) };
+
+        let md_info: NativeMDataInfo =
+            unsafe { unwrap!(call_1(|ud, cb| mdata_info_random_public(10_000, ud, cb))) };
+        let md_info = md_info.into_repr_c();
+
+        unsafe {
+            unwrap!(call_0(|ud, cb| mdata_put(
+                &app, &md_info, perms_h, entries_h, ud, cb
+            )))
+        };
+
+        let prefix = b"a".to_vec();
+        let iter_h: MDataEntriesIterHandle = unsafe {
+            unwrap!(call_1(|ud, cb| mdata_entries_iter_new(
+                &app,
+                &md_info,
+                prefix.as_ptr(),
+                prefix.len(),
+                2,
+                ud,
+                cb,
+            )))
+        };
+
+        let mut keys = Vec::new();
+        let mut page_lens = Vec::new();
+        loop {
+            let page: Vec<MDataEntry> = unsafe {
+                unwrap!(call_vec(|ud, cb| mdata_entries_iter_next(
+                    &app, iter_h, ud, cb
+                )))
+            };
+            if page.is_empty() {
+                break;
+            }
+
+            page_lens.push(page.len());
+            for entry in page {
+                assert_eq!(entry.value, MDataValue::from_routing(value.clone()));
+                keys.push(entry.key.0);
+            }
+        }
+
+        assert_eq!(page_lens, vec![2, 1]);
+        assert_eq!(keys, vec![b"a1".to_vec(), b"a2".to_vec(), b"a3".to_vec()]);
+
+        unsafe { unwrap!(call_0(|ud, cb| mdata_entries_iter_free(&app, iter_h, ud, cb))) };
+
+        let res = unsafe { call_0(|ud, cb| mdata_entries_iter_free(&app, iter_h, ud, cb)) };
+        assert_eq!(
+            res,
+            Err(AppError::InvalidMDataEntriesIterHandle.error_code())
+        );
+    }
 }
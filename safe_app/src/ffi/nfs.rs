@@ -9,30 +9,41 @@
 
 use crate::client::AppClient;
 use crate::errors::AppError;
-use crate::ffi::helper::send;
-use crate::ffi::object_cache::FileContextHandle;
+use crate::ffi::helper::{send, send_sync};
+use crate::ffi::object_cache::{DirWatcherHandle, FileContextHandle};
 use ffi_utils::{
     catch_unwind_cb, from_c_str, vec_clone_from_raw_parts, FfiResult, OpaqueCtx, ReprC, SafePtr,
     FFI_RESULT_OK,
 };
 use futures::future::{self, Either};
-use futures::Future;
-use safe_core::ffi::nfs::File;
+use futures::sync::oneshot;
+use futures::{Future, Stream};
+use safe_core::ffi::nfs::{DirChange, File, FileEntry};
 use safe_core::ffi::MDataInfo;
 use safe_core::nfs::file_helper::{self, Version};
+use safe_core::nfs::watcher::{Backoff, ChangeKind, Watcher};
 use safe_core::nfs::File as NativeFile;
 use safe_core::nfs::{Mode, Reader, Writer};
-use safe_core::{FutureExt, MDataInfo as NativeMDataInfo};
+use safe_core::{DedupStats, FutureExt, MDataInfo as NativeMDataInfo};
+use std::ffi::CString;
 use std::os::raw::{c_char, c_void};
+use std::slice;
+use std::time::Duration;
 use crate::App;
 
 /// Holds context for file operations, depending on the mode.
 pub struct FileContext {
     reader: Option<Reader<AppClient>>,
     writer: Option<Writer<AppClient>>,
+    dedup_stats: Option<DedupStats>,
     original_file: NativeFile,
 }
 
+/// Keeps a directory watcher running until it is removed from the object cache.
+pub struct DirWatcher {
+    stop_tx: oneshot::Sender<()>,
+}
+
 /// Constant to pass to `dir_update_file()` or `dir_delete_file()` when the next version should be
 /// retrieved and used automatically.
 pub const GET_NEXT_VERSION: u64 = 0;
@@ -43,9 +54,28 @@ pub static OPEN_MODE_OVERWRITE: u64 = 1;
 pub static OPEN_MODE_APPEND: u64 = 2;
 /// Open file to read.
 pub static OPEN_MODE_READ: u64 = 4;
+/// Modifies existing data in the file at arbitrary positions.
+pub static OPEN_MODE_MODIFY: u64 = 8;
+/// Combined with one of the write modes, skips putting the chunks which are stored on the network
+/// already. Every chunk costs an extra GET. Close the file with `file_close_dedup` to learn how
+/// many chunks have been skipped.
+pub static OPEN_MODE_DEDUPLICATE: u64 = 16;
 /// Read entire contents of a file.
 pub static FILE_READ_TO_END: u64 = 0;
 
+/// File contents are stored as they are.
+pub static COMPRESSION_NONE: u64 = 0;
+/// File contents are compressed with deflate. Set as the `compression` of a file before opening
+/// it with `OPEN_MODE_OVERWRITE`; compressed files can't be opened for appending or modifying.
+pub static COMPRESSION_DEFLATE: u64 = 1;
+
+/// The directory entry has been inserted.
+pub static WATCH_EVENT_CREATED: u64 = 1;
+/// The directory entry has been updated.
+pub static WATCH_EVENT_MODIFIED: u64 = 2;
+/// The directory entry has been deleted.
+pub static WATCH_EVENT_DELETED: u64 = 3;
+
 /// Retrieve file with the given name, and its version, from the directory.
 #[no_mangle]
 pub unsafe extern "C" fn dir_fetch_file(
@@ -79,6 +109,57 @@ pub unsafe extern "C" fn dir_fetch_file(
     })
 }
 
+/// Retrieve all files, along with their names and versions, from the directory.
+///
+/// Subdirectories and deleted entries are not included.
+#[no_mangle]
+pub unsafe extern "C" fn dir_list_files(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        files: *const FileEntry,
+        files_len: usize,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            file_helper::list(client.clone(), parent_info)
+                .map_err(AppError::from)
+                .and_then(|files| {
+                    files
+                        .into_iter()
+                        .map(|(name, version, file)| -> Result<_, AppError> {
+                            Ok((CString::new(name)?, version, file))
+                        }).collect::<Result<Vec<_>, _>>()
+                }).map(move |files| {
+                    let entries: Vec<_> = files
+                        .iter()
+                        .map(|&(ref name, version, ref file)| FileEntry {
+                            name: name.as_ptr(),
+                            version,
+                            file: file.clone().into_repr_c(),
+                        }).collect();
+
+                    o_cb(
+                        user_data.0,
+                        FFI_RESULT_OK,
+                        entries.as_safe_ptr(),
+                        entries.len(),
+                    );
+                }).map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                }).into_box()
+                .into()
+        })
+    })
+}
+
 /// Insert the file into the parent directory.
 #[no_mangle]
 pub unsafe extern "C" fn dir_insert_file(
@@ -156,6 +237,73 @@ pub unsafe extern "C" fn dir_delete_file(
     })
 }
 
+/// Set the content type of the file, or clear it if `content_type` is null. The updated copy of
+/// the file is passed to the callback, `file` itself is left unchanged.
+#[no_mangle]
+pub unsafe extern "C" fn file_set_content_type(
+    file: *const File,
+    content_type: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, file: *const File),
+) {
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let mut file = NativeFile::clone_from_repr_c(file)?;
+        let content_type = if content_type.is_null() {
+            None
+        } else {
+            Some(from_c_str(content_type)?)
+        };
+        file.set_content_type(content_type);
+
+        let ffi_file = file.into_repr_c();
+        o_cb(user_data, FFI_RESULT_OK, &ffi_file);
+        Ok(())
+    })
+}
+
+/// Set the extended attribute `name` of the file to the given value. The updated copy of the file
+/// is passed to the callback, `file` itself is left unchanged.
+#[no_mangle]
+pub unsafe extern "C" fn file_set_attribute(
+    file: *const File,
+    name: *const c_char,
+    value: *const u8,
+    value_len: usize,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, file: *const File),
+) {
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let mut file = NativeFile::clone_from_repr_c(file)?;
+        let name = from_c_str(name)?;
+        let value = vec_clone_from_raw_parts(value, value_len);
+        let _ = file.set_attribute(name, value);
+
+        let ffi_file = file.into_repr_c();
+        o_cb(user_data, FFI_RESULT_OK, &ffi_file);
+        Ok(())
+    })
+}
+
+/// Remove the extended attribute `name` from the file, if it is set. The updated copy of the file
+/// is passed to the callback, `file` itself is left unchanged.
+#[no_mangle]
+pub unsafe extern "C" fn file_remove_attribute(
+    file: *const File,
+    name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, file: *const File),
+) {
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let mut file = NativeFile::clone_from_repr_c(file)?;
+        let name = from_c_str(name)?;
+        let _ = file.remove_attribute(&name);
+
+        let ffi_file = file.into_repr_c();
+        o_cb(user_data, FFI_RESULT_OK, &ffi_file);
+        Ok(())
+    })
+}
+
 /// Open the file to read or write its contents.
 #[no_mangle]
 pub unsafe extern "C" fn file_open(
@@ -188,27 +336,46 @@ pub unsafe extern "C" fn file_open(
             };
 
             // Initialise the writer if one of write modes is requested
-            let writer = if open_mode & (OPEN_MODE_OVERWRITE | OPEN_MODE_APPEND) != 0 {
-                let writer_mode = if open_mode & OPEN_MODE_APPEND != 0 {
+            let writer = if open_mode
+                & (OPEN_MODE_OVERWRITE | OPEN_MODE_APPEND | OPEN_MODE_MODIFY)
+                != 0
+            {
+                let writer_mode = if open_mode & OPEN_MODE_MODIFY != 0 {
+                    Mode::Modify
+                } else if open_mode & OPEN_MODE_APPEND != 0 {
                     Mode::Append
                 } else {
                     Mode::Overwrite
                 };
-                let fut = file_helper::write(
-                    client.clone(),
-                    file,
-                    writer_mode,
-                    parent_info.enc_key().cloned(),
-                ).map(Some);
+                let fut = if open_mode & OPEN_MODE_DEDUPLICATE != 0 {
+                    let dedup_stats = DedupStats::new();
+                    file_helper::write_deduplicating(
+                        client.clone(),
+                        file,
+                        writer_mode,
+                        parent_info.enc_key().cloned(),
+                        dedup_stats.clone(),
+                    ).map(move |writer| (Some(writer), Some(dedup_stats)))
+                    .into_box()
+                } else {
+                    file_helper::write(
+                        client.clone(),
+                        file,
+                        writer_mode,
+                        parent_info.enc_key().cloned(),
+                    ).map(|writer| (Some(writer), None))
+                    .into_box()
+                };
                 Either::A(fut)
             } else {
-                Either::B(future::ok(None))
+                Either::B(future::ok((None, None)))
             };
 
-            reader.join(writer).map(move |(reader, writer)| {
+            reader.join(writer).map(move |(reader, (writer, dedup_stats))| {
                 let file_ctx = FileContext {
                     reader,
                     writer,
+                    dedup_stats,
                     original_file,
                 };
                 context.object_cache().insert_file(file_ctx)
@@ -318,6 +485,73 @@ pub unsafe extern "C" fn file_write(
     })
 }
 
+/// Write data to file at the given position. Unless the file was opened with
+/// `OPEN_MODE_MODIFY`, data can only be written at the current end of the file.
+#[no_mangle]
+pub unsafe extern "C" fn file_write_at(
+    app: *const App,
+    file_h: FileContextHandle,
+    position: u64,
+    data: *const u8,
+    data_len: usize,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let user_data = OpaqueCtx(user_data);
+        let data = vec_clone_from_raw_parts(data, data_len);
+
+        (*app).send(move |_client, context| {
+            let file_ctx = try_cb!(context.object_cache().get_file(file_h), user_data, o_cb);
+
+            if let Some(ref writer) = file_ctx.writer {
+                writer
+                    .write_at(position, &data)
+                    .then(move |res| {
+                        call_result_cb!(res.map_err(AppError::from), user_data, o_cb);
+                        Ok(())
+                    }).into_box()
+                    .into()
+            } else {
+                call_result_cb!(Err::<(), _>(AppError::InvalidFileMode), user_data, o_cb);
+                None
+            }
+        })
+    })
+}
+
+/// Truncate or extend (with zeros) the file to the given size. Requires the file
+/// to be opened with `OPEN_MODE_MODIFY`.
+#[no_mangle]
+pub unsafe extern "C" fn file_truncate(
+    app: *const App,
+    file_h: FileContextHandle,
+    size: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |_client, context| {
+            let file_ctx = try_cb!(context.object_cache().get_file(file_h), user_data, o_cb);
+
+            if let Some(ref writer) = file_ctx.writer {
+                writer
+                    .truncate(size)
+                    .then(move |res| {
+                        call_result_cb!(res.map_err(AppError::from), user_data, o_cb);
+                        Ok(())
+                    }).into_box()
+                    .into()
+            } else {
+                call_result_cb!(Err::<(), _>(AppError::InvalidFileMode), user_data, o_cb);
+                None
+            }
+        })
+    })
+}
+
 /// Close is invoked only after all the data is completely written. The
 /// file is saved only when `close` is invoked.
 ///
@@ -361,3 +595,165 @@ pub unsafe extern "C" fn file_close(
         })
     })
 }
+
+/// Close a file opened with `OPEN_MODE_DEDUPLICATE` like `file_close`, additionally reporting the
+/// number of chunks, and their total size in bytes, which haven't been put because they were
+/// stored already. Fails with `InvalidFileMode` if the file wasn't opened with that mode, in which
+/// case the file context handle is kept.
+///
+/// Frees the file context handle.
+#[no_mangle]
+pub unsafe extern "C" fn file_close_dedup(
+    app: *const App,
+    file_h: FileContextHandle,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        file: *const File,
+        chunks: u64,
+        bytes: u64,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |_client, context| {
+            let is_dedup = try_cb!(
+                context
+                    .object_cache()
+                    .get_file(file_h)
+                    .map(|file_ctx| file_ctx.dedup_stats.is_some()),
+                user_data,
+                o_cb
+            );
+            if !is_dedup {
+                call_result_cb!(Err::<(), _>(AppError::InvalidFileMode), user_data, o_cb);
+                return None;
+            }
+
+            let file_ctx = try_cb!(context.object_cache().remove_file(file_h), user_data, o_cb);
+            let dedup_stats = file_ctx.dedup_stats.unwrap_or_default();
+
+            match file_ctx.writer {
+                Some(writer) => writer
+                    .close()
+                    .map(move |file| {
+                        o_cb(
+                            user_data.0,
+                            FFI_RESULT_OK,
+                            &file.into_repr_c(),
+                            dedup_stats.chunks(),
+                            dedup_stats.bytes(),
+                        );
+                    }).map_err(move |err| {
+                        call_result_cb!(Err::<(), _>(AppError::from(err)), user_data, o_cb);
+                    }).into_box()
+                    .into(),
+                None => {
+                    call_result_cb!(Err::<(), _>(AppError::InvalidFileMode), user_data, o_cb);
+                    None
+                }
+            }
+        })
+    })
+}
+
+/// Start watching the directories for entries being created, modified or deleted.
+///
+/// The directories are polled, starting every `min_interval_ms` milliseconds and backing off up
+/// to every `max_interval_ms` milliseconds while nothing changes. Pass `0` for either of them to
+/// use the default.
+///
+/// `o_change_cb` is called for every change found. If watching fails, it is called once more
+/// with the error and no further changes are reported.
+///
+/// `o_cb` is called with the handle of the watcher. The watcher keeps polling until the handle
+/// is freed with `dir_watch_stop`.
+#[no_mangle]
+pub unsafe extern "C" fn dir_watch(
+    app: *const App,
+    dirs: *const MDataInfo,
+    dirs_len: usize,
+    min_interval_ms: u64,
+    max_interval_ms: u64,
+    user_data: *mut c_void,
+    o_change_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        change: *const DirChange,
+    ),
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        watcher_h: DirWatcherHandle,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let dirs = slice::from_raw_parts(dirs, dirs_len)
+            .iter()
+            .map(|dir| NativeMDataInfo::clone_from_repr_c(dir))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut backoff = Backoff::default();
+        if min_interval_ms != 0 {
+            backoff.min = Duration::from_millis(min_interval_ms);
+        }
+        if max_interval_ms != 0 {
+            backoff.max = Duration::from_millis(max_interval_ms);
+        }
+
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, context| {
+            let (stop_tx, stop_rx) = oneshot::channel();
+            let watcher_h = context.object_cache().insert_dir_watcher(DirWatcher { stop_tx });
+            o_cb(user_data.0, FFI_RESULT_OK, watcher_h);
+
+            let changes = Watcher::new(client.clone(), dirs, backoff)
+                .map_err(AppError::from)
+                .for_each(move |change| -> Result<_, AppError> {
+                    let name = CString::new(change.name)?;
+                    let kind = match change.kind {
+                        ChangeKind::Created => WATCH_EVENT_CREATED,
+                        ChangeKind::Modified => WATCH_EVENT_MODIFIED,
+                        ChangeKind::Deleted => WATCH_EVENT_DELETED,
+                    };
+                    let ffi_change = DirChange {
+                        dir_index: change.dir,
+                        name: name.as_ptr(),
+                        kind,
+                    };
+                    o_change_cb(user_data.0, FFI_RESULT_OK, &ffi_change);
+                    Ok(())
+                }).map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_change_cb);
+                });
+
+            // Polling ends once the watcher is stopped, or the object cache is reset.
+            changes
+                .select(stop_rx.then(|_| Ok::<_, ()>(())))
+                .map(|_| ())
+                .map_err(|_| ())
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Stop watching the directories and free the watcher handle.
+#[no_mangle]
+pub unsafe extern "C" fn dir_watch_stop(
+    app: *const App,
+    watcher_h: DirWatcherHandle,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        send_sync(app, user_data, o_cb, move |_, context| {
+            let watcher = context.object_cache().remove_dir_watcher(watcher_h)?;
+            let _ = watcher.stop_tx.send(());
+            Ok(())
+        })
+    })
+}
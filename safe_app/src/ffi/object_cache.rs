@@ -34,6 +34,8 @@ pub type EncryptSecKeyHandle = ObjectHandle;
 /// Disambiguating `ObjectHandle`
 pub type MDataEntriesHandle = ObjectHandle;
 /// Disambiguating `ObjectHandle`
+pub type MDataEntriesIterHandle = ObjectHandle;
+/// Disambiguating `ObjectHandle`
 pub type MDataEntryActionsHandle = ObjectHandle;
 /// Disambiguating `ObjectHandle`
 pub type MDataPermissionsHandle = ObjectHandle;
@@ -47,3 +49,5 @@ pub type SignPubKeyHandle = ObjectHandle;
 pub type SignSecKeyHandle = ObjectHandle;
 /// Disambiguating `ObjectHandle`
 pub type FileContextHandle = ObjectHandle;
+/// Disambiguating `ObjectHandle`
+pub type DirWatcherHandle = ObjectHandle;
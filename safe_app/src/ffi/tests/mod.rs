@@ -87,6 +87,57 @@ fn account_info() {
     unsafe { app_free(app) };
 }
 
+// Test enabling and disabling the disk cache.
+#[test]
+fn disk_cache() {
+    use crate::test_utils::run;
+    use ffi_utils::test_utils::call_0;
+    use std::env;
+    use std::ffi::CString;
+    use std::fs;
+    use std::ptr;
+
+    let app = create_app();
+    let app = Box::into_raw(Box::new(app));
+
+    let cache_dir = env::temp_dir().join(format!("safe_app_cache_{}", rand::random::<u64>()));
+    let ffi_cache_dir = unwrap!(CString::new(unwrap!(cache_dir.to_str())));
+    let data = ImmutableData::new((0..100).map(|_| rand::random()).collect());
+    let name = *data.name();
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| app_set_disk_cache(
+            app,
+            ffi_cache_dir.as_ptr(),
+            1_000_000,
+            ud,
+            cb
+        )));
+
+        run(&*app, move |client, _| {
+            let client2 = client.clone();
+            client
+                .put_idata(data)
+                .and_then(move |()| client2.get_idata(name))
+                .map_err(AppError::from)
+        });
+
+        unwrap!(call_0(|ud, cb| app_set_disk_cache(
+            app,
+            ptr::null(),
+            0,
+            ud,
+            cb
+        )));
+    }
+
+    // The fetched data has been stored in the cache.
+    assert_eq!(unwrap!(fs::read_dir(&cache_dir)).count(), 1);
+
+    unsafe { app_free(app) };
+    unwrap!(fs::remove_dir_all(&cache_dir));
+}
+
 // Test disconnection and reconnection with apps.
 #[cfg(all(test, feature = "use-mock-routing"))]
 #[test]
@@ -9,17 +9,24 @@
 
 use crate::errors::AppError;
 use crate::ffi::nfs::*;
-use crate::ffi::object_cache::FileContextHandle;
-use ffi_utils::test_utils::{call_0, call_1, call_2, call_vec_u8};
-use ffi_utils::ErrorCode;
+use crate::ffi::object_cache::{DirWatcherHandle, FileContextHandle};
+use ffi_utils::test_utils::{
+    call_0, call_1, call_1_with_custom, call_2, call_vec, call_vec_u8, send_via_user_data_custom,
+    UserData,
+};
+use ffi_utils::{from_c_str, ErrorCode, FfiResult, ReprC};
 use futures::Future;
-use safe_core::ffi::nfs::File;
+use safe_core::ffi::nfs::{DirChange, File, FileEntry};
 use safe_core::ffi::MDataInfo;
 use safe_core::ipc::Permission;
 use safe_core::nfs::{File as NativeFile, NfsError};
 use std;
 use std::collections::HashMap;
 use std::ffi::CString;
+use std::os::raw::c_void;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
 use crate::test_utils::{create_app_by_req, create_auth_req_with_access, run};
 use crate::App;
 
@@ -50,11 +57,27 @@ fn setup() -> (App, MDataInfo) {
     (app, container_info)
 }
 
+struct ListedFile(String, u64, NativeFile);
+
+impl ReprC for ListedFile {
+    type C = *const FileEntry;
+    type Error = AppError;
+
+    unsafe fn clone_from_repr_c(c_repr: Self::C) -> Result<Self, Self::Error> {
+        Ok(ListedFile(
+            from_c_str((*c_repr).name)?,
+            (*c_repr).version,
+            NativeFile::clone_from_repr_c(&(*c_repr).file)?,
+        ))
+    }
+}
+
 // Test the basics of NFS.
 // 1. Fetching a non-existing file should fail.
 // 2. Create an empty file.
 // 3. Fetch it back, assert that all file info is correct.
-// 4. Delete the file.
+// 4. List the directory, assert that the file is listed.
+// 5. Delete the file, assert that it's no longer listed.
 #[test]
 fn basics() {
     let (app, container_info) = setup();
@@ -103,6 +126,14 @@ fn basics() {
     assert_eq!(retrieved_file.size(), 0);
     assert_eq!(retrieved_version, 0);
 
+    // List the directory.
+    let files: Vec<ListedFile> =
+        unsafe { unwrap!(call_vec(|ud, cb| dir_list_files(&app, &container_info, ud, cb))) };
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].0, file_name0);
+    assert_eq!(files[0].1, 0);
+    assert_eq!(files[0].2.user_metadata(), &user_metadata[..]);
+
     // Delete file.
     let version: u64 = unsafe {
         unwrap!(call_1(|ud, cb| dir_delete_file(
@@ -115,6 +146,11 @@ fn basics() {
         )))
     };
     assert_eq!(version, 1);
+
+    // Deleted files are not listed.
+    let files: Vec<ListedFile> =
+        unsafe { unwrap!(call_vec(|ud, cb| dir_list_files(&app, &container_info, ud, cb))) };
+    assert!(files.is_empty());
 }
 
 // Test NFS functions for writing and updating file contents.
@@ -1012,6 +1048,397 @@ fn file_write_chunks() {
     assert_eq!(retrieved_content, vec![0u8; 2 * GOAL_SIZE]);
 }
 
+// Test positional writes and truncation of an existing file.
+// 1. Write a file in chunks and open it again with `OPEN_MODE_APPEND`.
+// 2. Try writing before the end of the file, which should fail.
+// 3. Open the file with `OPEN_MODE_MODIFY`, overwrite a range inside it, truncate it and
+//    write past the new end.
+// 4. Read the file back and check the contents.
+#[test]
+fn file_modify() {
+    const ORIG_SIZE: usize = 5555;
+    const PATCH_POS: usize = 1000;
+    const PATCH_SIZE: usize = 100;
+    const TRUNCATED_SIZE: usize = 2000;
+    const FINAL_SIZE: usize = 3000;
+
+    let (app, container_info) = setup();
+
+    let content = [0u8; ORIG_SIZE];
+    let patch = [1u8; PATCH_SIZE];
+
+    let ffi_file = NativeFile::new(Vec::new()).into_repr_c();
+    let write_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &ffi_file,
+            OPEN_MODE_OVERWRITE,
+            ud,
+            cb,
+        )))
+    };
+    let file = write_chunks(&app, write_h, &content, ORIG_SIZE, 1000);
+    let ffi_file = file.into_repr_c();
+
+    // Positional writes before the end are not allowed in the append mode
+    let write_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &ffi_file,
+            OPEN_MODE_APPEND,
+            ud,
+            cb,
+        )))
+    };
+    let res = unsafe {
+        call_0(|ud, cb| file_write_at(&app, write_h, 0, patch.as_ptr(), PATCH_SIZE, ud, cb))
+    };
+    match res {
+        Err(code) if code == AppError::from(NfsError::InvalidRange).error_code() => (),
+        Err(x) => panic!("Unexpected: {:?}", x),
+        Ok(()) => panic!("Unexpected success"),
+    }
+    let _: NativeFile = unsafe { unwrap!(call_1(|ud, cb| file_close(&app, write_h, ud, cb))) };
+
+    // Modify the file at arbitrary positions
+    let write_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &ffi_file,
+            OPEN_MODE_MODIFY,
+            ud,
+            cb,
+        )))
+    };
+    let file: NativeFile = unsafe {
+        unwrap!(call_0(|ud, cb| file_write_at(
+            &app,
+            write_h,
+            PATCH_POS as u64,
+            patch.as_ptr(),
+            PATCH_SIZE,
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| file_truncate(
+            &app,
+            write_h,
+            TRUNCATED_SIZE as u64,
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| file_write_at(
+            &app,
+            write_h,
+            (FINAL_SIZE - PATCH_SIZE) as u64,
+            patch.as_ptr(),
+            PATCH_SIZE,
+            ud,
+            cb,
+        )));
+        unwrap!(call_1(|ud, cb| file_close(&app, write_h, ud, cb)))
+    };
+    assert_eq!(file.size(), FINAL_SIZE as u64);
+
+    // Read the content
+    let read_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &file.into_repr_c(),
+            OPEN_MODE_READ,
+            ud,
+            cb,
+        )))
+    };
+    let retrieved_content = unsafe {
+        unwrap!(call_vec_u8(|ud, cb| file_read(
+            &app,
+            read_h,
+            0,
+            FILE_READ_TO_END,
+            ud,
+            cb
+        )))
+    };
+
+    let mut expected = vec![0u8; FINAL_SIZE];
+    expected[PATCH_POS..PATCH_POS + PATCH_SIZE].copy_from_slice(&patch);
+    expected[FINAL_SIZE - PATCH_SIZE..].copy_from_slice(&patch);
+    assert_eq!(retrieved_content, expected);
+}
+
+// Test setting the content type and extended attributes of a file through the FFI.
+// 1. Set the content type and two attributes, then remove one of the attributes.
+// 2. Insert the file into a container and fetch it back.
+// 3. Check that the content type and the remaining attribute survived the round trip.
+// 4. Clear the content type.
+#[test]
+fn file_attributes() {
+    let (app, container_info) = setup();
+
+    let ffi_file_name = unwrap!(CString::new("attributes.txt"));
+    let content_type = unwrap!(CString::new("text/plain"));
+    let tag = unwrap!(CString::new("user.tag"));
+    let author = unwrap!(CString::new("user.author"));
+    let tag_value = b"tag";
+    let author_value = b"author";
+
+    let file: NativeFile = unsafe {
+        let ffi_file = NativeFile::new(Vec::new()).into_repr_c();
+        let file: NativeFile = unwrap!(call_1(|ud, cb| file_set_content_type(
+            &ffi_file,
+            content_type.as_ptr(),
+            ud,
+            cb
+        )));
+
+        let ffi_file = file.into_repr_c();
+        let file: NativeFile = unwrap!(call_1(|ud, cb| file_set_attribute(
+            &ffi_file,
+            tag.as_ptr(),
+            tag_value.as_ptr(),
+            tag_value.len(),
+            ud,
+            cb
+        )));
+
+        let ffi_file = file.into_repr_c();
+        let file: NativeFile = unwrap!(call_1(|ud, cb| file_set_attribute(
+            &ffi_file,
+            author.as_ptr(),
+            author_value.as_ptr(),
+            author_value.len(),
+            ud,
+            cb
+        )));
+        assert_eq!(file.attributes().len(), 2);
+
+        let ffi_file = file.into_repr_c();
+        unwrap!(call_1(|ud, cb| file_remove_attribute(
+            &ffi_file,
+            author.as_ptr(),
+            ud,
+            cb
+        )))
+    };
+
+    // Insert the file and fetch it back.
+    let (retrieved_file, _version): (NativeFile, u64) = unsafe {
+        unwrap!(call_0(|ud, cb| dir_insert_file(
+            &app,
+            &container_info,
+            ffi_file_name.as_ptr(),
+            &file.into_repr_c(),
+            ud,
+            cb,
+        )));
+        unwrap!(call_2(|ud, cb| dir_fetch_file(
+            &app,
+            &container_info,
+            ffi_file_name.as_ptr(),
+            ud,
+            cb
+        )))
+    };
+    assert_eq!(retrieved_file.content_type(), Some("text/plain"));
+    assert_eq!(retrieved_file.attributes().len(), 1);
+    assert_eq!(retrieved_file.attribute("user.tag"), Some(&tag_value[..]));
+
+    // Clear the content type.
+    let ffi_file = retrieved_file.into_repr_c();
+    let file: NativeFile = unsafe {
+        unwrap!(call_1(|ud, cb| file_set_content_type(
+            &ffi_file,
+            std::ptr::null(),
+            ud,
+            cb
+        )))
+    };
+    assert_eq!(file.content_type(), None);
+    assert_eq!(file.attributes().len(), 1);
+}
+
+// Test watching a directory for changes.
+// 1. Start watching the directory.
+// 2. Insert a file, assert that its creation is reported.
+// 3. Update the file, assert that its modification is reported.
+// 4. Stop watching.
+#[test]
+fn watch_dir() {
+    let (app, container_info) = setup();
+
+    let (tx, rx): (Sender<(usize, String, u64)>, Receiver<_>) = mpsc::channel();
+    let mut custom_ud: UserData = Default::default();
+    let ptr: *const _ = &tx;
+    custom_ud.custom = ptr as *mut c_void;
+
+    let watcher_h: DirWatcherHandle = unsafe {
+        unwrap!(call_1_with_custom(&mut custom_ud, |ud, cb| dir_watch(
+            &app,
+            &container_info,
+            1,
+            10,
+            40,
+            ud,
+            change_cb,
+            cb,
+        )))
+    };
+
+    // Let the watcher record the initial state of the directory.
+    thread::sleep(Duration::from_millis(200));
+
+    let file_name = "file0.txt";
+    let ffi_file_name = unwrap!(CString::new(file_name));
+    let ffi_file = NativeFile::new(Vec::new()).into_repr_c();
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| dir_insert_file(
+            &app,
+            &container_info,
+            ffi_file_name.as_ptr(),
+            &ffi_file,
+            ud,
+            cb,
+        )))
+    }
+
+    let change = unwrap!(rx.recv_timeout(Duration::from_secs(10)));
+    assert_eq!(change, (0, file_name.to_string(), WATCH_EVENT_CREATED));
+
+    let version: u64 = unsafe {
+        unwrap!(call_1(|ud, cb| dir_update_file(
+            &app,
+            &container_info,
+            ffi_file_name.as_ptr(),
+            &ffi_file,
+            GET_NEXT_VERSION,
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(version, 1);
+
+    let change = unwrap!(rx.recv_timeout(Duration::from_secs(10)));
+    assert_eq!(change, (0, file_name.to_string(), WATCH_EVENT_MODIFIED));
+
+    unsafe { unwrap!(call_0(|ud, cb| dir_watch_stop(&app, watcher_h, ud, cb))) }
+
+    // The handle is freed.
+    let res = unsafe { call_0(|ud, cb| dir_watch_stop(&app, watcher_h, ud, cb)) };
+    match res {
+        Err(code) if code == AppError::InvalidDirWatcherHandle.error_code() => (),
+        Err(x) => panic!("Unexpected: {:?}", x),
+        Ok(_) => panic!("Unexpected success"),
+    }
+
+    extern "C" fn change_cb(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        change: *const DirChange,
+    ) {
+        unsafe {
+            assert_eq!((*result).error_code, 0);
+            let name = unwrap!(from_c_str((*change).name));
+            send_via_user_data_custom(user_data, ((*change).dir_index, name, (*change).kind));
+        }
+    }
+}
+
+// Test that writing the same content again with `OPEN_MODE_DEDUPLICATE` skips its chunks.
+// 1. Write a file without deduplication; `file_close_dedup` rejects it.
+// 2. Write the same content with deduplication and check the skipped chunks are reported.
+#[test]
+fn file_write_dedup() {
+    let (app, container_info) = setup();
+    let ffi_file = NativeFile::new(Vec::new()).into_repr_c();
+    let content = unwrap!(safe_core::utils::generate_random_vector::<u8>(10 * 1024));
+
+    let write_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &ffi_file,
+            OPEN_MODE_OVERWRITE,
+            ud,
+            cb,
+        )))
+    };
+    unsafe {
+        unwrap!(call_0(|ud, cb| file_write(
+            &app,
+            write_h,
+            content.as_ptr(),
+            content.len(),
+            ud,
+            cb
+        )));
+    }
+    match close_dedup(&app, write_h) {
+        Err(code) if code == AppError::InvalidFileMode.error_code() => (),
+        Err(x) => panic!("Unexpected: {:?}", x),
+        Ok(_) => panic!("Unexpected success"),
+    }
+    let _: NativeFile = unsafe { unwrap!(call_1(|ud, cb| file_close(&app, write_h, ud, cb))) };
+
+    let write_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &ffi_file,
+            OPEN_MODE_OVERWRITE | OPEN_MODE_DEDUPLICATE,
+            ud,
+            cb,
+        )))
+    };
+    unsafe {
+        unwrap!(call_0(|ud, cb| file_write(
+            &app,
+            write_h,
+            content.as_ptr(),
+            content.len(),
+            ud,
+            cb
+        )));
+    }
+    let (file, chunks, bytes) = unwrap!(close_dedup(&app, write_h));
+    assert_eq!(file.size(), content.len() as u64);
+    assert_eq!(chunks, 3);
+    assert!(bytes > 0);
+}
+
+// Close the file with `file_close_dedup`, returning the file and the deduplication stats.
+fn close_dedup(app: &App, file_h: FileContextHandle) -> Result<(NativeFile, u64, u64), i32> {
+    let (tx, rx) = mpsc::channel::<Result<(NativeFile, u64, u64), i32>>();
+    let tx_ptr: *const _ = &tx;
+
+    unsafe {
+        file_close_dedup(app, file_h, tx_ptr as *mut c_void, close_dedup_cb);
+    }
+    return unwrap!(rx.recv());
+
+    extern "C" fn close_dedup_cb(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        file: *const File,
+        chunks: u64,
+        bytes: u64,
+    ) {
+        unsafe {
+            let tx = user_data as *const mpsc::Sender<Result<(NativeFile, u64, u64), i32>>;
+            let res = match (*result).error_code {
+                0 => Ok((unwrap!(NativeFile::clone_from_repr_c(file)), chunks, bytes)),
+                code => Err(code),
+            };
+            unwrap!((*tx).send(res));
+        }
+    }
+}
+
 // Helper function for writing to a file in chunks.
 fn write_chunks(
     app: &App,
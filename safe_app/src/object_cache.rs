@@ -13,11 +13,12 @@
 use super::errors::AppError;
 use crate::cipher_opt::CipherOpt;
 use crate::client::AppClient;
-use crate::ffi::nfs::FileContext;
+use crate::ffi::nfs::{DirWatcher, FileContext};
 use crate::ffi::object_cache::*;
 use routing::{EntryAction, PermissionSet, User, Value};
 use rust_sodium::crypto::{box_, sign};
 use safe_core::crypto::{shared_box, shared_sign};
+use safe_core::client::EntryPages;
 use safe_core::SelfEncryptionStorage;
 use self_encryption::{SelfEncryptor, SequentialEncryptor};
 use std::cell::{Cell, RefCell, RefMut};
@@ -30,6 +31,7 @@ pub struct ObjectCache {
     encrypt_key: Store<box_::PublicKey>,
     secret_key: Store<shared_box::SecretKey>,
     mdata_entries: Store<BTreeMap<Vec<u8>, Value>>,
+    mdata_entries_iter: Store<EntryPages>,
     mdata_entry_actions: Store<BTreeMap<Vec<u8>, EntryAction>>,
     mdata_permissions: Store<BTreeMap<User, PermissionSet>>,
     se_reader: Store<SelfEncryptor<SelfEncryptionStorage<AppClient>>>,
@@ -37,6 +39,7 @@ pub struct ObjectCache {
     pub_sign_key: Store<sign::PublicKey>,
     sec_sign_key: Store<shared_sign::SecretKey>,
     file: Store<FileContext>,
+    dir_watcher: Store<DirWatcher>,
 }
 
 impl ObjectCache {
@@ -48,6 +51,7 @@ impl ObjectCache {
             encrypt_key: Store::new(),
             secret_key: Store::new(),
             mdata_entries: Store::new(),
+            mdata_entries_iter: Store::new(),
             mdata_entry_actions: Store::new(),
             mdata_permissions: Store::new(),
             se_reader: Store::new(),
@@ -55,6 +59,7 @@ impl ObjectCache {
             pub_sign_key: Store::new(),
             sec_sign_key: Store::new(),
             file: Store::new(),
+            dir_watcher: Store::new(),
         }
     }
 
@@ -65,6 +70,7 @@ impl ObjectCache {
         self.encrypt_key.clear();
         self.secret_key.clear();
         self.mdata_entries.clear();
+        self.mdata_entries_iter.clear();
         self.mdata_entry_actions.clear();
         self.mdata_permissions.clear();
         self.se_reader.clear();
@@ -72,6 +78,7 @@ impl ObjectCache {
         self.pub_sign_key.clear();
         self.sec_sign_key.clear();
         self.file.clear();
+        self.dir_watcher.clear();
     }
 }
 
@@ -134,6 +141,15 @@ impl_cache!(
     insert_mdata_entries,
     remove_mdata_entries
 );
+impl_cache!(
+    mdata_entries_iter,
+    EntryPages,
+    MDataEntriesIterHandle,
+    InvalidMDataEntriesIterHandle,
+    get_mdata_entries_iter,
+    insert_mdata_entries_iter,
+    remove_mdata_entries_iter
+);
 impl_cache!(
     mdata_entry_actions,
     BTreeMap<Vec<u8>, EntryAction>,
@@ -195,6 +211,15 @@ impl_cache!(
     insert_file,
     remove_file
 );
+impl_cache!(
+    dir_watcher,
+    DirWatcher,
+    DirWatcherHandle,
+    InvalidDirWatcherHandle,
+    get_dir_watcher,
+    insert_dir_watcher,
+    remove_dir_watcher
+);
 
 impl Default for ObjectCache {
     fn default() -> Self {
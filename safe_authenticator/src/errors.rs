@@ -46,6 +46,8 @@ mod codes {
     pub const ERR_REQUEST_TIMEOUT: i32 = -17;
     pub const ERR_CONFIG_FILE: i32 = -18;
     pub const ERR_IO: i32 = -19;
+    pub const ERR_INVALID_LINK: i32 = -20;
+    pub const ERR_INVALID_SNAPSHOT: i32 = -21;
 
     // routing Client errors
     pub const ERR_ACCESS_DENIED: i32 = -100;
@@ -82,6 +84,10 @@ mod codes {
     pub const ERR_FILE_EXISTS: i32 = -300;
     pub const ERR_FILE_NOT_FOUND: i32 = -301;
     pub const ERR_INVALID_RANGE: i32 = -302;
+    pub const ERR_DIRECTORY_NOT_EMPTY: i32 = -303;
+    pub const ERR_INVALID_PATH: i32 = -304;
+    pub const ERR_CONFLICT: i32 = -305;
+    pub const ERR_COMPRESSED_FILE: i32 = -306;
 
     // Authenticator errors.
     pub const ERR_IO_ERROR: i32 = -1013;
@@ -251,6 +257,10 @@ impl ErrorCode for AuthError {
                 NfsError::FileExists => ERR_FILE_EXISTS,
                 NfsError::FileNotFound => ERR_FILE_NOT_FOUND,
                 NfsError::InvalidRange => ERR_INVALID_RANGE,
+                NfsError::DirectoryNotEmpty => ERR_DIRECTORY_NOT_EMPTY,
+                NfsError::InvalidPath => ERR_INVALID_PATH,
+                NfsError::Conflict(_) => ERR_CONFLICT,
+                NfsError::CompressedFile => ERR_COMPRESSED_FILE,
                 NfsError::EncodeDecodeError(_) => ERR_ENCODE_DECODE_ERROR,
                 NfsError::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
                 NfsError::Unexpected(_) => ERR_UNEXPECTED,
@@ -305,6 +315,8 @@ fn core_error_code(err: &CoreError) -> i32 {
         CoreError::RequestTimeout => ERR_REQUEST_TIMEOUT,
         CoreError::ConfigError(_) => ERR_CONFIG_FILE,
         CoreError::IoError(_) => ERR_IO,
+        CoreError::InvalidLink(_) => ERR_INVALID_LINK,
+        CoreError::InvalidSnapshot(_) => ERR_INVALID_SNAPSHOT,
         CoreError::Unexpected(_) => ERR_UNEXPECTED,
     }
 }
@@ -0,0 +1,70 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Garbage collection reporting.
+//!
+//! Collects everything reachable from the user's account: the access container and the config
+//! root, the standard directories from the authenticator entry and the containers granted to
+//! every registered app, along with the whole directory trees below them.
+
+use crate::access_container;
+use crate::client::AuthClient;
+use crate::config;
+use futures::{future, Future};
+use routing::ClientError;
+use safe_core::nfs::reachability::{self, Reachability};
+use safe_core::{CoreError, FutureExt, MDataInfo};
+use crate::{AuthError, AuthFuture};
+
+/// Collect the data reachable from the user's account. Names of stored `ImmutableData` missing
+/// from the report's `idata` are orphaned, unless the walk recorded failures; see
+/// `Reachability::orphaned`.
+pub fn gc_report(client: &AuthClient) -> Box<AuthFuture<Reachability>> {
+    trace!("Collecting data reachable from the account");
+
+    let c2 = client.clone();
+    let c3 = client.clone();
+    let c4 = client.clone();
+
+    let std_dirs = access_container::fetch_authenticator_entry(client)
+        .map(|(_, entries)| entries.into_iter().map(|(_, dir)| dir).collect::<Vec<_>>());
+
+    let app_containers = config::list_apps(client).and_then(move |(_, apps)| {
+        let entries = apps.into_iter().map(move |(_, app)| {
+            access_container::fetch_entry(&c2, &app.info.id, app.keys)
+                .map(|(_, entry)| entry)
+                .or_else(|error| match error {
+                    // Apps which have been revoked no longer have an entry.
+                    AuthError::CoreError(CoreError::RoutingClientError(
+                        ClientError::NoSuchEntry,
+                    )) => Ok(None),
+                    error => Err(error),
+                })
+        });
+
+        future::join_all(entries).map(|entries| {
+            entries
+                .into_iter()
+                .flat_map(|entry| entry.unwrap_or_default())
+                .map(|(_, (dir, _))| dir)
+                .collect::<Vec<MDataInfo>>()
+        })
+    });
+
+    std_dirs
+        .join(app_containers)
+        .and_then(move |(mut roots, app_containers)| {
+            roots.extend(app_containers);
+            reachability::walk(c3, roots).map_err(AuthError::from)
+        }).map(move |mut reachability| {
+            for dir in &[c4.access_container(), c4.config_root_dir()] {
+                let _ = reachability.mdata.insert((dir.name, dir.type_tag));
+            }
+            reachability
+        }).into_box()
+}
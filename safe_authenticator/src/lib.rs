@@ -93,6 +93,7 @@ mod app_container;
 mod client;
 mod config;
 mod errors;
+mod gc;
 mod ipc;
 mod revocation;
 mod std_dirs;
@@ -105,6 +106,7 @@ pub mod test_utils;
 mod tests;
 
 pub use self::errors::AuthError;
+pub use self::gc::gc_report;
 pub use crate::client::AuthClient;
 
 use futures::stream::Stream;
@@ -22,10 +22,12 @@ use crate::ffi::ipc::{
 use ffi_utils::test_utils::{call_1, call_vec, sender_as_user_data};
 use ffi_utils::{from_c_str, ErrorCode, ReprC, StringError};
 use futures::{future, Future};
+use routing::XorName;
 use safe_core::ffi::ipc::req::AppExchangeInfo as FfiAppExchangeInfo;
 use safe_core::ipc::{
     self, AuthReq, BootstrapConfig, ContainersReq, IpcError, IpcMsg, IpcReq, IpcResp, Permission,
 };
+use safe_core::nfs::{file_helper, File};
 use safe_core::{app_container_name, mdata_info, Client};
 use std::collections::HashMap;
 use std::ffi::CString;
@@ -468,6 +470,68 @@ fn config_root_dir() {
     assert!(config.content.is_empty());
 }
 
+// Test that the garbage collection report only references the data of existing files.
+#[test]
+fn gc_report() {
+    let authenticator = test_utils::create_account_and_login();
+    let docs = unwrap!(test_utils::get_container_from_authenticator_entry(
+        &authenticator,
+        "_documents"
+    ));
+
+    unwrap!(test_utils::create_file(
+        &authenticator,
+        docs.clone(),
+        "small.txt",
+        vec![1; 10]
+    ));
+    unwrap!(test_utils::create_file(
+        &authenticator,
+        docs.clone(),
+        "large.txt",
+        vec![2; 10_000]
+    ));
+    let small = unwrap!(test_utils::fetch_file(&authenticator, docs.clone(), "small.txt"));
+    let large = unwrap!(test_utils::fetch_file(&authenticator, docs.clone(), "large.txt"));
+
+    let before = test_utils::run(&authenticator, |client| crate::gc_report(client));
+    assert_eq!(before.files, 2);
+    assert_eq!(before.skipped_entries, 0);
+    assert!(before.mdata.contains(&(docs.name, docs.type_tag)));
+    assert!(before.idata.contains(small.data_map_name()));
+    assert!(before.idata.contains(large.data_map_name()));
+    assert!(unwrap!(before.orphaned(before.idata.iter().cloned())).is_empty());
+    assert!(before.failures.is_empty());
+
+    unwrap!(test_utils::delete_file(&authenticator, docs.clone(), "large.txt", 1));
+
+    // The data map of the deleted file and its three content chunks are orphaned.
+    let after = test_utils::run(&authenticator, |client| crate::gc_report(client));
+    assert_eq!(after.files, 1);
+    let orphaned = unwrap!(after.orphaned(before.idata.iter().cloned()));
+    assert_eq!(orphaned.len(), 4);
+    assert!(orphaned.contains(large.data_map_name()));
+    assert!(!orphaned.contains(small.data_map_name()));
+
+    // A file whose data map is missing is reported as a failure without stopping the walk.
+    let mut broken = File::new(Vec::new());
+    broken.set_data_map_name(XorName([7; 32]));
+    let docs2 = docs.clone();
+    test_utils::run(&authenticator, move |client| {
+        file_helper::insert(client.clone(), docs2, "broken.txt", &broken).map_err(AuthError::from)
+    });
+
+    let report = test_utils::run(&authenticator, |client| crate::gc_report(client));
+    assert_eq!(report.files, 2);
+    assert!(report.idata.contains(small.data_map_name()));
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].dir, (docs.name, docs.type_tag));
+    assert_eq!(report.failures[0].entry, Some("broken.txt".to_string()));
+
+    // Orphaned data can't be determined from an incomplete walk.
+    assert!(report.orphaned(before.idata.iter().cloned()).is_none());
+}
+
 // Test app authentication.
 #[test]
 fn app_authentication() {
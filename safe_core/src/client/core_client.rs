@@ -171,6 +171,7 @@ impl CoreClient {
                 routing,
                 hooks: HashMap::with_capacity(10),
                 cache: LruCache::new(IMMUT_DATA_CACHE_SIZE),
+                disk_cache: None,
                 timeout: Duration::from_secs(REQUEST_TIMEOUT_SECS),
                 joiner,
                 net_tx,
@@ -0,0 +1,348 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Differences between two observed states of a `MutableData`, and three-way merging of entries.
+//!
+//! A three-way merge takes the state both sides started from (the base), the local state and the
+//! current state on the network. The local changes which don't clash with the changes made on the
+//! network are turned into entry actions to apply to the current state. Entries changed on both
+//! sides in different ways are reported as conflicts and left for the caller to resolve.
+
+use routing::{EntryAction, MutableData, PermissionSet, User, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Change of a single entry or permission set.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change<T> {
+    /// The item has been added.
+    Added(T),
+    /// The item has been removed.
+    Removed(T),
+    /// The item has been changed.
+    Changed {
+        /// The item before the change.
+        old: T,
+        /// The item after the change.
+        new: T,
+    },
+}
+
+impl<T> Change<T> {
+    /// Returns the item before the change, or `None` if it has been added.
+    pub fn before(&self) -> Option<&T> {
+        match *self {
+            Change::Added(_) => None,
+            Change::Removed(ref old) | Change::Changed { ref old, .. } => Some(old),
+        }
+    }
+
+    /// Returns the item after the change, or `None` if it has been removed.
+    pub fn after(&self) -> Option<&T> {
+        match *self {
+            Change::Removed(_) => None,
+            Change::Added(ref new) | Change::Changed { ref new, .. } => Some(new),
+        }
+    }
+}
+
+/// Differences between two states of a `MutableData`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MDataDiff {
+    /// Changed entries.
+    pub entries: BTreeMap<Vec<u8>, Change<Value>>,
+    /// Changed permissions.
+    pub permissions: BTreeMap<User, Change<PermissionSet>>,
+}
+
+impl MDataDiff {
+    /// Compute the differences between the `old` and `new` states.
+    pub fn new(old: &MutableData, new: &MutableData) -> Self {
+        MDataDiff {
+            entries: diff_entries(old.entries(), new.entries()),
+            permissions: diff_permissions(old.permissions(), new.permissions()),
+        }
+    }
+
+    /// Returns true if nothing has changed.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.permissions.is_empty()
+    }
+}
+
+/// Compute the differences between two states of entries. An entry is changed if either its
+/// content or its version differs. Deleted entries, which are kept with empty content, are
+/// treated as absent: deleting an entry shows as its removal, and deleted entries never differ.
+pub fn diff_entries(
+    old: &BTreeMap<Vec<u8>, Value>,
+    new: &BTreeMap<Vec<u8>, Value>,
+) -> BTreeMap<Vec<u8>, Change<Value>> {
+    diff(&live_entries(old), &live_entries(new))
+}
+
+/// Compute the differences between two states of permissions.
+pub fn diff_permissions(
+    old: &BTreeMap<User, PermissionSet>,
+    new: &BTreeMap<User, PermissionSet>,
+) -> BTreeMap<User, Change<PermissionSet>> {
+    diff(old, new)
+}
+
+/// Entry changed in different ways by both sides of a merge.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Conflict {
+    /// The entry in the base state, if it existed.
+    pub base: Option<Value>,
+    /// The entry in the local state, if it exists.
+    pub ours: Option<Value>,
+    /// The entry in the current state on the network, if it exists.
+    pub theirs: Option<Value>,
+}
+
+/// Outcome of a three-way merge of entries.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EntryMerge {
+    /// Entry actions applying the local changes to the current state on the network.
+    pub actions: BTreeMap<Vec<u8>, EntryAction>,
+    /// Entries which haven't been merged because of conflicting changes.
+    pub conflicts: BTreeMap<Vec<u8>, Conflict>,
+}
+
+impl EntryMerge {
+    /// Returns true if there are no conflicts.
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge the changes made locally since `base` into `theirs`, the current entries on the network.
+///
+/// The entry actions are conditioned on the versions of the entries in `theirs`. Changes made on
+/// both sides are only reported as conflicts if their contents differ.
+pub fn merge_entries(
+    base: &BTreeMap<Vec<u8>, Value>,
+    ours: &BTreeMap<Vec<u8>, Value>,
+    theirs: &BTreeMap<Vec<u8>, Value>,
+) -> EntryMerge {
+    let their_changes = diff_entries(base, theirs);
+    let mut merge = EntryMerge::default();
+
+    for (key, change) in diff_entries(base, ours) {
+        let their_change = match their_changes.get(&key) {
+            Some(their_change) => their_change,
+            None => {
+                if let Some(action) = entry_action(theirs.get(&key), change.after()) {
+                    let _ = merge.actions.insert(key, action);
+                }
+                continue;
+            }
+        };
+
+        let our_content = change.after().map(|value| &value.content);
+        let their_content = their_change.after().map(|value| &value.content);
+        if our_content != their_content {
+            let conflict = Conflict {
+                base: change.before().cloned(),
+                ours: change.after().cloned(),
+                theirs: their_change.after().cloned(),
+            };
+            let _ = merge.conflicts.insert(key, conflict);
+        }
+    }
+
+    merge
+}
+
+/// Entry action changing the `current` entry into the `desired` one, or `None` if no change is
+/// needed. New entries are inserted as they are, existing ones updated or deleted with the
+/// version following the current one. A `desired` entry with empty content is treated as
+/// absent, and a deleted `current` entry is updated, as its version is kept by the network.
+pub fn entry_action(current: Option<&Value>, desired: Option<&Value>) -> Option<EntryAction> {
+    let desired = desired.filter(|desired| !desired.content.is_empty());
+
+    match (current, desired) {
+        (Some(current), Some(desired)) if current.content == desired.content => None,
+        (Some(current), Some(desired)) => Some(EntryAction::Update(Value {
+            content: desired.content.clone(),
+            entry_version: current.entry_version + 1,
+        })),
+        (None, Some(desired)) => Some(EntryAction::Ins(desired.clone())),
+        (Some(current), None) if current.content.is_empty() => None,
+        (Some(current), None) => Some(EntryAction::Del(current.entry_version + 1)),
+        (None, None) => None,
+    }
+}
+
+// Entries which haven't been deleted.
+fn live_entries(entries: &BTreeMap<Vec<u8>, Value>) -> BTreeMap<Vec<u8>, Value> {
+    entries
+        .iter()
+        .filter(|&(_, value)| !value.content.is_empty())
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+// Differences between two maps. Unlike `diff_entries`, this compares the entries exactly as they
+// are stored, including the deleted ones.
+pub(crate) fn diff<K, V>(old: &BTreeMap<K, V>, new: &BTreeMap<K, V>) -> BTreeMap<K, Change<V>>
+where
+    K: Clone + Ord,
+    V: Clone + PartialEq,
+{
+    let keys: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let change = match (old.get(key), new.get(key)) {
+                (Some(old), Some(new)) if old == new => return None,
+                (Some(old), Some(new)) => Change::Changed {
+                    old: old.clone(),
+                    new: new.clone(),
+                },
+                (Some(old), None) => Change::Removed(old.clone()),
+                (None, Some(new)) => Change::Added(new.clone()),
+                (None, None) => return None,
+            };
+            Some((key.clone(), change))
+        }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use routing::Action;
+
+    fn value(content: &[u8], entry_version: u64) -> Value {
+        Value {
+            content: content.to_vec(),
+            entry_version,
+        }
+    }
+
+    // Test computing the differences between entries and permissions.
+    #[test]
+    fn diff_states() {
+        let old = btree_map![
+            b"kept".to_vec() => value(b"a", 0),
+            b"changed".to_vec() => value(b"a", 0),
+            b"removed".to_vec() => value(b"a", 0)
+        ];
+        let new = btree_map![
+            b"kept".to_vec() => value(b"a", 0),
+            b"changed".to_vec() => value(b"b", 1),
+            b"added".to_vec() => value(b"a", 0)
+        ];
+
+        let diff = diff_entries(&old, &new);
+        assert_eq!(diff.len(), 3);
+        assert_eq!(diff[&b"added".to_vec()], Change::Added(value(b"a", 0)));
+        assert_eq!(diff[&b"removed".to_vec()], Change::Removed(value(b"a", 0)));
+        assert_eq!(
+            diff[&b"changed".to_vec()],
+            Change::Changed {
+                old: value(b"a", 0),
+                new: value(b"b", 1),
+            }
+        );
+        assert!(diff_entries(&new, &new).is_empty());
+
+        let old = btree_map![User::Anyone => PermissionSet::new().allow(Action::Insert)];
+        let new = btree_map![User::Anyone => PermissionSet::new().deny(Action::Insert)];
+        let diff = diff_permissions(&old, &new);
+        assert_eq!(diff[&User::Anyone].before(), old.get(&User::Anyone));
+        assert_eq!(diff[&User::Anyone].after(), new.get(&User::Anyone));
+    }
+
+    // Test that deleted entries are treated as absent.
+    #[test]
+    fn deleted_entries() {
+        let old = btree_map![
+            b"deleted".to_vec() => value(b"", 1),
+            b"deleting".to_vec() => value(b"a", 0),
+            b"restored".to_vec() => value(b"", 1)
+        ];
+        let new = btree_map![
+            b"deleted".to_vec() => value(b"", 3),
+            b"deleting".to_vec() => value(b"", 1),
+            b"restored".to_vec() => value(b"a", 2)
+        ];
+
+        let diff = diff_entries(&old, &new);
+        assert_eq!(diff.len(), 2);
+        assert_eq!(diff[&b"deleting".to_vec()], Change::Removed(value(b"a", 0)));
+        assert_eq!(diff[&b"restored".to_vec()], Change::Added(value(b"a", 2)));
+
+        let deleted = value(b"", 1);
+        let live = value(b"a", 0);
+        assert_eq!(entry_action(Some(&deleted), None), None);
+        assert_eq!(entry_action(Some(&deleted), Some(&value(b"", 3))), None);
+        assert_eq!(entry_action(None, Some(&deleted)), None);
+        assert_eq!(
+            entry_action(Some(&live), Some(&deleted)),
+            Some(EntryAction::Del(1))
+        );
+        assert_eq!(
+            entry_action(Some(&deleted), Some(&live)),
+            Some(EntryAction::Update(value(b"a", 2)))
+        );
+
+        // Deleting an entry which has been deleted on the network as well is no conflict.
+        let base = btree_map![b"entry".to_vec() => value(b"a", 0)];
+        let ours = btree_map![b"entry".to_vec() => value(b"", 1)];
+        let theirs = btree_map![b"entry".to_vec() => value(b"", 1)];
+        let merge = merge_entries(&base, &ours, &theirs);
+        assert!(merge.is_clean());
+        assert!(merge.actions.is_empty());
+    }
+
+    // Test merging non-conflicting changes and reporting conflicting ones.
+    #[test]
+    fn three_way_merge() {
+        let base = btree_map![
+            b"ours".to_vec() => value(b"base", 0),
+            b"theirs".to_vec() => value(b"base", 0),
+            b"both".to_vec() => value(b"base", 0),
+            b"same".to_vec() => value(b"base", 0),
+            b"deleted".to_vec() => value(b"base", 0)
+        ];
+        let ours = btree_map![
+            b"ours".to_vec() => value(b"ours", 1),
+            b"theirs".to_vec() => value(b"base", 0),
+            b"both".to_vec() => value(b"ours", 1),
+            b"same".to_vec() => value(b"same", 1),
+            b"new".to_vec() => value(b"ours", 0)
+        ];
+        let theirs = btree_map![
+            b"ours".to_vec() => value(b"base", 0),
+            b"theirs".to_vec() => value(b"theirs", 1),
+            b"both".to_vec() => value(b"theirs", 2),
+            b"same".to_vec() => value(b"same", 2),
+            b"deleted".to_vec() => value(b"base", 0)
+        ];
+
+        let merge = merge_entries(&base, &ours, &theirs);
+        assert_eq!(
+            merge.actions,
+            btree_map![
+                b"ours".to_vec() => EntryAction::Update(value(b"ours", 1)),
+                b"new".to_vec() => EntryAction::Ins(value(b"ours", 0)),
+                b"deleted".to_vec() => EntryAction::Del(1)
+            ]
+        );
+
+        assert!(!merge.is_clean());
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(
+            merge.conflicts[&b"both".to_vec()],
+            Conflict {
+                base: Some(value(b"base", 0)),
+                ours: Some(value(b"ours", 1)),
+                theirs: Some(value(b"theirs", 2)),
+            }
+        );
+    }
+}
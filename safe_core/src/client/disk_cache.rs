@@ -0,0 +1,374 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::crypto::shared_secretbox;
+use crate::errors::CoreError;
+use crate::utils;
+use lru_cache::LruCache;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use routing::{ImmutableData, XorName};
+use rust_sodium::crypto::secretbox;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+use std::usize;
+use tiny_keccak::sha3_256;
+
+const TMP_SUFFIX: &str = ".tmp";
+// Length of the file names of the cached chunks: the hex encoded SHA3-256 digest.
+const FILE_NAME_LEN: usize = 64;
+
+/// Cache of `ImmutableData` kept on disk, so the data survives restarts of the client.
+///
+/// Every chunk is stored encrypted in its own file. The file names are derived from both the
+/// names of the chunks and the cache key, so they don't reveal which data has been fetched.
+/// Chunks read back from the cache are checked against their names, and ones which fail to
+/// decrypt or don't match are discarded.
+///
+/// Once the total size of the files exceeds the capacity, the least recently used chunks are
+/// evicted. Recency is tracked in memory; after reopening the cache, chunks are ordered by the
+/// time they were stored.
+///
+/// Only files named like cached chunks are considered part of the cache, so other files in the
+/// directory are never counted, evicted or removed. Still, the directory should be dedicated to
+/// the cache.
+///
+/// All operations are performed with blocking file I/O. Chunks are at most about 1 MiB large and
+/// the client keeps the chunks read from the disk in its memory cache too, so the event loop only
+/// blocks for the first read of every chunk and while storing newly fetched ones.
+pub struct DiskCache {
+    dir: PathBuf,
+    key: shared_secretbox::Key,
+    capacity: u64,
+    size: u64,
+    // File names of the cached chunks, along with their sizes.
+    entries: LruCache<String, u64>,
+}
+
+impl DiskCache {
+    /// Open the cache stored in `dir`, creating the directory if it doesn't exist. The chunks are
+    /// encrypted with `key` and at most `capacity` bytes are kept.
+    pub fn open<P: Into<PathBuf>>(
+        dir: P,
+        key: shared_secretbox::Key,
+        capacity: u64,
+    ) -> Result<Self, CoreError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let file_name = match entry.file_name().into_string() {
+                Ok(file_name) => file_name,
+                Err(_) => continue,
+            };
+            if file_name.ends_with(TMP_SUFFIX) {
+                if is_chunk_file_name(&file_name[..file_name.len() - TMP_SUFFIX.len()]) {
+                    // Left behind by an interrupted write.
+                    let _ = fs::remove_file(entry.path());
+                }
+                continue;
+            }
+            if !is_chunk_file_name(&file_name) {
+                continue;
+            }
+
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            files.push((modified, file_name, metadata.len()));
+        }
+        files.sort();
+
+        let mut cache = DiskCache {
+            dir,
+            key,
+            capacity,
+            size: 0,
+            entries: LruCache::new(usize::MAX),
+        };
+        for (_, file_name, size) in files {
+            cache.size += size;
+            let _ = cache.entries.insert(file_name, size);
+        }
+        cache.evict();
+
+        Ok(cache)
+    }
+
+    /// Returns the maximal total size of the cached chunks, in bytes.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the total size of the cached chunks, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the number of cached chunks.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no chunks are cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the chunk with the given name, if it's cached and valid.
+    pub fn get(&mut self, name: &XorName) -> Option<ImmutableData> {
+        let file_name = self.file_name(name);
+        // Looking the entry up marks it as the most recently used one.
+        if self.entries.get_mut(&file_name).is_none() {
+            return None;
+        }
+
+        match self.read(&file_name) {
+            Ok(ref data) if data.name() == name => return Some(data.clone()),
+            Ok(_) => warn!("Cached chunk {:?} doesn't match its name, discarding it.", name),
+            Err(error) => warn!("Failed to read cached chunk {:?}: {:?}", name, error),
+        }
+
+        self.remove_file(&file_name);
+        None
+    }
+
+    /// Store the chunk, evicting the least recently used ones if the capacity is exceeded.
+    /// Failing to store a chunk is not an error, it is only logged.
+    pub fn insert(&mut self, data: &ImmutableData) {
+        let file_name = self.file_name(data.name());
+        if self.entries.get_mut(&file_name).is_some() {
+            return;
+        }
+
+        match self.write(&file_name, data) {
+            Ok(size) => {
+                self.size += size;
+                let _ = self.entries.insert(file_name, size);
+                self.evict();
+            }
+            Err(error) => warn!("Failed to cache chunk {:?}: {:?}", data.name(), error),
+        }
+    }
+
+    /// Remove the chunk with the given name from the cache.
+    pub fn remove(&mut self, name: &XorName) {
+        let file_name = self.file_name(name);
+        self.remove_file(&file_name);
+    }
+
+    /// Remove all the chunks from the cache.
+    pub fn clear(&mut self) {
+        while let Some((file_name, _)) = self.entries.remove_lru() {
+            let _ = fs::remove_file(self.dir.join(file_name));
+        }
+        self.size = 0;
+    }
+
+    fn file_name(&self, name: &XorName) -> String {
+        let key: &secretbox::Key = &self.key;
+        let digest = sha3_256(&[&key.0[..], &name.0[..]].concat());
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn read(&self, file_name: &str) -> Result<ImmutableData, CoreError> {
+        let mut content = Vec::new();
+        let _ = File::open(self.dir.join(file_name))?.read_to_end(&mut content)?;
+        let plain_text = utils::symmetric_decrypt(&content, &self.key)?;
+        Ok(deserialise(&plain_text)?)
+    }
+
+    // Write the chunk to a temporary file first, so an interrupted write never leaves a partial
+    // chunk behind under its final name.
+    fn write(&self, file_name: &str, data: &ImmutableData) -> Result<u64, CoreError> {
+        let cipher_text = utils::symmetric_encrypt(&serialise(data)?, &self.key, None)?;
+
+        let path = self.dir.join(file_name);
+        let tmp_path = self.dir.join(format!("{}{}", file_name, TMP_SUFFIX));
+        File::create(&tmp_path)?.write_all(&cipher_text)?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(cipher_text.len() as u64)
+    }
+
+    fn remove_file(&mut self, file_name: &str) {
+        if let Some(size) = self.entries.remove(file_name) {
+            self.size -= size;
+            if let Err(error) = fs::remove_file(self.dir.join(file_name)) {
+                warn!("Failed to remove cached chunk {}: {:?}", file_name, error);
+            }
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.capacity {
+            match self.entries.remove_lru() {
+                Some((file_name, size)) => {
+                    self.size -= size;
+                    if let Err(error) = fs::remove_file(self.dir.join(&file_name)) {
+                        warn!("Failed to evict cached chunk {}: {:?}", file_name, error);
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+fn is_chunk_file_name(file_name: &str) -> bool {
+    file_name.len() == FILE_NAME_LEN
+        && file_name
+            .bytes()
+            .all(|byte| byte.is_ascii_hexdigit() && !byte.is_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::utils;
+    use crate::utils::test_utils::random_client;
+    use futures::Future;
+    use rand;
+    use std::env;
+
+    fn random_data(size: usize) -> ImmutableData {
+        ImmutableData::new(unwrap!(utils::generate_random_vector(size)))
+    }
+
+    fn temp_dir() -> PathBuf {
+        env::temp_dir().join(format!("safe_core_disk_cache_{}", rand::random::<u64>()))
+    }
+
+    // Test storing chunks, evicting them and reopening the cache.
+    #[test]
+    fn insert_evict_reopen() {
+        let dir = temp_dir();
+        let key = shared_secretbox::gen_key();
+        let data: Vec<_> = (0..3).map(|_| random_data(1000)).collect();
+
+        {
+            let mut cache = unwrap!(DiskCache::open(&dir, key.clone(), 2500));
+            cache.insert(&data[0]);
+            cache.insert(&data[1]);
+            assert_eq!(cache.len(), 2);
+
+            // Using the first chunk makes the second one the least recently used.
+            assert_eq!(cache.get(data[0].name()), Some(data[0].clone()));
+            cache.insert(&data[2]);
+            assert_eq!(cache.len(), 2);
+            assert!(cache.size() <= cache.capacity());
+            assert_eq!(cache.get(data[1].name()), None);
+        }
+
+        {
+            let mut cache = unwrap!(DiskCache::open(&dir, key, 2500));
+            assert_eq!(cache.len(), 2);
+            assert_eq!(cache.get(data[0].name()), Some(data[0].clone()));
+            assert_eq!(cache.get(data[2].name()), Some(data[2].clone()));
+        }
+
+        // Chunks cached with another key can't be found.
+        {
+            let mut cache = unwrap!(DiskCache::open(&dir, shared_secretbox::gen_key(), 2500));
+            assert_eq!(cache.get(data[0].name()), None);
+            cache.clear();
+            assert!(cache.is_empty());
+        }
+
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    // Test that files which aren't cached chunks are left alone.
+    #[test]
+    fn foreign_files() {
+        let dir = temp_dir();
+        let key = shared_secretbox::gen_key();
+        unwrap!(fs::create_dir_all(&dir));
+        unwrap!(fs::write(dir.join("notes.txt"), vec![0; 1000]));
+        unwrap!(fs::write(dir.join("notes.tmp"), vec![0; 1000]));
+
+        let mut cache = unwrap!(DiskCache::open(&dir, key.clone(), 1500));
+        assert!(cache.is_empty());
+        assert_eq!(cache.size(), 0);
+
+        cache.insert(&random_data(1000));
+        cache.insert(&random_data(1000));
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+
+        let cache = unwrap!(DiskCache::open(&dir, key, 1500));
+        assert!(cache.is_empty());
+        assert!(dir.join("notes.txt").exists());
+        assert!(dir.join("notes.tmp").exists());
+
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    // Test that `get_idata` reads from the disk cache and stores the fetched data in it.
+    #[test]
+    fn client_get_idata() {
+        let dir = temp_dir();
+        let key = shared_secretbox::gen_key();
+        // Never stored on the network, so it can only be retrieved from the cache.
+        let cached = random_data(1000);
+        let stored = random_data(1000);
+
+        let mut cache = unwrap!(DiskCache::open(&dir, key.clone(), 1_000_000));
+        cache.insert(&cached);
+
+        let cached2 = cached.clone();
+        let stored2 = stored.clone();
+
+        random_client(move |client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            client.set_disk_cache(Some(cache));
+
+            client
+                .get_idata(*cached2.name())
+                .and_then(move |data| {
+                    assert_eq!(data, cached2);
+                    client2.put_idata(stored2.clone()).map(move |()| stored2)
+                }).and_then(move |stored| client3.get_idata(*stored.name()))
+        });
+
+        let mut cache = unwrap!(DiskCache::open(&dir, key, 1_000_000));
+        assert_eq!(cache.get(stored.name()), Some(stored));
+
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    // Test that chunks modified on disk are detected and discarded.
+    #[test]
+    fn tampered_chunk() {
+        let dir = temp_dir();
+        let key = shared_secretbox::gen_key();
+        let data = random_data(1000);
+        let other = random_data(1000);
+
+        let mut cache = unwrap!(DiskCache::open(&dir, key, 1_000_000));
+        cache.insert(&data);
+        cache.insert(&other);
+
+        // Replace the file of the first chunk by the file of the second one.
+        let path = dir.join(cache.file_name(data.name()));
+        unwrap!(fs::copy(dir.join(cache.file_name(other.name())), &path));
+
+        assert_eq!(cache.get(data.name()), None);
+        assert!(!path.exists());
+        assert_eq!(cache.len(), 1);
+
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+}
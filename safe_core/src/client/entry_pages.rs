@@ -0,0 +1,208 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::client::mdata_info::is_hidden_entry;
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use futures::stream::{self, Stream};
+use futures::Future;
+use routing::Value;
+use std::cmp;
+use std::collections::VecDeque;
+
+/// Number of entries in a page, unless specified otherwise.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Entries of a `MutableData`, handed out a page at a time and ordered by their decrypted keys.
+///
+/// The network only returns all the entries at once, so they are fetched up front. Their keys are
+/// decrypted to filter and order them, but the values are only decrypted page by page, as they
+/// are handed out. Deleted entries and the metadata entry are skipped.
+pub struct EntryPages {
+    info: MDataInfo,
+    page_size: usize,
+    // Decrypted keys of the remaining entries, along with their still encrypted values.
+    entries: VecDeque<(Vec<u8>, Value)>,
+}
+
+impl EntryPages {
+    /// Fetch the entries of the `MutableData` described by `info` whose decrypted keys start with
+    /// `prefix`. Every page holds `page_size` entries, except for the last one.
+    pub fn fetch(
+        client: &impl Client,
+        info: MDataInfo,
+        prefix: Vec<u8>,
+        page_size: usize,
+    ) -> Box<CoreFuture<EntryPages>> {
+        trace!(
+            "Listing entries of {:?} in pages of {}",
+            info.name,
+            page_size
+        );
+
+        client
+            .list_mdata_entries(info.name, info.type_tag)
+            .and_then(move |entries| {
+                let mut filtered = Vec::new();
+                for (key, value) in entries {
+                    if is_hidden_entry(&key, &value) {
+                        continue;
+                    }
+                    let key = info.decrypt(&key)?;
+                    if key.starts_with(&prefix) {
+                        filtered.push((key, value));
+                    }
+                }
+                filtered.sort_by(|a, b| a.0.cmp(&b.0));
+
+                Ok(EntryPages {
+                    info,
+                    page_size: cmp::max(page_size, 1),
+                    entries: filtered.into_iter().collect(),
+                })
+            }).into_box()
+    }
+
+    /// Returns the number of entries which haven't been handed out yet.
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the next page of decrypted entries, or `None` once all of them have been handed
+    /// out.
+    pub fn next_page(&mut self) -> Result<Option<Vec<(Vec<u8>, Value)>>, CoreError> {
+        if self.entries.is_empty() {
+            return Ok(None);
+        }
+
+        let len = cmp::min(self.page_size, self.entries.len());
+        let mut page = Vec::with_capacity(len);
+
+        for (key, value) in self.entries.drain(..len) {
+            let value = Value {
+                content: self.info.decrypt(&value.content)?,
+                entry_version: value.entry_version,
+            };
+            page.push((key, value));
+        }
+
+        Ok(Some(page))
+    }
+}
+
+impl Iterator for EntryPages {
+    type Item = Result<Vec<(Vec<u8>, Value)>, CoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_page() {
+            Ok(page) => page.map(Ok),
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+/// Stream of the decrypted entries of the `MutableData` described by `info` whose decrypted keys
+/// start with `prefix`, ordered by their keys.
+pub fn entry_stream(
+    client: &impl Client,
+    info: MDataInfo,
+    prefix: Vec<u8>,
+) -> Box<Stream<Item = (Vec<u8>, Value), Error = CoreError>> {
+    let entries = EntryPages::fetch(client, info, prefix, DEFAULT_PAGE_SIZE)
+        .map(stream::iter_result)
+        .flatten_stream()
+        .map(stream::iter_ok)
+        .flatten();
+
+    Box::new(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mdata_info;
+    use crate::ipc::resp::METADATA_KEY;
+    use crate::utils::test_utils::random_client;
+    use crate::DIR_TAG;
+    use routing::{Action, MutableData, PermissionSet, User};
+    use std::collections::BTreeMap;
+
+    // Test listing entries in pages and as a stream, filtered by prefix.
+    #[test]
+    fn list_pages() {
+        let info = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let info2 = info.clone();
+        let info3 = info.clone();
+
+        random_client(move |client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+
+            let mut entries = BTreeMap::new();
+            for i in 0..25 {
+                let prefix = if i % 2 == 0 { "even" } else { "odd" };
+                let key = format!("{}-{:02}", prefix, i).into_bytes();
+                let value = Value {
+                    content: format!("value {}", i).into_bytes(),
+                    entry_version: 0,
+                };
+                let _ = entries.insert(key, value);
+            }
+            let mut entries = unwrap!(mdata_info::encrypt_entries(&info, &entries));
+
+            // Neither deleted entries nor the metadata entry are listed.
+            let deleted = Value {
+                content: Vec::new(),
+                entry_version: 1,
+            };
+            let metadata = Value {
+                content: b"metadata".to_vec(),
+                entry_version: 0,
+            };
+            let _ = entries.insert(unwrap!(info.enc_entry_key(b"even-deleted")), deleted);
+            let _ = entries.insert(METADATA_KEY.to_vec(), metadata);
+
+            let owners = btree_set![unwrap!(client.public_signing_key())];
+            let permissions = btree_map![
+                User::Anyone => PermissionSet::new().allow(Action::Insert)
+            ];
+            let data = unwrap!(MutableData::new(
+                info.name,
+                info.type_tag,
+                permissions,
+                entries,
+                owners,
+            ));
+
+            client
+                .put_mdata(data)
+                .and_then(move |()| EntryPages::fetch(&client2, info2, b"even".to_vec(), 5))
+                .and_then(move |pages| {
+                    assert_eq!(pages.remaining(), 13);
+
+                    let pages: Vec<_> = unwrap!(pages.collect::<Result<_, _>>());
+                    assert_eq!(
+                        pages.iter().map(|page| page.len()).collect::<Vec<_>>(),
+                        vec![5, 5, 3]
+                    );
+
+                    let (ref key, ref value) = pages[0][1];
+                    assert_eq!(*key, b"even-02".to_vec());
+                    assert_eq!(value.content, b"value 2".to_vec());
+
+                    entry_stream(&client3, info3, Vec::new()).collect()
+                }).map(move |entries| {
+                    assert_eq!(entries.len(), 25);
+                    assert_eq!(entries[0].0, b"even-00".to_vec());
+                    assert_eq!(entries[24].0, b"odd-23".to_vec());
+                })
+        })
+    }
+}
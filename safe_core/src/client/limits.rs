@@ -0,0 +1,475 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! The network rejects `MutableData` with more than `MAX_MUTABLE_DATA_ENTRIES` entries or larger
+//! than `MAX_MUTABLE_DATA_SIZE_IN_BYTES` once serialised. These limits apply to the `MutableData`
+//! as a whole: a mutation is rejected if the data would exceed them once it's applied, no matter
+//! how many requests the entries have been added in. Deleted entries keep counting towards them.
+//!
+//! `Client::put_mdata` validates the data against these limits before sending it, and
+//! `Client::mutate_mdata_entries` rejects requests which exceed them on their own, failing with
+//! the same errors the network would return. Whether a mutation fits along with the existing
+//! entries can only be told knowing them, which `validate_mutation` checks.
+//!
+//! Large batches of entry actions can be applied with `mutate_mdata_entries_chunked`, which
+//! checks them against the current state of the data first and then splits them into several
+//! requests.
+
+use crate::client::Client;
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::Future;
+use maidsafe_utilities::serialisation::serialise;
+use routing::{ClientError, EntryAction, MutableData, Value, XorName};
+pub use routing::{MAX_MUTABLE_DATA_ENTRIES, MAX_MUTABLE_DATA_SIZE_IN_BYTES};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Limits of a single request mutating `MutableData` entries, used to split large mutations.
+///
+/// By default they equal the limits of a whole `MutableData`, as no request can exceed those on
+/// its own. Smaller limits make for smaller requests and more frequent progress reports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RequestLimits {
+    /// Maximal number of entry actions.
+    pub max_actions: usize,
+    /// Maximal size of the serialised entry actions, in bytes.
+    pub max_size: u64,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_actions: MAX_MUTABLE_DATA_ENTRIES as usize,
+            max_size: MAX_MUTABLE_DATA_SIZE_IN_BYTES,
+        }
+    }
+}
+
+/// Progress of a chunked mutation, reported after every request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// Number of requests which have succeeded.
+    pub requests_done: usize,
+    /// Total number of requests.
+    pub requests_total: usize,
+    /// Number of entry actions which have been applied.
+    pub actions_done: usize,
+    /// Total number of entry actions.
+    pub actions_total: usize,
+}
+
+/// Outcome of a chunked mutation.
+#[derive(Debug)]
+pub struct MutationReport {
+    /// Keys of the entries which have been mutated.
+    pub applied: BTreeSet<Vec<u8>>,
+    /// Entry actions which haven't been applied, including the ones of the failed request.
+    pub remaining: BTreeMap<Vec<u8>, EntryAction>,
+    /// Error the failed request was rejected with, or `None` if all the actions were applied.
+    pub error: Option<CoreError>,
+}
+
+impl MutationReport {
+    /// Returns true if all the entry actions have been applied.
+    pub fn is_complete(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Check that the `MutableData` fits within the network limits.
+pub fn validate_mdata(data: &MutableData) -> Result<(), CoreError> {
+    if data.entries().len() as u64 > MAX_MUTABLE_DATA_ENTRIES {
+        return Err(CoreError::RoutingClientError(ClientError::TooManyEntries));
+    }
+    if data.serialised_size() > MAX_MUTABLE_DATA_SIZE_IN_BYTES {
+        return Err(CoreError::RoutingClientError(ClientError::DataTooLarge));
+    }
+    Ok(())
+}
+
+/// Check that applying the entry actions keeps the `MutableData` within the network limits,
+/// counting its current entries too.
+pub fn validate_mutation(
+    data: &MutableData,
+    actions: &BTreeMap<Vec<u8>, EntryAction>,
+) -> Result<(), CoreError> {
+    let mut entries = data.entries().clone();
+    for (key, action) in actions {
+        let value = match *action {
+            EntryAction::Ins(ref value) | EntryAction::Update(ref value) => value.clone(),
+            EntryAction::Del(entry_version) => Value {
+                content: Vec::new(),
+                entry_version,
+            },
+        };
+        let _ = entries.insert(key.clone(), value);
+    }
+
+    if entries.len() as u64 > MAX_MUTABLE_DATA_ENTRIES {
+        return Err(CoreError::RoutingClientError(ClientError::TooManyEntries));
+    }
+
+    let entries_size = serialise(&entries)?.len() as u64;
+    let current_size = serialise(data.entries())?.len() as u64;
+    if data.serialised_size() - current_size + entries_size > MAX_MUTABLE_DATA_SIZE_IN_BYTES {
+        return Err(CoreError::RoutingClientError(ClientError::DataTooLarge));
+    }
+    Ok(())
+}
+
+/// Check that the entry actions fit within the limits of a single request.
+pub fn validate_entry_actions(
+    actions: &BTreeMap<Vec<u8>, EntryAction>,
+    limits: &RequestLimits,
+) -> Result<(), CoreError> {
+    if actions.len() > limits.max_actions {
+        return Err(CoreError::RoutingClientError(ClientError::TooManyEntries));
+    }
+    if serialise(actions)?.len() as u64 > limits.max_size {
+        return Err(CoreError::RoutingClientError(ClientError::DataTooLarge));
+    }
+    Ok(())
+}
+
+/// Split the entry actions into batches which fit within the limits of a single request,
+/// ordered by their keys. Fails with `ClientError::DataTooLarge` if a single action doesn't fit.
+pub fn split_entry_actions(
+    actions: BTreeMap<Vec<u8>, EntryAction>,
+    limits: &RequestLimits,
+) -> Result<Vec<BTreeMap<Vec<u8>, EntryAction>>, CoreError> {
+    // Size of the serialised map without any entries.
+    let empty_size = serialise(&BTreeMap::<Vec<u8>, EntryAction>::new())?.len() as u64;
+
+    let mut batches = Vec::new();
+    let mut batch = BTreeMap::new();
+    let mut batch_size = empty_size;
+
+    for (key, action) in actions {
+        let size = serialise(&(&key, &action))?.len() as u64;
+        if empty_size + size > limits.max_size {
+            return Err(CoreError::RoutingClientError(ClientError::DataTooLarge));
+        }
+
+        if batch.len() >= limits.max_actions || batch_size + size > limits.max_size {
+            batches.push(batch);
+            batch = BTreeMap::new();
+            batch_size = empty_size;
+        }
+
+        let _ = batch.insert(key, action);
+        batch_size += size;
+    }
+
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    Ok(batches)
+}
+
+/// Apply the entry actions in as many requests as needed to stay within `limits`, sent one after
+/// another. `progress` is called after every successful request.
+///
+/// The data is fetched first, and nothing is sent if applying all the actions would make it
+/// exceed the network limits. As other clients may add entries in the meantime, a request can
+/// still be rejected for exceeding them.
+///
+/// The requests are not atomic as a whole: if one of them fails, the following ones are not sent
+/// and the returned report lists the actions which have been applied and the ones which haven't,
+/// along with the error. The future itself only fails if the data can't be fetched, the actions
+/// exceed the limits of the data or they can't be split.
+pub fn mutate_mdata_entries_chunked<P>(
+    client: &impl Client,
+    name: XorName,
+    tag: u64,
+    actions: BTreeMap<Vec<u8>, EntryAction>,
+    limits: RequestLimits,
+    progress: P,
+) -> Box<CoreFuture<MutationReport>>
+where
+    P: FnMut(&Progress) + 'static,
+{
+    let client = client.clone();
+
+    client
+        .get_mdata(name, tag)
+        .and_then(move |data| {
+            validate_mutation(&data, &actions)?;
+            let actions_total = actions.len();
+            let batches: VecDeque<_> = split_entry_actions(actions, &limits)?.into();
+            Ok((batches, actions_total))
+        }).and_then(move |(batches, actions_total)| {
+            trace!(
+                "Mutating {} entries of {:?} in {} requests",
+                actions_total,
+                name,
+                batches.len()
+            );
+
+            let state = Progress {
+                requests_done: 0,
+                requests_total: batches.len(),
+                actions_done: 0,
+                actions_total,
+            };
+
+            apply_batches(client, name, tag, batches, state, progress)
+        }).into_box()
+}
+
+// Send the batches one after another, stopping at the first failure.
+fn apply_batches<C, P>(
+    client: C,
+    name: XorName,
+    tag: u64,
+    batches: VecDeque<BTreeMap<Vec<u8>, EntryAction>>,
+    state: Progress,
+    progress: P,
+) -> Box<CoreFuture<MutationReport>>
+where
+    C: Client,
+    P: FnMut(&Progress) + 'static,
+{
+    future::loop_fn(
+        (batches, state, BTreeSet::new(), progress),
+        move |(mut batches, mut state, mut applied, mut progress)| {
+            let batch = match batches.pop_front() {
+                Some(batch) => batch,
+                None => {
+                    return ok!(Loop::Break(MutationReport {
+                        applied,
+                        remaining: BTreeMap::new(),
+                        error: None,
+                    }))
+                }
+            };
+            let batch2 = batch.clone();
+
+            client
+                .mutate_mdata_entries(name, tag, batch)
+                .then(move |result| match result {
+                    Ok(()) => {
+                        state.requests_done += 1;
+                        state.actions_done += batch2.len();
+                        applied.extend(batch2.into_iter().map(|(key, _)| key));
+                        progress(&state);
+                        Ok(Loop::Continue((batches, state, applied, progress)))
+                    }
+                    Err(error) => {
+                        let mut remaining = batch2;
+                        remaining.extend(batches.into_iter().flat_map(|batch| batch));
+                        Ok(Loop::Break(MutationReport {
+                            applied,
+                            remaining,
+                            error: Some(error),
+                        }))
+                    }
+                }).into_box()
+        },
+    ).into_box()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use rand;
+    use routing::{Action, PermissionSet, User};
+    use rust_sodium::crypto::sign;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn insert(content: Vec<u8>) -> EntryAction {
+        EntryAction::Ins(Value {
+            content,
+            entry_version: 0,
+        })
+    }
+
+    fn insert_actions(count: usize, size: usize) -> BTreeMap<Vec<u8>, EntryAction> {
+        (0..count)
+            .map(|i| (format!("key-{:04}", i).into_bytes(), insert(vec![0; size])))
+            .collect()
+    }
+
+    // Test splitting entry actions by their number and by their size.
+    #[test]
+    fn split() {
+        let limits = RequestLimits {
+            max_actions: 3,
+            max_size: 1000,
+        };
+        let batches = unwrap!(split_entry_actions(insert_actions(10, 10), &limits));
+        assert_eq!(
+            batches.iter().map(|batch| batch.len()).collect::<Vec<_>>(),
+            vec![3, 3, 3, 1]
+        );
+
+        let batches = unwrap!(split_entry_actions(insert_actions(10, 300), &limits));
+        assert_eq!(batches.len(), 5);
+        for batch in &batches {
+            unwrap!(validate_entry_actions(batch, &limits));
+        }
+
+        match split_entry_actions(insert_actions(1, 1000), &limits) {
+            Err(CoreError::RoutingClientError(ClientError::DataTooLarge)) => (),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    // Test that mutations are validated along with the current entries of the data.
+    #[test]
+    fn mutation_limits() {
+        let owners = btree_set![sign::gen_keypair().0];
+        let entries = (0..MAX_MUTABLE_DATA_ENTRIES - 2)
+            .map(|i| {
+                let value = Value {
+                    content: vec![0; 10],
+                    entry_version: 0,
+                };
+                (format!("key-{:04}", i).into_bytes(), value)
+            }).collect();
+        let data = unwrap!(MutableData::new(
+            rand::random(),
+            10_000,
+            BTreeMap::new(),
+            entries,
+            owners.clone()
+        ));
+
+        let fits: BTreeMap<_, _> = (0..2)
+            .map(|i| (format!("new-{}", i).into_bytes(), insert(vec![0; 10])))
+            .collect();
+        unwrap!(validate_mutation(&data, &fits));
+
+        let too_many: BTreeMap<_, _> = (0..3)
+            .map(|i| (format!("new-{}", i).into_bytes(), insert(vec![0; 10])))
+            .collect();
+        match validate_mutation(&data, &too_many) {
+            Err(CoreError::RoutingClientError(ClientError::TooManyEntries)) => (),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        // Updating and deleting existing entries doesn't add to their number.
+        let actions = btree_map![
+            b"key-0000".to_vec() => EntryAction::Update(Value {
+                content: vec![1; 10],
+                entry_version: 1,
+            }),
+            b"key-0001".to_vec() => EntryAction::Del(1),
+            b"new-0".to_vec() => insert(vec![0; 10])
+        ];
+        unwrap!(validate_mutation(&data, &actions));
+
+        let half = MAX_MUTABLE_DATA_SIZE_IN_BYTES as usize / 2;
+        let value = Value {
+            content: vec![0; half],
+            entry_version: 0,
+        };
+        let data = unwrap!(MutableData::new(
+            rand::random(),
+            10_000,
+            BTreeMap::new(),
+            btree_map![b"large".to_vec() => value],
+            owners
+        ));
+
+        let actions = btree_map![b"large".to_vec() => EntryAction::Update(Value {
+            content: vec![1; half],
+            entry_version: 1,
+        })];
+        unwrap!(validate_mutation(&data, &actions));
+
+        let actions = btree_map![b"larger".to_vec() => insert(vec![0; half])];
+        match validate_mutation(&data, &actions) {
+            Err(CoreError::RoutingClientError(ClientError::DataTooLarge)) => (),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    // Test that oversized requests are rejected before being sent, and that chunked mutations
+    // report their progress and partial failures.
+    #[test]
+    fn chunked_mutation() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+
+            let name: XorName = rand::random();
+            let tag = 10_000;
+            let permissions = btree_map![
+                User::Anyone => PermissionSet::new().allow(Action::Insert).allow(Action::Update)
+            ];
+            let owners = btree_set![unwrap!(client.public_signing_key())];
+            let data = unwrap!(MutableData::new(
+                name,
+                tag,
+                permissions,
+                BTreeMap::new(),
+                owners
+            ));
+
+            let limits = RequestLimits {
+                max_actions: 3,
+                max_size: MAX_MUTABLE_DATA_SIZE_IN_BYTES,
+            };
+            let reported = Rc::new(RefCell::new(Vec::new()));
+            let reported2 = Rc::clone(&reported);
+
+            let too_many = insert_actions(MAX_MUTABLE_DATA_ENTRIES as usize + 1, 1);
+
+            client
+                .put_mdata(data)
+                .and_then(move |()| client2.mutate_mdata_entries(name, tag, too_many))
+                .then(move |res| {
+                    match res {
+                        Err(CoreError::RoutingClientError(ClientError::TooManyEntries)) => (),
+                        res => panic!("Unexpected result: {:?}", res),
+                    }
+
+                    mutate_mdata_entries_chunked(
+                        &client3,
+                        name,
+                        tag,
+                        insert_actions(7, 10),
+                        limits,
+                        move |progress| reported2.borrow_mut().push(*progress),
+                    )
+                }).and_then(move |report| {
+                    assert!(report.is_complete());
+                    assert_eq!(report.applied.len(), 7);
+                    {
+                        let reported = reported.borrow();
+                        assert_eq!(reported.len(), 3);
+                        assert_eq!(reported[2].requests_done, 3);
+                        assert_eq!(reported[2].actions_done, 7);
+                    }
+
+                    // The second request fails, as it inserts an existing entry.
+                    let actions: BTreeMap<_, _> = vec!["b-1", "b-2", "b-3", "key-0000", "key-9999"]
+                        .into_iter()
+                        .map(|key| (key.as_bytes().to_vec(), insert(Vec::new())))
+                        .collect();
+
+                    mutate_mdata_entries_chunked(&client4, name, tag, actions, limits, |_| ())
+                }).map(|report| {
+                    assert!(!report.is_complete());
+                    assert_eq!(report.applied.len(), 3);
+                    assert!(report.applied.contains(&b"b-3".to_vec()));
+                    assert_eq!(report.remaining.len(), 2);
+                    assert!(report.remaining.contains_key(&b"key-9999".to_vec()));
+                    match unwrap!(report.error) {
+                        CoreError::RoutingClientError(ClientError::InvalidEntryActions(_)) => (),
+                        error => panic!("Unexpected error: {:?}", error),
+                    }
+                })
+        })
+    }
+}
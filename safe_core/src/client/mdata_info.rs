@@ -11,6 +11,7 @@ use crate::errors::CoreError;
 use crate::ffi::arrays::{SymNonce, SymSecretKey};
 use crate::ffi::MDataInfo as FfiMDataInfo;
 use ffi_utils::ReprC;
+use crate::ipc::resp::METADATA_KEY;
 use crate::ipc::IpcError;
 use rand::{OsRng, Rng};
 use routing::{EntryAction, Value, XorName};
@@ -195,6 +196,12 @@ pub fn encrypt_entry_actions(
     Ok(output)
 }
 
+/// Returns true if the entry, given with its key still encrypted, is deleted or holds the
+/// metadata of the `MutableData`. Such entries can't be decrypted and aren't listed.
+pub fn is_hidden_entry(key: &[u8], value: &Value) -> bool {
+    value.content.is_empty() || key == METADATA_KEY
+}
+
 /// Decrypt entries using the `MDataInfo`.
 pub fn decrypt_entries(
     info: &MDataInfo,
@@ -87,6 +87,7 @@ pub struct Routing {
     client_auth: Authority<XorName>,
     max_ops_countdown: Option<Cell<u64>>,
     timeout_simulation: bool,
+    latency_ms: u64,
     request_hook: Option<Box<RequestHookFn>>,
     response_hook: Option<Box<ResponseHookFn>>,
 }
@@ -121,6 +122,7 @@ impl Routing {
             client_auth,
             max_ops_countdown: None,
             timeout_simulation: false,
+            latency_ms: 0,
             request_hook: None,
             response_hook: None,
         })
@@ -841,7 +843,7 @@ impl Routing {
 
         let event = Event::Response { response, src, dst };
 
-        self.send_event(delay_ms, event)
+        self.send_event(delay_ms + self.latency_ms, event)
     }
 
     fn send_event(&self, delay_ms: u64, event: Event) {
@@ -1137,6 +1139,11 @@ impl Routing {
     pub fn set_simulate_timeout(&mut self, enable: bool) {
         self.timeout_simulation = enable;
     }
+
+    /// Simulates network latency by delaying every response by the given duration.
+    pub fn set_simulated_latency(&mut self, latency: Duration) {
+        self.latency_ms = latency.as_secs() * 1000 + u64::from(latency.subsec_millis());
+    }
 }
 
 impl Drop for Routing {
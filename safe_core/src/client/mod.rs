@@ -11,16 +11,32 @@ pub mod account;
 /// Client provided for testing purposes.
 #[cfg(any(test, feature = "testing"))]
 pub mod core_client;
+/// Diffs and three-way merges of `MutableData` states.
+pub mod diff;
+/// Persistent cache of `ImmutableData`.
+pub mod disk_cache;
+/// Paginated listing of `MutableData` entries.
+pub mod entry_pages;
+/// Network limits of `MutableData` and splitting of oversized mutations.
+pub mod limits;
 /// `MDataInfo` utilities.
 pub mod mdata_info;
 /// Operations with recovery.
 pub mod recovery;
+/// Snapshots of `MutableData` for backup and restore.
+pub mod snapshot;
+/// Optimistic-concurrency transactions on `MutableData` entries.
+pub mod transaction;
 
 #[cfg(feature = "use-mock-routing")]
 mod mock;
 mod routing_event_loop;
 
 pub use self::account::ClientKeys;
+pub use self::disk_cache::DiskCache;
+pub use self::entry_pages::EntryPages;
+pub use self::limits::RequestLimits;
+pub use self::snapshot::Snapshot;
 pub use self::mdata_info::MDataInfo;
 #[cfg(feature = "use-mock-routing")]
 pub use self::mock::vault::file_store_path as mock_vault_path;
@@ -145,6 +161,22 @@ pub trait Client: Clone + 'static {
         inner.borrow_mut().timeout = duration;
     }
 
+    /// Set the cache on disk consulted by `get_idata` before fetching data from the network, or
+    /// disable it by passing `None`. The cache performs blocking file I/O on the event loop, see
+    /// `DiskCache` for details.
+    fn set_disk_cache(&self, cache: Option<DiskCache>) {
+        let inner = self.inner();
+        inner.borrow_mut().disk_cache = cache;
+    }
+
+    /// Create a future that resolves after the given time interval has elapsed on the client's
+    /// event loop.
+    fn delay(&self, duration: Duration) -> Box<CoreFuture<()>> {
+        let inner = self.inner();
+        let timeout = fry!(Timeout::new(duration, &inner.borrow().el_handle));
+        timeout.map_err(CoreError::from).into_box()
+    }
+
     /// Restart the routing client and reconnect to the network.
     fn restart_routing(&self) -> Result<(), CoreError> {
         let opt_id = self.full_id();
@@ -174,8 +206,9 @@ pub trait Client: Clone + 'static {
         }
     }
 
-    /// Get immutable data from the network. If the data exists locally in the cache then it will be
-    /// immediately returned without making an actual network request.
+    /// Get immutable data from the network. If the data exists locally in the cache (or in the
+    /// disk cache, if one is set) then it will be immediately returned without making an actual
+    /// network request.
     fn get_idata(&self, name: XorName) -> Box<CoreFuture<ImmutableData>> {
         trace!("GetIData for {:?}", name);
 
@@ -185,17 +218,41 @@ pub trait Client: Clone + 'static {
             return future::ok(data.clone()).into_box();
         }
 
+        let cached = inner
+            .borrow_mut()
+            .disk_cache
+            .as_mut()
+            .and_then(|disk_cache| disk_cache.get(&name));
+        if let Some(data) = cached {
+            trace!("ImmutableData found in disk cache.");
+            let _ = inner.borrow_mut().cache.insert(name, data.clone());
+            return future::ok(data).into_box();
+        }
+
         let inner = Rc::downgrade(&self.inner());
+        self.fetch_idata(name)
+            .map(move |data| {
+                if let Some(inner) = inner.upgrade() {
+                    // Put to cache
+                    let mut inner = inner.borrow_mut();
+                    if let Some(ref mut disk_cache) = inner.disk_cache {
+                        disk_cache.insert(&data);
+                    }
+                    let _ = inner.cache.insert(*data.name(), data.clone());
+                }
+                data
+            }).into_box()
+    }
+
+    /// Get immutable data from the network, bypassing the caches. The fetched data is not stored
+    /// in the caches either.
+    fn fetch_idata(&self, name: XorName) -> Box<CoreFuture<ImmutableData>> {
+        trace!("FetchIData for {:?}", name);
+
         send(self, move |routing, msg_id| {
             routing.get_idata(Authority::NaeManager(name), name, msg_id)
         }).and_then(|event| match_event!(event, CoreEvent::GetIData))
-        .map(move |data| {
-            if let Some(inner) = inner.upgrade() {
-                // Put to cache
-                let _ = inner.borrow_mut().cache.insert(*data.name(), data.clone());
-            }
-            data
-        }).into_box()
+        .into_box()
     }
 
     // TODO All these return the same future from all branches. So convert to impl
@@ -214,13 +271,15 @@ pub trait Client: Clone + 'static {
     fn put_mdata(&self, data: MutableData) -> Box<CoreFuture<()>> {
         trace!("PutMData for {:?}", data);
 
+        fry!(limits::validate_mdata(&data));
         let requester = some_or_err!(self.public_signing_key());
         send_mutation(self, move |routing, dst, msg_id| {
             routing.put_mdata(dst, data.clone(), msg_id, requester)
         })
     }
 
-    /// Mutates `MutableData` entries in bulk.
+    /// Mutates `MutableData` entries in bulk. Requests which exceed the limits of a whole
+    /// `MutableData` on their own are rejected before being sent.
     fn mutate_mdata_entries(
         &self,
         name: XorName,
@@ -229,6 +288,10 @@ pub trait Client: Clone + 'static {
     ) -> Box<CoreFuture<()>> {
         trace!("PutMData for {:?}", name);
 
+        fry!(limits::validate_entry_actions(
+            &actions,
+            &RequestLimits::default()
+        ));
         let requester = some_or_err!(self.public_signing_key());
         send_mutation(self, move |routing, dst, msg_id| {
             routing.mutate_mdata_entries(dst, name, tag, actions.clone(), msg_id, requester)
@@ -471,6 +534,18 @@ pub trait Client: Clone + 'static {
         let inner = self.inner();
         inner.borrow_mut().routing.set_simulate_timeout(enabled);
     }
+
+    #[cfg(
+        any(
+            all(test, feature = "use-mock-routing"),
+            all(feature = "testing", feature = "use-mock-routing")
+        )
+    )]
+    #[doc(hidden)]
+    fn set_simulated_latency(&self, latency: Duration) {
+        let inner = self.inner();
+        inner.borrow_mut().routing.set_simulated_latency(latency);
+    }
 }
 
 /// Struct containing fields expected by the `Client` trait. Implementers of `Client` should be
@@ -481,6 +556,7 @@ pub struct ClientInner<C: Client, T> {
     routing: Routing,
     hooks: HashMap<MessageId, Complete<CoreEvent>>,
     cache: LruCache<XorName, ImmutableData>,
+    disk_cache: Option<DiskCache>,
     timeout: Duration,
     joiner: Joiner,
     core_tx: CoreMsgTx<C, T>,
@@ -504,6 +580,7 @@ impl<C: Client, T> ClientInner<C, T> {
             routing,
             hooks,
             cache,
+            disk_cache: None,
             timeout,
             joiner,
             core_tx,
@@ -6,6 +6,7 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::diff::{self, Change};
 use super::Client;
 use crate::errors::CoreError;
 use crate::event_loop::CoreFuture;
@@ -39,6 +40,9 @@ pub fn put_mdata(client: &impl Client, data: MutableData) -> Box<CoreFuture<()>>
 }
 
 /// Mutates mutable data entries and tries to recover from errors.
+///
+/// Conflicting entry versions are fixed up, so concurrent changes to the same entries are
+/// overwritten. Use `transaction::run` to apply changes based on the current values instead.
 pub fn mutate_mdata_entries(
     client: &impl Client,
     name: XorName,
@@ -184,18 +188,18 @@ fn update_mdata_entries(
     current_entries: &BTreeMap<Vec<u8>, Value>,
     desired_entries: BTreeMap<Vec<u8>, Value>,
 ) -> Box<CoreFuture<()>> {
-    let actions = desired_entries
+    let actions = diff::diff(current_entries, &desired_entries)
         .into_iter()
-        .filter_map(|(key, value)| {
-            if let Some(current_value) = current_entries.get(&key) {
-                if current_value.entry_version <= value.entry_version {
-                    Some((key, EntryAction::Update(value)))
+        .filter_map(|(key, change)| match change {
+            Change::Added(value) => Some((key, EntryAction::Ins(value))),
+            Change::Changed { old, new } => {
+                if old.entry_version <= new.entry_version {
+                    Some((key, EntryAction::Update(new)))
                 } else {
                     None
                 }
-            } else {
-                Some((key, EntryAction::Ins(value)))
             }
+            Change::Removed(_) => None,
         }).collect();
 
     mutate_mdata_entries(client, name, tag, actions)
@@ -209,14 +213,19 @@ fn update_mdata_permissions(
     desired_permissions: BTreeMap<User, PermissionSet>,
     version: u64,
 ) -> Box<CoreFuture<()>> {
-    let permissions: Vec<_> = desired_permissions
+    let permissions: Vec<_> = diff::diff_permissions(current_permissions, &desired_permissions)
         .into_iter()
-        .map(|(user, desired_set)| {
-            if let Some(current_set) = current_permissions.get(&user) {
-                (user, union_permission_sets(*current_set, desired_set))
-            } else {
-                (user, desired_set)
+        .filter_map(|(user, change)| match change {
+            Change::Added(desired_set) => Some((user, desired_set)),
+            Change::Changed { old, new } => {
+                let set = union_permission_sets(old, new);
+                if set == old {
+                    None
+                } else {
+                    Some((user, set))
+                }
             }
+            Change::Removed(_) => None,
         }).collect();
 
     let state = (client.clone(), permissions, version);
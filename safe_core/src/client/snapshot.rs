@@ -0,0 +1,346 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A snapshot is a copy of a `MutableData` fetched in a single request, so it is consistent. The
+//! entries are kept as they are stored on the network, so the snapshot of a private `MutableData`
+//! stays encrypted.
+//!
+//! The snapshot file starts with the `MAGIC` bytes, followed by the little endian `u32` format
+//! version and the serialised `Snapshot`. Files of newer format versions are rejected.
+
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::ipc::resp::METADATA_KEY;
+use crate::utils::FutureExt;
+use chrono::{DateTime, Utc};
+use futures::Future;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use routing::{MutableData, PermissionSet, User, Value, XorName};
+use rust_sodium::crypto::sign;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Bytes every snapshot file starts with.
+pub const MAGIC: &[u8; 8] = b"SAFEMDSN";
+/// Format version of the snapshot files written by this version of the library.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Copy of a `MutableData`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Snapshot {
+    /// Name of the data.
+    pub name: XorName,
+    /// Type tag of the data.
+    pub tag: u64,
+    /// Version of the data at the time the snapshot was taken.
+    pub version: u64,
+    /// Owners of the data.
+    pub owners: BTreeSet<sign::PublicKey>,
+    /// Permissions of the data.
+    pub permissions: BTreeMap<User, PermissionSet>,
+    /// Entries of the data, encrypted as they are stored on the network.
+    pub entries: BTreeMap<Vec<u8>, Value>,
+    /// Time the snapshot was taken.
+    pub taken_at: DateTime<Utc>,
+}
+
+impl Snapshot {
+    /// Take a snapshot of the `MutableData` with the given name and type tag.
+    pub fn take(client: &impl Client, name: XorName, tag: u64) -> Box<CoreFuture<Snapshot>> {
+        trace!("Taking snapshot of {:?}", name);
+
+        client
+            .get_mdata(name, tag)
+            .map(|data| Snapshot::from_mdata(&data))
+            .into_box()
+    }
+
+    /// Create a snapshot of the `MutableData`.
+    pub fn from_mdata(data: &MutableData) -> Self {
+        Snapshot {
+            name: *data.name(),
+            tag: data.tag(),
+            version: data.version(),
+            owners: data.owners().clone(),
+            permissions: data.permissions().clone(),
+            entries: data.entries().clone(),
+            taken_at: Utc::now(),
+        }
+    }
+
+    /// Returns a copy of the snapshot with the entries decrypted using `from` and encrypted again
+    /// using `to`, which also provides the name and type tag of the copy.
+    ///
+    /// The metadata entry isn't encrypted and is carried over unchanged. Deleted entries keep
+    /// their empty content and version, only their keys are re-encrypted.
+    pub fn reencrypt(&self, from: &MDataInfo, to: &MDataInfo) -> Result<Self, CoreError> {
+        let mut entries = BTreeMap::new();
+        for (key, value) in &self.entries {
+            if key == METADATA_KEY {
+                let _ = entries.insert(key.clone(), value.clone());
+                continue;
+            }
+
+            let key = to.enc_entry_key(&from.decrypt(key)?)?;
+            let value = if value.content.is_empty() {
+                value.clone()
+            } else {
+                Value {
+                    content: to.enc_entry_value(&from.decrypt(&value.content)?)?,
+                    entry_version: value.entry_version,
+                }
+            };
+            let _ = entries.insert(key, value);
+        }
+
+        Ok(Snapshot {
+            name: to.name,
+            tag: to.type_tag,
+            entries,
+            ..self.clone()
+        })
+    }
+
+    /// Store the snapshot on the network as a new `MutableData` owned by the client, with the
+    /// permissions and entries of the snapshot. The version of the new data starts from zero.
+    pub fn restore(&self, client: &impl Client) -> Box<CoreFuture<()>> {
+        trace!("Restoring snapshot of {:?}", self.name);
+
+        let owner = fry!(client
+            .public_signing_key()
+            .ok_or(CoreError::OperationForbidden));
+        let data = fry!(MutableData::new(
+            self.name,
+            self.tag,
+            self.permissions.clone(),
+            self.entries.clone(),
+            btree_set![owner],
+        ));
+
+        client.put_mdata(data)
+    }
+
+    /// Write the snapshot in the snapshot file format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), CoreError> {
+        let content = serialise(self)?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&u32_to_le_bytes(FORMAT_VERSION))?;
+        writer.write_all(&content)?;
+        Ok(())
+    }
+
+    /// Read a snapshot in the snapshot file format.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, CoreError> {
+        let mut magic = [0; 8];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| CoreError::InvalidSnapshot("Not a snapshot file".to_owned()))?;
+        if magic != *MAGIC {
+            return Err(CoreError::InvalidSnapshot("Not a snapshot file".to_owned()));
+        }
+
+        let mut version = [0; 4];
+        reader
+            .read_exact(&mut version)
+            .map_err(|_| CoreError::InvalidSnapshot("Missing format version".to_owned()))?;
+        let version = u32_from_le_bytes(version);
+        if version > FORMAT_VERSION {
+            return Err(CoreError::InvalidSnapshot(format!(
+                "Unsupported format version {}",
+                version
+            )));
+        }
+
+        let mut content = Vec::new();
+        let _ = reader.read_to_end(&mut content)?;
+        deserialise(&content)
+            .map_err(|error| CoreError::InvalidSnapshot(format!("Malformed content: {}", error)))
+    }
+
+    /// Write the snapshot to the file at `path`, replacing it if it exists.
+    pub fn export<P: AsRef<Path>>(&self, path: P) -> Result<(), CoreError> {
+        let mut file = File::create(path)?;
+        self.write_to(&mut file)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Read the snapshot from the file at `path`.
+    pub fn import<P: AsRef<Path>>(path: P) -> Result<Self, CoreError> {
+        let mut file = File::open(path)?;
+        Snapshot::read_from(&mut file)
+    }
+}
+
+fn u32_to_le_bytes(value: u32) -> [u8; 4] {
+    [
+        value as u8,
+        (value >> 8) as u8,
+        (value >> 16) as u8,
+        (value >> 24) as u8,
+    ]
+}
+
+fn u32_from_le_bytes(bytes: [u8; 4]) -> u32 {
+    u32::from(bytes[0])
+        | u32::from(bytes[1]) << 8
+        | u32::from(bytes[2]) << 16
+        | u32::from(bytes[3]) << 24
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mdata_info;
+    use crate::utils::test_utils::random_client;
+    use crate::DIR_TAG;
+    use rand;
+    use routing::Action;
+    use std::env;
+    use std::fs;
+
+    // Test taking a snapshot, exporting and importing it, and restoring it re-encrypted.
+    #[test]
+    fn export_import_restore() {
+        let path = env::temp_dir().join(format!("safe_core_snapshot_{}", rand::random::<u64>()));
+        let path2 = path.clone();
+
+        let info = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let new_info = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let info2 = info.clone();
+        let new_info2 = new_info.clone();
+
+        let entries = btree_map![
+            b"key".to_vec() => Value {
+                content: b"value".to_vec(),
+                entry_version: 3,
+            }
+        ];
+        let entries2 = entries.clone();
+
+        random_client(move |client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+
+            let permissions = btree_map![
+                User::Anyone => PermissionSet::new().allow(Action::Insert)
+            ];
+            let owners = btree_set![unwrap!(client.public_signing_key())];
+            let data = unwrap!(MutableData::new(
+                info.name,
+                info.type_tag,
+                permissions,
+                unwrap!(mdata_info::encrypt_entries(&info, &entries)),
+                owners,
+            ));
+
+            client
+                .put_mdata(data)
+                .and_then(move |()| Snapshot::take(&client2, info.name, info.type_tag))
+                .and_then(move |snapshot| {
+                    unwrap!(snapshot.export(&path));
+                    let imported = unwrap!(Snapshot::import(&path));
+                    assert_eq!(imported, snapshot);
+
+                    let reencrypted = unwrap!(imported.reencrypt(&info2, &new_info));
+                    assert_eq!(reencrypted.name, new_info.name);
+                    reencrypted
+                        .restore(&client3)
+                        .map(move |()| reencrypted.permissions)
+                }).and_then(move |permissions| {
+                    client4
+                        .get_mdata(new_info2.name, new_info2.type_tag)
+                        .map(move |data| {
+                            assert_eq!(*data.permissions(), permissions);
+                            let entries =
+                                unwrap!(mdata_info::decrypt_entries(&new_info2, data.entries()));
+                            assert_eq!(entries, entries2);
+                        })
+                })
+        });
+
+        unwrap!(fs::remove_file(&path2));
+    }
+
+    // Test that deleted entries and the metadata entry survive re-encryption.
+    #[test]
+    fn reencrypt_hidden_entries() {
+        let info = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let new_info = unwrap!(MDataInfo::random_private(DIR_TAG));
+
+        let live = Value {
+            content: unwrap!(info.enc_entry_value(b"value")),
+            entry_version: 0,
+        };
+        let deleted = Value {
+            content: Vec::new(),
+            entry_version: 2,
+        };
+        let metadata = Value {
+            content: b"metadata".to_vec(),
+            entry_version: 1,
+        };
+        let snapshot = Snapshot {
+            name: info.name,
+            tag: info.type_tag,
+            version: 3,
+            owners: BTreeSet::new(),
+            permissions: BTreeMap::new(),
+            entries: btree_map![
+                unwrap!(info.enc_entry_key(b"live")) => live,
+                unwrap!(info.enc_entry_key(b"deleted")) => deleted.clone(),
+                METADATA_KEY.to_vec() => metadata.clone()
+            ],
+            taken_at: Utc::now(),
+        };
+
+        let reencrypted = unwrap!(snapshot.reencrypt(&info, &new_info));
+        let entries = reencrypted.entries;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[METADATA_KEY], metadata);
+        assert_eq!(entries[&unwrap!(new_info.enc_entry_key(b"deleted"))], deleted);
+
+        let live = &entries[&unwrap!(new_info.enc_entry_key(b"live"))];
+        assert_eq!(unwrap!(new_info.decrypt(&live.content)), b"value".to_vec());
+        assert_eq!(live.entry_version, 0);
+    }
+
+    // Test that files which aren't snapshots, or are of a newer format version, are rejected.
+    #[test]
+    fn invalid_file() {
+        let mut content = Vec::new();
+        let snapshot = Snapshot {
+            name: rand::random(),
+            tag: DIR_TAG,
+            version: 0,
+            owners: BTreeSet::new(),
+            permissions: BTreeMap::new(),
+            entries: BTreeMap::new(),
+            taken_at: Utc::now(),
+        };
+        unwrap!(snapshot.write_to(&mut content));
+        assert_eq!(unwrap!(Snapshot::read_from(&mut &content[..])), snapshot);
+
+        let mut newer = content.clone();
+        newer[MAGIC.len()] = FORMAT_VERSION as u8 + 1;
+        let mut truncated = content.clone();
+        truncated.truncate(content.len() - 1);
+
+        for invalid in vec![b"not a snapshot".to_vec(), newer, truncated] {
+            match Snapshot::read_from(&mut &invalid[..]) {
+                Err(CoreError::InvalidSnapshot(_)) => (),
+                res => panic!("Unexpected result: {:?}", res),
+            }
+        }
+    }
+}
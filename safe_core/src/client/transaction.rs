@@ -0,0 +1,325 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A transaction reads the current values of a set of entries, passes them to a closure which
+//! decides what to write, and submits the writes conditioned on the entry versions it has read.
+//! If any of those entries has been changed in the meantime, the network rejects the whole
+//! mutation and the transaction starts over: the entries are read again and the closure is run on
+//! their new values. Concurrent changes to the written entries are therefore never overwritten
+//! unknowingly; once the attempts run out, the conflict is returned as an error.
+//!
+//! Entries which are only read are not protected this way: the network can only condition a
+//! mutation on the versions of the entries it changes, so such an entry may change after it has
+//! been read without the transaction noticing. A transaction which depends on an entry staying
+//! unchanged has to write it too.
+
+use crate::client::Client;
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::Future;
+use routing::{ClientError, EntryAction, EntryError, Value, XorName};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+/// Default number of times a transaction is attempted before giving up.
+pub const DEFAULT_MAX_ATTEMPTS: usize = 10;
+
+/// Entries written by a transaction: `Some` content inserts or updates the entry, `None` deletes
+/// it.
+pub type Writes = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Run a transaction on the entries of the `MutableData` with the given `keys`, attempting it up
+/// to `DEFAULT_MAX_ATTEMPTS` times. See `run_with_attempts`.
+pub fn run<F>(
+    client: &impl Client,
+    name: XorName,
+    tag: u64,
+    keys: BTreeSet<Vec<u8>>,
+    f: F,
+) -> Box<CoreFuture<Writes>>
+where
+    F: FnMut(&BTreeMap<Vec<u8>, Value>) -> Result<Writes, CoreError> + 'static,
+{
+    run_with_attempts(client, name, tag, keys, DEFAULT_MAX_ATTEMPTS, f)
+}
+
+/// Run a transaction on the entries of the `MutableData` with the given `keys`.
+///
+/// `f` receives the current values of the entries which exist and returns the entries to write.
+/// Deleted entries and entries not among `keys` are treated as if they didn't exist. If `f` fails,
+/// the transaction is aborted with its error. When the writes conflict with concurrent changes,
+/// `f` is run again on the new values, up to `max_attempts` times in total, after which the
+/// `ClientError::InvalidEntryActions` error is returned. Returns the writes which were applied.
+///
+/// Only the written entries are checked for concurrent changes, see the module documentation.
+///
+/// Other errors, including timeouts, are not retried, as the mutation might have been applied.
+pub fn run_with_attempts<F>(
+    client: &impl Client,
+    name: XorName,
+    tag: u64,
+    keys: BTreeSet<Vec<u8>>,
+    max_attempts: usize,
+    f: F,
+) -> Box<CoreFuture<Writes>>
+where
+    F: FnMut(&BTreeMap<Vec<u8>, Value>) -> Result<Writes, CoreError> + 'static,
+{
+    let client = client.clone();
+    let f = Rc::new(RefCell::new(f));
+
+    future::loop_fn(1, move |attempt| {
+        let client2 = client.clone();
+        let f = Rc::clone(&f);
+
+        read(&client, name, tag, &keys)
+            .and_then(move |current| {
+                let live = current
+                    .iter()
+                    .filter(|&(_, value)| !value.content.is_empty())
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                let writes = (&mut *f.borrow_mut())(&live)?;
+                Ok((current, writes))
+            }).and_then(move |(current, writes)| {
+                let actions = build_actions(&current, &writes);
+                if actions.is_empty() {
+                    return ok!(Loop::Break(writes));
+                }
+
+                client2
+                    .mutate_mdata_entries(name, tag, actions)
+                    .map(move |()| Loop::Break(writes))
+                    .or_else(move |error| match error {
+                        CoreError::RoutingClientError(ClientError::InvalidEntryActions(
+                            ref errors,
+                        ))
+                            if attempt < max_attempts && is_conflict(errors) =>
+                        {
+                            trace!("Transaction attempt {} conflicted, retrying.", attempt);
+                            Ok(Loop::Continue(attempt + 1))
+                        }
+                        error => Err(error),
+                    }).into_box()
+            })
+    }).into_box()
+}
+
+// Read the current values of the entries, leaving out the ones which don't exist. Deleted entries
+// are kept, as writing them has to be conditioned on their versions too.
+fn read(
+    client: &impl Client,
+    name: XorName,
+    tag: u64,
+    keys: &BTreeSet<Vec<u8>>,
+) -> Box<CoreFuture<BTreeMap<Vec<u8>, Value>>> {
+    let reads = keys.iter().map(|key| {
+        let key2 = key.clone();
+
+        client
+            .get_mdata_value(name, tag, key.clone())
+            .map(move |value| Some((key2, value)))
+            .or_else(|error| match error {
+                CoreError::RoutingClientError(ClientError::NoSuchEntry) => Ok(None),
+                error => Err(error),
+            })
+    });
+
+    future::join_all(reads.collect::<Vec<_>>())
+        .map(|entries| entries.into_iter().filter_map(|entry| entry).collect())
+        .into_box()
+}
+
+// Entry actions applying the writes, conditioned on the versions of the entries which were read.
+// Deleted entries are brought back by updating them, and deleting them again is a no-op.
+fn build_actions(
+    current: &BTreeMap<Vec<u8>, Value>,
+    writes: &Writes,
+) -> BTreeMap<Vec<u8>, EntryAction> {
+    writes
+        .iter()
+        .filter_map(|(key, content)| {
+            let action = match (current.get(key), content) {
+                (Some(value), &Some(ref content)) => EntryAction::Update(Value {
+                    content: content.clone(),
+                    entry_version: value.entry_version + 1,
+                }),
+                (None, &Some(ref content)) => EntryAction::Ins(Value {
+                    content: content.clone(),
+                    entry_version: 0,
+                }),
+                (Some(value), &None) if !value.content.is_empty() => {
+                    EntryAction::Del(value.entry_version + 1)
+                }
+                (_, &None) => return None,
+            };
+            Some((key.clone(), action))
+        }).collect()
+}
+
+// Returns true if the entries have been changed since they were read.
+fn is_conflict(errors: &BTreeMap<Vec<u8>, EntryError>) -> bool {
+    errors.values().all(|error| match *error {
+        EntryError::NoSuchEntry | EntryError::EntryExists(_) | EntryError::InvalidSuccessor(_) => {
+            true
+        }
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use rand;
+    use routing::{Action, MutableData, PermissionSet, User};
+
+    const COUNTER: &[u8] = b"counter";
+
+    // Increment the counter stored as a decimal string, starting from zero if it doesn't exist.
+    fn increment(current: &BTreeMap<Vec<u8>, Value>) -> Result<Writes, CoreError> {
+        let counter = match current.get(COUNTER) {
+            Some(value) => unwrap!(unwrap!(String::from_utf8(value.content.clone())).parse()),
+            None => 0u64,
+        };
+        let content = (counter + 1).to_string().into_bytes();
+        Ok(btree_map![COUNTER.to_vec() => Some(content)])
+    }
+
+    // Test that concurrent transactions don't overwrite each other, but are retried instead.
+    #[test]
+    fn concurrent_increments() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+            let client5 = client.clone();
+
+            let name: XorName = rand::random();
+            let tag = 10_000;
+            let keys = btree_set![COUNTER.to_vec()];
+            let keys2 = keys.clone();
+            let keys3 = keys.clone();
+
+            let permissions = btree_map![
+                User::Anyone => PermissionSet::new().allow(Action::Insert).allow(Action::Update)
+            ];
+            let owners = btree_set![unwrap!(client.public_signing_key())];
+            let data = unwrap!(MutableData::new(
+                name,
+                tag,
+                permissions,
+                BTreeMap::new(),
+                owners
+            ));
+
+            client
+                .put_mdata(data)
+                .and_then(move |()| {
+                    let first = run(&client2, name, tag, keys, increment);
+                    let second = run(&client3, name, tag, keys2, increment);
+                    first.join(second)
+                }).and_then(move |_| client4.get_mdata_value(name, tag, COUNTER.to_vec()))
+                .and_then(move |value| {
+                    assert_eq!(value.content, b"2".to_vec());
+                    assert_eq!(value.entry_version, 1);
+
+                    // With a single attempt, one of the conflicting transactions fails.
+                    let first = run_with_attempts(&client5, name, tag, keys3.clone(), 1, increment);
+                    let second = run_with_attempts(&client5, name, tag, keys3, 1, increment);
+                    first.then(Ok::<_, CoreError>).join(second.then(Ok))
+                }).map(|(first, second)| {
+                    let results = vec![first, second];
+                    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+
+                    for result in results {
+                        match result {
+                            Ok(_) => (),
+                            Err(CoreError::RoutingClientError(
+                                ClientError::InvalidEntryActions(_),
+                            )) => (),
+                            Err(error) => panic!("Unexpected {:?}", error),
+                        }
+                    }
+                })
+        })
+    }
+
+    // Test that deleted entries are treated as if they didn't exist.
+    #[test]
+    fn deleted_entry() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+
+            let name: XorName = rand::random();
+            let tag = 10_000;
+            let owners = btree_set![unwrap!(client.public_signing_key())];
+            let deleted = Value {
+                content: Vec::new(),
+                entry_version: 1,
+            };
+            let data = unwrap!(MutableData::new(
+                name,
+                tag,
+                BTreeMap::new(),
+                btree_map![COUNTER.to_vec() => deleted],
+                owners
+            ));
+
+            client
+                .put_mdata(data)
+                .and_then(move |()| {
+                    run(&client2, name, tag, btree_set![COUNTER.to_vec()], |current| {
+                        assert!(current.is_empty());
+                        Ok(btree_map![COUNTER.to_vec() => None])
+                    })
+                }).and_then(move |_| {
+                    run(&client3, name, tag, btree_set![COUNTER.to_vec()], increment)
+                }).and_then(move |_| client4.get_mdata_value(name, tag, COUNTER.to_vec()))
+                .map(|value| {
+                    assert_eq!(value.content, b"1".to_vec());
+                    assert_eq!(value.entry_version, 2);
+                })
+        })
+    }
+
+    // Test that a failing closure aborts the transaction without writing anything.
+    #[test]
+    fn aborted() {
+        random_client(|client| {
+            let client2 = client.clone();
+
+            let name: XorName = rand::random();
+            let tag = 10_000;
+            let owners = btree_set![unwrap!(client.public_signing_key())];
+            let data = unwrap!(MutableData::new(
+                name,
+                tag,
+                BTreeMap::new(),
+                BTreeMap::new(),
+                owners
+            ));
+
+            client
+                .put_mdata(data)
+                .and_then(move |()| {
+                    run(&client2, name, tag, btree_set![COUNTER.to_vec()], |_| {
+                        Err(CoreError::OperationAborted)
+                    })
+                }).then(|result| match result {
+                    Err(CoreError::OperationAborted) => Ok::<_, CoreError>(()),
+                    result => panic!("Unexpected {:?}", result),
+                })
+        })
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{Actor, Crdt};
+use std::collections::BTreeMap;
+
+/// Counter which can be both incremented and decremented. Every actor keeps track of its own
+/// increments and decrements, and the value of the counter is the difference of their sums.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub struct PnCounter {
+    increments: BTreeMap<Actor, u64>,
+    decrements: BTreeMap<Actor, u64>,
+}
+
+impl PnCounter {
+    /// Create a counter with the value of zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Increment the counter by `amount` on behalf of `actor`.
+    pub fn increment(&mut self, actor: Actor, amount: u64) {
+        *self.increments.entry(actor).or_insert(0) += amount;
+    }
+
+    /// Decrement the counter by `amount` on behalf of `actor`.
+    pub fn decrement(&mut self, actor: Actor, amount: u64) {
+        *self.decrements.entry(actor).or_insert(0) += amount;
+    }
+
+    /// Returns the value of the counter.
+    pub fn value(&self) -> i64 {
+        let increments: u64 = self.increments.values().sum();
+        let decrements: u64 = self.decrements.values().sum();
+        increments as i64 - decrements as i64
+    }
+}
+
+impl Crdt for PnCounter {
+    fn merge(&mut self, other: &Self) {
+        merge_max(&mut self.increments, &other.increments);
+        merge_max(&mut self.decrements, &other.decrements);
+    }
+}
+
+fn merge_max(own: &mut BTreeMap<Actor, u64>, other: &BTreeMap<Actor, u64>) {
+    for (actor, count) in other {
+        let own = own.entry(*actor).or_insert(0);
+        *own = (*own).max(*count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+
+    // Test that concurrent increments and decrements are all counted, but only once.
+    #[test]
+    fn merge() {
+        let alice: Actor = rand::random();
+        let bob: Actor = rand::random();
+
+        let mut first = PnCounter::new();
+        first.increment(alice, 5);
+
+        let mut second = first.clone();
+        first.decrement(alice, 2);
+        second.increment(bob, 1);
+        second.decrement(bob, 7);
+
+        first.merge(&second);
+        first.merge(&second);
+        second.merge(&first);
+        assert_eq!(first, second);
+        assert_eq!(first.value(), -3);
+    }
+}
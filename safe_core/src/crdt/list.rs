@@ -0,0 +1,218 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{Actor, Crdt, Dot};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Ordered list which elements can be inserted at and removed from any position.
+///
+/// Every element remembers the element it was inserted after. Elements inserted after the same
+/// one are ordered with the most recent insertion first, so concurrent insertions at the same
+/// position are never interleaved. Removed elements are kept as tombstones without their values,
+/// so the elements inserted after them keep their positions.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct List<T> {
+    elements: BTreeMap<Dot, Element<T>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+struct Element<T> {
+    // The element this one was inserted after, or `None` if inserted at the front.
+    after: Option<Dot>,
+    // `None` once the element has been removed.
+    value: Option<T>,
+}
+
+impl<T> List<T> {
+    /// Create an empty list.
+    pub fn new() -> Self {
+        List {
+            elements: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.elements
+            .values()
+            .filter(|element| element.value.is_some())
+            .count()
+    }
+
+    /// Returns true if the list has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the element at `index`.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.iter().nth(index)
+    }
+
+    /// Iterate over the elements in order.
+    pub fn iter<'a>(&'a self) -> Box<Iterator<Item = &'a T> + 'a> {
+        Box::new(
+            self.order()
+                .into_iter()
+                .filter_map(move |dot| self.elements[&dot].value.as_ref()),
+        )
+    }
+
+    /// Insert the element at `index` on behalf of `actor`, shifting the following elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length of the list.
+    pub fn insert(&mut self, actor: Actor, index: usize, value: T) {
+        let after = if index == 0 {
+            None
+        } else {
+            match self.visible().nth(index - 1) {
+                Some(dot) => Some(dot),
+                None => panic!("Insertion index {} out of bounds", index),
+            }
+        };
+
+        // Newer insertions are ordered first, so the counter must exceed all the existing ones.
+        let latest = self.elements.keys().map(|dot| dot.counter).max();
+        let dot = Dot {
+            counter: latest.unwrap_or(0) + 1,
+            actor,
+        };
+        let _ = self.elements.insert(
+            dot,
+            Element {
+                after,
+                value: Some(value),
+            },
+        );
+    }
+
+    /// Append the element to the end of the list on behalf of `actor`.
+    pub fn push(&mut self, actor: Actor, value: T) {
+        let len = self.len();
+        self.insert(actor, len, value)
+    }
+
+    /// Remove the element at `index` and return it, or `None` if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let dot = self.visible().nth(index)?;
+        self.elements
+            .get_mut(&dot)
+            .and_then(|element| element.value.take())
+    }
+
+    // Dots of the elements which haven't been removed, in order.
+    fn visible<'a>(&'a self) -> impl Iterator<Item = Dot> + 'a {
+        self.order()
+            .into_iter()
+            .filter(move |dot| self.elements[dot].value.is_some())
+    }
+
+    // Dots of all the elements, including the removed ones, in order. Every element is followed
+    // by the elements inserted after it, the most recent ones first.
+    fn order(&self) -> Vec<Dot> {
+        let mut children: BTreeMap<Option<Dot>, Vec<Dot>> = BTreeMap::new();
+        for (dot, element) in &self.elements {
+            children
+                .entry(element.after)
+                .or_insert_with(Vec::new)
+                .push(*dot);
+        }
+
+        let mut order = Vec::with_capacity(self.elements.len());
+        // Dots are iterated in ascending order, so the last child is the most recent one.
+        let mut stack = children.remove(&None).unwrap_or_else(Vec::new);
+        while let Some(dot) = stack.pop() {
+            order.push(dot);
+            if let Some(after) = children.remove(&Some(dot)) {
+                stack.extend(after);
+            }
+        }
+        order
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for List<T>
+where
+    T: Clone + Serialize + DeserializeOwned + 'static,
+{
+    fn merge(&mut self, other: &Self) {
+        for (dot, element) in &other.elements {
+            let own = self.elements.entry(*dot).or_insert_with(|| element.clone());
+            if element.value.is_none() {
+                own.value = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+
+    fn to_vec(list: &List<char>) -> Vec<char> {
+        list.iter().cloned().collect()
+    }
+
+    // Test inserting and removing elements.
+    #[test]
+    fn insert_remove() {
+        let alice: Actor = rand::random();
+
+        let mut list = List::new();
+        list.push(alice, 'a');
+        list.push(alice, 'c');
+        list.insert(alice, 1, 'b');
+        list.insert(alice, 0, '_');
+        assert_eq!(to_vec(&list), vec!['_', 'a', 'b', 'c']);
+
+        assert_eq!(list.remove(0), Some('_'));
+        assert_eq!(list.remove(3), None);
+        list.insert(alice, 1, 'x');
+        assert_eq!(to_vec(&list), vec!['a', 'x', 'b', 'c']);
+        assert_eq!(list.get(3), Some(&'c'));
+        assert_eq!(list.len(), 4);
+    }
+
+    // Test that concurrent edits converge without interleaving the inserted elements.
+    #[test]
+    fn merge() {
+        let alice: Actor = rand::random();
+        let bob: Actor = rand::random();
+
+        let mut first = List::new();
+        first.push(alice, 'a');
+        first.push(alice, 'b');
+
+        let mut second = first.clone();
+        first.insert(alice, 1, 'x');
+        first.insert(alice, 2, 'y');
+        second.insert(bob, 1, 'z');
+        let _ = second.remove(0);
+
+        let mut merged = first.clone();
+        merged.merge(&second);
+        second.merge(&first);
+        assert_eq!(merged, second);
+
+        let merged = to_vec(&merged);
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged[3], 'b');
+        assert!(merged == vec!['x', 'y', 'z', 'b'] || merged == vec!['z', 'x', 'y', 'b']);
+    }
+}
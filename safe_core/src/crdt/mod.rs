@@ -0,0 +1,219 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Replicated data types stored in `MutableData`.
+//!
+//! Every replica of a value, identified by its `Actor`, stores its own copy of the state in a
+//! separate entry, so replicas never write to the same entry. The entry key is the key of the
+//! value followed by a zero byte and the name of the actor. Loading a value merges the states of
+//! all the replicas, and saving one merges the state into the replica's entry, so concurrent
+//! updates are never lost and all replicas converge to the same value regardless of the order in
+//! which the updates are seen.
+//!
+//! The keys and the states are encrypted according to the `MDataInfo`, so both public and private
+//! `MutableData` can be used. Saving requires the `Insert` and `Update` permissions.
+
+mod counter;
+mod list;
+mod register;
+mod set;
+
+pub use self::counter::PnCounter;
+pub use self::list::List;
+pub use self::register::LwwRegister;
+pub use self::set::{GSet, OrSet};
+
+use crate::client::entry_pages::entry_stream;
+use crate::client::{transaction, Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use futures::{Future, Stream};
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use routing::{XorName, XOR_NAME_LEN};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Identifies a replica making updates. Every replica, e.g. every device of a user, should use
+/// its own actor and keep using it across restarts.
+pub type Actor = XorName;
+
+/// Unique identifier of an update, made of the number of updates the actor has made so far and
+/// the actor itself.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+pub struct Dot {
+    /// Counter of the updates.
+    pub counter: u64,
+    /// Actor making the update.
+    pub actor: Actor,
+}
+
+/// State of a replicated data type.
+pub trait Crdt: Clone + Default + Serialize + DeserializeOwned + 'static {
+    /// Merge the state of another replica into this one. Merging is commutative, associative and
+    /// idempotent.
+    fn merge(&mut self, other: &Self);
+}
+
+/// Load the value stored under `key`, merging the states of all its replicas. Returns the default
+/// value if no replica has stored it yet.
+pub fn load<T: Crdt>(client: &impl Client, info: &MDataInfo, key: &[u8]) -> Box<CoreFuture<T>> {
+    let prefix = replica_key_prefix(key);
+    let len = prefix.len() + XOR_NAME_LEN;
+
+    entry_stream(client, info.clone(), prefix)
+        .filter(move |&(ref key, _)| key.len() == len)
+        .fold(T::default(), |mut state, (_, value)| {
+            let other: T = deserialise(&value.content)?;
+            state.merge(&other);
+            Ok::<_, CoreError>(state)
+        }).into_box()
+}
+
+/// Merge `state` into the replica of the value under `key` stored by `actor`.
+pub fn save<T: Crdt>(
+    client: &impl Client,
+    info: &MDataInfo,
+    key: &[u8],
+    actor: Actor,
+    state: &T,
+) -> Box<CoreFuture<()>> {
+    let mut plain_key = replica_key_prefix(key);
+    plain_key.extend_from_slice(&actor.0);
+    let enc_key = fry!(info.enc_entry_key(&plain_key));
+
+    let info = info.clone();
+    let state = state.clone();
+
+    transaction::run(
+        client,
+        info.name,
+        info.type_tag,
+        btree_set![enc_key.clone()],
+        move |current| {
+            let mut merged = state.clone();
+            if let Some(value) = current.get(&enc_key) {
+                let stored: T = deserialise(&info.decrypt(&value.content)?)?;
+                merged.merge(&stored);
+            }
+            let content = info.enc_entry_value(&serialise(&merged)?)?;
+            Ok(btree_map![enc_key.clone() => Some(content)])
+        },
+    ).map(|_| ())
+    .into_box()
+}
+
+/// Load the value stored under `key`, apply `f` to it and save the result as the replica of
+/// `actor`. Returns the updated value.
+pub fn update<T, F>(
+    client: &impl Client,
+    info: &MDataInfo,
+    key: &[u8],
+    actor: Actor,
+    f: F,
+) -> Box<CoreFuture<T>>
+where
+    T: Crdt,
+    F: FnOnce(&mut T) + 'static,
+{
+    let client = client.clone();
+    let info = info.clone();
+    let key = key.to_vec();
+
+    load(&client, &info, &key)
+        .and_then(move |mut state: T| {
+            f(&mut state);
+            save(&client, &info, &key, actor, &state).map(move |()| state)
+        }).into_box()
+}
+
+fn replica_key_prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = key.to_vec();
+    prefix.push(0);
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use crate::DIR_TAG;
+    use rand;
+    use routing::{Action, MutableData, PermissionSet, User};
+    use std::collections::BTreeMap;
+
+    fn put_mdata(client: &impl Client, info: &MDataInfo) -> Box<CoreFuture<()>> {
+        let owners = btree_set![unwrap!(client.public_signing_key())];
+        let permissions = btree_map![
+            User::Anyone => PermissionSet::new().allow(Action::Insert).allow(Action::Update)
+        ];
+        let data = unwrap!(MutableData::new(
+            info.name,
+            info.type_tag,
+            permissions,
+            BTreeMap::new(),
+            owners,
+        ));
+        client.put_mdata(data)
+    }
+
+    // Test concurrent updates of replicated values by several actors, in both public and private
+    // `MutableData`.
+    #[test]
+    fn concurrent_updates() {
+        let infos = vec![
+            unwrap!(MDataInfo::random_public(DIR_TAG)),
+            unwrap!(MDataInfo::random_private(DIR_TAG)),
+        ];
+
+        for info in infos {
+            random_client(move |client| {
+                let client2 = client.clone();
+                let client3 = client.clone();
+                let client4 = client.clone();
+                let info2 = info.clone();
+                let info3 = info.clone();
+                let info4 = info.clone();
+
+                let alice: Actor = rand::random();
+                let bob: Actor = rand::random();
+
+                put_mdata(client, &info)
+                    .and_then(move |()| {
+                        let (c, i) = (&client2, &info2);
+                        let first = update(c, i, b"set", alice, move |set: &mut OrSet<_>| {
+                            set.insert(alice, "apple".to_owned())
+                        });
+                        let second = update(c, i, b"set", bob, move |set: &mut OrSet<_>| {
+                            set.insert(bob, "banana".to_owned())
+                        });
+                        let third = update(c, i, b"counter", alice, move |n: &mut PnCounter| {
+                            n.increment(alice, 3)
+                        });
+                        let fourth = update(c, i, b"counter", bob, move |n: &mut PnCounter| {
+                            n.decrement(bob, 1)
+                        });
+                        first.join4(second, third, fourth)
+                    }).and_then(move |_| {
+                        let set = load::<OrSet<String>>(&client3, &info3, b"set");
+                        let counter = load::<PnCounter>(&client3, &info3, b"counter");
+                        set.join(counter)
+                    }).and_then(move |(set, counter)| {
+                        assert_eq!(
+                            set.iter().cloned().collect::<Vec<_>>(),
+                            vec!["apple".to_owned(), "banana".to_owned()]
+                        );
+                        assert_eq!(counter.value(), 2);
+
+                        // Values which were never saved are empty.
+                        load::<GSet<u64>>(&client4, &info4, b"missing")
+                    }).map(|set| assert!(set.is_empty()))
+            })
+        }
+    }
+}
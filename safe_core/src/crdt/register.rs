@@ -0,0 +1,115 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{Actor, Crdt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cmp;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Register holding a single value, where the last write wins.
+///
+/// Writes are ordered by their timestamps, in milliseconds since the Unix epoch, and concurrent
+/// writes with the same timestamp by the actors which made them. A write is always timestamped
+/// after the one it replaces, so writes by the same replica are ordered even if its clock goes
+/// back.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct LwwRegister<T> {
+    value: Option<T>,
+    timestamp: u64,
+    actor: Option<Actor>,
+}
+
+impl<T> LwwRegister<T> {
+    /// Create an empty register.
+    pub fn new() -> Self {
+        LwwRegister {
+            value: None,
+            timestamp: 0,
+            actor: None,
+        }
+    }
+
+    /// Returns the value of the register, or `None` if it has never been set.
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    /// Set the value of the register on behalf of `actor`, using the current time.
+    pub fn set(&mut self, actor: Actor, value: T) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() * 1000 + u64::from(duration.subsec_millis()))
+            .unwrap_or(0);
+        self.set_with_timestamp(actor, value, now)
+    }
+
+    /// Set the value of the register on behalf of `actor`, using the given timestamp.
+    pub fn set_with_timestamp(&mut self, actor: Actor, value: T, timestamp: u64) {
+        self.value = Some(value);
+        self.timestamp = cmp::max(timestamp, self.timestamp + 1);
+        self.actor = Some(actor);
+    }
+}
+
+impl<T> Default for LwwRegister<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for LwwRegister<T>
+where
+    T: Clone + Serialize + DeserializeOwned + 'static,
+{
+    fn merge(&mut self, other: &Self) {
+        if (other.timestamp, other.actor) > (self.timestamp, self.actor) {
+            *self = other.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+
+    // Test that the latest write wins, with ties broken by the actors.
+    #[test]
+    fn merge() {
+        let alice: Actor = rand::random();
+        let bob: Actor = rand::random();
+
+        let mut first = LwwRegister::new();
+        assert_eq!(first.get(), None);
+        first.set_with_timestamp(alice, 1, 10);
+
+        let mut second = first.clone();
+        second.set_with_timestamp(bob, 2, 20);
+        first.set_with_timestamp(alice, 3, 15);
+
+        first.merge(&second);
+        assert_eq!(first.get(), Some(&2));
+
+        // A write is ordered after the value it replaces, even with an older timestamp.
+        first.set_with_timestamp(alice, 4, 5);
+        second.merge(&first);
+        assert_eq!(second.get(), Some(&4));
+
+        // Concurrent writes with the same timestamp converge.
+        let mut first = LwwRegister::new();
+        let mut second = LwwRegister::new();
+        first.set_with_timestamp(alice, 'a', 100);
+        second.set_with_timestamp(bob, 'b', 100);
+
+        let mut merged = first.clone();
+        merged.merge(&second);
+        second.merge(&first);
+        assert_eq!(merged, second);
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{Actor, Crdt, Dot};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{btree_map, btree_set, BTreeMap, BTreeSet};
+
+/// Set which elements can only be added, never removed.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct GSet<T: Ord> {
+    elements: BTreeSet<T>,
+}
+
+impl<T: Ord> GSet<T> {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        GSet {
+            elements: BTreeSet::new(),
+        }
+    }
+
+    /// Add the element to the set.
+    pub fn insert(&mut self, element: T) {
+        let _ = self.elements.insert(element);
+    }
+
+    /// Returns true if the set contains the element.
+    pub fn contains(&self, element: &T) -> bool {
+        self.elements.contains(element)
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns true if the set has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Iterate over the elements in ascending order.
+    pub fn iter(&self) -> btree_set::Iter<T> {
+        self.elements.iter()
+    }
+}
+
+impl<T: Ord> Default for GSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for GSet<T>
+where
+    T: Clone + Ord + Serialize + DeserializeOwned + 'static,
+{
+    fn merge(&mut self, other: &Self) {
+        self.elements.extend(other.elements.iter().cloned());
+    }
+}
+
+/// Observed-remove set: elements can be added and removed again. When an element is added and
+/// removed concurrently, the addition wins, as a replica can only remove the additions it has
+/// seen.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct OrSet<T: Ord> {
+    // Elements in the set, along with the additions which haven't been removed yet.
+    elements: BTreeMap<T, BTreeSet<Dot>>,
+    // Additions which have been removed.
+    removed: BTreeSet<Dot>,
+    // Number of additions made by every actor.
+    counters: BTreeMap<Actor, u64>,
+}
+
+impl<T: Ord> OrSet<T> {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        OrSet {
+            elements: BTreeMap::new(),
+            removed: BTreeSet::new(),
+            counters: BTreeMap::new(),
+        }
+    }
+
+    /// Add the element to the set on behalf of `actor`.
+    pub fn insert(&mut self, actor: Actor, element: T) {
+        let counter = self.counters.entry(actor).or_insert(0);
+        *counter += 1;
+        let dot = Dot {
+            counter: *counter,
+            actor,
+        };
+
+        let _ = self
+            .elements
+            .entry(element)
+            .or_insert_with(BTreeSet::new)
+            .insert(dot);
+    }
+
+    /// Remove the element from the set. Returns true if it was in the set.
+    pub fn remove(&mut self, element: &T) -> bool {
+        match self.elements.remove(element) {
+            Some(dots) => {
+                self.removed.extend(dots);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if the set contains the element.
+    pub fn contains(&self, element: &T) -> bool {
+        self.elements.contains_key(element)
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns true if the set has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Iterate over the elements in ascending order.
+    pub fn iter(&self) -> btree_map::Keys<T, BTreeSet<Dot>> {
+        self.elements.keys()
+    }
+}
+
+impl<T: Ord> Default for OrSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for OrSet<T>
+where
+    T: Clone + Ord + Serialize + DeserializeOwned + 'static,
+{
+    fn merge(&mut self, other: &Self) {
+        self.removed.extend(other.removed.iter().cloned());
+
+        for (element, dots) in &other.elements {
+            self.elements
+                .entry(element.clone())
+                .or_insert_with(BTreeSet::new)
+                .extend(dots.iter().cloned());
+        }
+
+        let removed = &self.removed;
+        for dots in self.elements.values_mut() {
+            *dots = dots.difference(removed).cloned().collect();
+        }
+        self.elements.retain(|_, dots| !dots.is_empty());
+
+        for (actor, counter) in &other.counters {
+            let own = self.counters.entry(*actor).or_insert(0);
+            *own = (*own).max(*counter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+
+    // Test merging grow-only sets.
+    #[test]
+    fn gset_merge() {
+        let mut first = GSet::new();
+        first.insert(1);
+        first.insert(2);
+
+        let mut second = GSet::new();
+        second.insert(2);
+        second.insert(3);
+
+        first.merge(&second);
+        first.merge(&second);
+        assert_eq!(first.iter().cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    // Test that concurrent additions win over removals, and that merging converges.
+    #[test]
+    fn orset_merge() {
+        let alice: Actor = rand::random();
+        let bob: Actor = rand::random();
+
+        let mut first = OrSet::new();
+        first.insert(alice, 'a');
+        first.insert(alice, 'b');
+
+        let mut second = first.clone();
+        // Bob removes 'a' and re-adds 'b' while Alice removes 'b'.
+        assert!(second.remove(&'a'));
+        second.insert(bob, 'b');
+        assert!(first.remove(&'b'));
+        assert!(!first.remove(&'c'));
+
+        let mut merged = first.clone();
+        merged.merge(&second);
+        second.merge(&first);
+        assert_eq!(merged, second);
+
+        assert!(!merged.contains(&'a'));
+        assert!(merged.contains(&'b'));
+        assert_eq!(merged.len(), 1);
+
+        // Removed elements can be added again.
+        merged.insert(alice, 'a');
+        assert!(merged.contains(&'a'));
+    }
+}
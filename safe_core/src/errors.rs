@@ -64,6 +64,10 @@ pub enum CoreError {
     ConfigError(config_file_handler::Error),
     /// Io error.
     IoError(io::Error),
+    /// The link couldn't be parsed, or doesn't match the data it refers to.
+    InvalidLink(String),
+    /// The snapshot file is malformed or of an unsupported format version.
+    InvalidSnapshot(String),
 }
 
 impl<'a> From<&'a str> for CoreError {
@@ -191,6 +195,12 @@ impl Debug for CoreError {
                 write!(formatter, "CoreError::ConfigError -> {:?}", error)
             }
             CoreError::IoError(ref error) => write!(formatter, "CoreError::IoError -> {:?}", error),
+            CoreError::InvalidLink(ref error) => {
+                write!(formatter, "CoreError::InvalidLink -> {:?}", error)
+            }
+            CoreError::InvalidSnapshot(ref error) => {
+                write!(formatter, "CoreError::InvalidSnapshot -> {:?}", error)
+            }
         }
     }
 }
@@ -250,6 +260,10 @@ impl Display for CoreError {
             CoreError::RequestTimeout => write!(formatter, "CoreError::RequestTimeout"),
             CoreError::ConfigError(ref error) => write!(formatter, "Config file error: {}", error),
             CoreError::IoError(ref error) => write!(formatter, "Io error: {}", error),
+            CoreError::InvalidLink(ref error) => write!(formatter, "Invalid link: {}", error),
+            CoreError::InvalidSnapshot(ref error) => {
+                write!(formatter, "Invalid snapshot: {}", error)
+            }
         }
     }
 }
@@ -280,6 +294,8 @@ impl Error for CoreError {
             CoreError::RequestTimeout => "Request has timed out",
             CoreError::ConfigError(ref error) => error.description(),
             CoreError::IoError(ref error) => error.description(),
+            CoreError::InvalidLink(_) => "Invalid link",
+            CoreError::InvalidSnapshot(_) => "Invalid snapshot",
         }
     }
 
@@ -7,8 +7,13 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::arrays::XorNameArray;
+use std::os::raw::c_char;
 
 /// FFI-wrapper for `File`.
+///
+/// The `content_type_*` and `attributes_*` fields extend the earlier layout of this struct, so
+/// bindings written against it have to be updated. The pointers of empty fields may be null when
+/// the struct is passed into the library.
 #[repr(C)]
 pub struct File {
     /// File size in bytes.
@@ -29,17 +34,90 @@ pub struct File {
     pub user_metadata_cap: usize,
     /// Name of the `ImmutableData` containing the content of this file.
     pub data_map_name: XorNameArray,
+    /// Compression applied to the content of this file.
+    pub compression: u64,
+    /// Pointer to the UTF-8 encoded MIME content type (not NUL-terminated).
+    pub content_type_ptr: *mut u8,
+    /// Size of the content type, 0 if not set.
+    pub content_type_len: usize,
+    /// Capacity of the content type (internal field).
+    pub content_type_cap: usize,
+    /// Pointer to the array of extended attributes.
+    pub attributes_ptr: *mut FileAttribute,
+    /// Number of extended attributes.
+    pub attributes_len: usize,
+    /// Capacity of the extended attributes array (internal field).
+    pub attributes_cap: usize,
 }
 
 impl Drop for File {
     #[allow(unsafe_code)]
     fn drop(&mut self) {
-        let _ = unsafe {
-            Vec::from_raw_parts(
+        unsafe {
+            let _ = Vec::from_raw_parts(
                 self.user_metadata_ptr,
                 self.user_metadata_len,
                 self.user_metadata_cap,
-            )
-        };
+            );
+            let _ = Vec::from_raw_parts(
+                self.content_type_ptr,
+                self.content_type_len,
+                self.content_type_cap,
+            );
+            let _ = Vec::from_raw_parts(
+                self.attributes_ptr,
+                self.attributes_len,
+                self.attributes_cap,
+            );
+        }
     }
 }
+
+/// FFI-wrapper for an extended attribute of a file.
+#[repr(C)]
+pub struct FileAttribute {
+    /// Pointer to the UTF-8 encoded attribute name (not NUL-terminated).
+    pub name_ptr: *mut u8,
+    /// Size of the attribute name.
+    pub name_len: usize,
+    /// Capacity of the attribute name (internal field).
+    pub name_cap: usize,
+    /// Pointer to the attribute value.
+    pub value_ptr: *mut u8,
+    /// Size of the attribute value.
+    pub value_len: usize,
+    /// Capacity of the attribute value (internal field).
+    pub value_cap: usize,
+}
+
+impl Drop for FileAttribute {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        unsafe {
+            let _ = Vec::from_raw_parts(self.name_ptr, self.name_len, self.name_cap);
+            let _ = Vec::from_raw_parts(self.value_ptr, self.value_len, self.value_cap);
+        }
+    }
+}
+
+/// FFI-wrapper for a file entry in a directory listing.
+#[repr(C)]
+pub struct FileEntry {
+    /// Name of the file.
+    pub name: *const c_char,
+    /// Version of the directory entry.
+    pub version: u64,
+    /// The file.
+    pub file: File,
+}
+
+/// FFI-wrapper for a change made to an entry of a watched directory.
+#[repr(C)]
+pub struct DirChange {
+    /// Index of the directory in the list of watched directories.
+    pub dir_index: usize,
+    /// Name of the entry. Names of subdirectory entries end with `/`.
+    pub name: *const c_char,
+    /// Kind of the change, one of the `WATCH_EVENT_*` constants.
+    pub kind: u64,
+}
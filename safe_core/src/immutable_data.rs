@@ -8,13 +8,20 @@
 
 use crate::client::Client;
 use crate::crypto::shared_secretbox;
+use crate::errors::CoreError;
 use crate::event_loop::CoreFuture;
+use futures::future::{self, Loop};
+use futures::stream::{self, Stream};
 use futures::Future;
 use maidsafe_utilities::serialisation::{deserialise, serialise};
-use routing::{ImmutableData, XorName};
+use routing::{ClientError, ImmutableData, XorName, XOR_NAME_LEN};
 use self_encryption::{DataMap, SelfEncryptor};
-use crate::self_encryption_storage::SelfEncryptionStorage;
+use crate::self_encryption_storage::{DedupStats, SelfEncryptionStorage};
 use crate::utils::{self, FutureExt};
+use std::cmp;
+
+/// Default number of chunks fetched in parallel when reading a value.
+pub const DEFAULT_CONCURRENCY: usize = 4;
 
 #[derive(Serialize, Deserialize)]
 enum DataTypeEncoding {
@@ -22,6 +29,31 @@ enum DataTypeEncoding {
     DataMap(DataMap),
 }
 
+/// Outcome of verifying the chunks of a value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Verification {
+    /// Number of chunks which have been checked.
+    pub checked: u64,
+    /// Chunks which couldn't be found on the network.
+    pub missing: Vec<XorName>,
+    /// Chunks whose content doesn't match their name.
+    pub corrupt: Vec<XorName>,
+}
+
+impl Verification {
+    /// Returns true if all the checked chunks are intact.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty()
+    }
+
+    /// Add the outcome of another verification to this one.
+    pub fn merge(&mut self, other: Verification) {
+        self.checked += other.checked;
+        self.missing.extend(other.missing);
+        self.corrupt.extend(other.corrupt);
+    }
+}
+
 /// Create and obtain immutable data out of the given raw bytes. This will encrypt the right content
 /// if the keys are provided and will ensure the maximum immutable data chunk size is respected.
 pub fn create(
@@ -31,8 +63,31 @@ pub fn create(
 ) -> Box<CoreFuture<ImmutableData>> {
     trace!("Creating conformant ImmutableData.");
 
-    let client = client.clone();
     let storage = SelfEncryptionStorage::new(client.clone());
+    create_with_storage(client.clone(), storage, value, encryption_key)
+}
+
+/// Create immutable data like `create`, without putting the chunks of the value which are stored
+/// on the network already. The skipped chunks are counted in `dedup_stats`, see
+/// `SelfEncryptionStorage::deduplicating`.
+pub fn create_deduplicating(
+    client: &impl Client,
+    value: &[u8],
+    encryption_key: Option<shared_secretbox::Key>,
+    dedup_stats: DedupStats,
+) -> Box<CoreFuture<ImmutableData>> {
+    trace!("Creating conformant ImmutableData, deduplicating its chunks.");
+
+    let storage = SelfEncryptionStorage::deduplicating(client.clone(), dedup_stats);
+    create_with_storage(client.clone(), storage, value, encryption_key)
+}
+
+fn create_with_storage<C: Client>(
+    client: C,
+    storage: SelfEncryptionStorage<C>,
+    value: &[u8],
+    encryption_key: Option<shared_secretbox::Key>,
+) -> Box<CoreFuture<ImmutableData>> {
     let self_encryptor = fry!(SelfEncryptor::new(storage, DataMap::None));
 
     self_encryptor
@@ -56,27 +111,35 @@ pub fn create(
 }
 
 /// Get the raw bytes from `ImmutableData` created via the `create` function in this module.
+/// Up to `DEFAULT_CONCURRENCY` chunks are fetched in parallel.
 pub fn extract_value(
     client: &impl Client,
     data: &ImmutableData,
     decryption_key: Option<shared_secretbox::Key>,
+) -> Box<CoreFuture<Vec<u8>>> {
+    extract_value_with_concurrency(client, data, decryption_key, DEFAULT_CONCURRENCY)
+}
+
+/// Same as `extract_value`, but fetches up to `concurrency` chunks in parallel. A `concurrency`
+/// of `1` fetches the chunks one after another.
+pub fn extract_value_with_concurrency(
+    client: &impl Client,
+    data: &ImmutableData,
+    decryption_key: Option<shared_secretbox::Key>,
+    concurrency: usize,
 ) -> Box<CoreFuture<Vec<u8>>> {
     let client = client.clone();
 
-    unpack(client.clone(), data)
+    unpack(client.clone(), data, concurrency)
         .and_then(move |value| {
             let data_map = if let Some(key) = decryption_key {
-                let plain_text = utils::symmetric_decrypt(&value, &key)?;
-                deserialise(&plain_text)?
+                let plain_text = fry!(utils::symmetric_decrypt(&value, &key));
+                fry!(deserialise(&plain_text))
             } else {
-                deserialise(&value)?
+                fry!(deserialise(&value))
             };
 
-            let storage = SelfEncryptionStorage::new(client);
-            Ok(SelfEncryptor::new(storage, data_map)?)
-        }).and_then(|self_encryptor| {
-            let length = self_encryptor.len();
-            self_encryptor.read(0, length).map_err(From::from)
+            read_all(client, data_map, concurrency)
         }).into_box()
 }
 
@@ -87,16 +150,219 @@ pub fn get_value(
     client: &impl Client,
     name: &XorName,
     decryption_key: Option<shared_secretbox::Key>,
+) -> Box<CoreFuture<Vec<u8>>> {
+    get_value_with_concurrency(client, name, decryption_key, DEFAULT_CONCURRENCY)
+}
+
+/// Same as `get_value`, but fetches up to `concurrency` chunks in parallel.
+pub fn get_value_with_concurrency(
+    client: &impl Client,
+    name: &XorName,
+    decryption_key: Option<shared_secretbox::Key>,
+    concurrency: usize,
 ) -> Box<CoreFuture<Vec<u8>>> {
     let client2 = client.clone();
     client
         .get_idata(*name)
-        .and_then(move |data| extract_value(&client2, &data, decryption_key))
-        .into_box()
+        .and_then(move |data| {
+            extract_value_with_concurrency(&client2, &data, decryption_key, concurrency)
+        }).into_box()
+}
+
+/// Get the names of all the `ImmutableData` chunks holding the value stored under `name` by the
+/// `create` function in this module, including `name` itself. The value itself is not fetched.
+pub fn chunk_names(
+    client: &impl Client,
+    name: &XorName,
+    decryption_key: Option<shared_secretbox::Key>,
+) -> Box<CoreFuture<Vec<XorName>>> {
+    value_data_map(client, name, decryption_key)
+        .map(|(data_map, mut names)| {
+            names.extend(data_map_chunk_names(&data_map));
+            names
+        }).into_box()
+}
+
+/// Same as `get_value`, but also returns the names of all the chunks holding the value, as
+/// `chunk_names` does, without fetching any of them twice.
+pub fn get_value_with_chunk_names(
+    client: &impl Client,
+    name: &XorName,
+    decryption_key: Option<shared_secretbox::Key>,
+) -> Box<CoreFuture<(Vec<u8>, Vec<XorName>)>> {
+    let client2 = client.clone();
+
+    value_data_map(client, name, decryption_key)
+        .and_then(move |(data_map, mut names)| {
+            names.extend(data_map_chunk_names(&data_map));
+            read_all(client2, data_map, DEFAULT_CONCURRENCY).map(move |value| (value, names))
+        }).into_box()
+}
+
+/// Get the names of the `ImmutableData` chunks referenced by the data map.
+pub fn data_map_chunk_names(data_map: &DataMap) -> Vec<XorName> {
+    match *data_map {
+        DataMap::Chunks(ref chunks) => chunks
+            .iter()
+            .filter(|chunk| chunk.hash.len() == XOR_NAME_LEN)
+            .map(|chunk| {
+                let mut name = [0; XOR_NAME_LEN];
+                name.copy_from_slice(&chunk.hash);
+                XorName(name)
+            }).collect(),
+        DataMap::Content(_) | DataMap::None => Vec::new(),
+    }
+}
+
+/// Check that every chunk holding the value stored under `name` by the `create` function in this
+/// module, including `name` itself, can be fetched and matches its name. Only the data maps are
+/// decrypted, the content of the value is never assembled.
+///
+/// The chunks are fetched from the network directly, so the caches can't hide chunks which have
+/// been lost. If a data map can't be retrieved because some of its own chunks are missing or
+/// corrupt, the chunks it references are not checked.
+pub fn verify(
+    client: &impl Client,
+    name: &XorName,
+    decryption_key: Option<shared_secretbox::Key>,
+) -> Box<CoreFuture<Verification>> {
+    trace!("Verifying ImmutableData {:?}", name);
+
+    let client = client.clone();
+    let name = *name;
+
+    check_chunk(&client, name)
+        .and_then(move |(root, verification)| {
+            let root = match root {
+                Some(root) => root,
+                None => return ok!(verification),
+            };
+
+            future::loop_fn((root, verification), move |(data, mut verification)| {
+                match fry!(deserialise(data.value())) {
+                    DataTypeEncoding::Serialised(value) => {
+                        let data_map: DataMap = if let Some(ref key) = decryption_key {
+                            let plain_text = fry!(utils::symmetric_decrypt(&value, key));
+                            fry!(deserialise(&plain_text))
+                        } else {
+                            fry!(deserialise(&value))
+                        };
+
+                        verify_data_map(&client, &data_map)
+                            .map(move |chunks| {
+                                verification.merge(chunks);
+                                Loop::Break(verification)
+                            }).into_box()
+                    }
+                    DataTypeEncoding::DataMap(data_map) => {
+                        let client = client.clone();
+
+                        verify_data_map(&client, &data_map)
+                            .and_then(move |chunks| {
+                                verification.merge(chunks);
+                                if !verification.is_ok() {
+                                    return ok!(Loop::Break(verification));
+                                }
+
+                                read_all(client, data_map, DEFAULT_CONCURRENCY)
+                                    .and_then(move |serialised_data| {
+                                        let data = deserialise(&serialised_data)?;
+                                        Ok(Loop::Continue((data, verification)))
+                                    }).into_box()
+                            }).into_box()
+                    }
+                }
+            }).into_box()
+        }).into_box()
+}
+
+/// Check that every chunk referenced by the data map can be fetched and matches its name. Up to
+/// `DEFAULT_CONCURRENCY` chunks are fetched in parallel.
+pub fn verify_data_map(client: &impl Client, data_map: &DataMap) -> Box<CoreFuture<Verification>> {
+    let client = client.clone();
+
+    stream::iter_ok::<_, CoreError>(data_map_chunk_names(data_map))
+        .map(move |name| check_chunk(&client, name).map(|(_, verification)| verification))
+        .buffered(DEFAULT_CONCURRENCY)
+        .fold(Verification::default(), |mut verification, chunk| {
+            verification.merge(chunk);
+            Ok::<_, CoreError>(verification)
+        }).into_box()
+}
+
+// Fetch the chunk and check it against its name. Returns the chunk, if it's intact.
+fn check_chunk(
+    client: &impl Client,
+    name: XorName,
+) -> Box<CoreFuture<(Option<ImmutableData>, Verification)>> {
+    client
+        .fetch_idata(name)
+        .then(move |result| {
+            let mut verification = Verification {
+                checked: 1,
+                ..Verification::default()
+            };
+
+            match result {
+                Ok(data) => {
+                    if *data.name() == name {
+                        return Ok((Some(data), verification));
+                    }
+
+                    warn!("Chunk {:?} doesn't match its name.", name);
+                    verification.corrupt.push(name);
+                }
+                Err(CoreError::RoutingClientError(ClientError::NoSuchData)) => {
+                    warn!("Chunk {:?} is missing.", name);
+                    verification.missing.push(name);
+                }
+                Err(error) => return Err(error),
+            }
+
+            Ok((None, verification))
+        }).into_box()
 }
 
 // TODO: consider rewriting these two function to not use recursion.
 
+// Unpack the `ImmutableData` stored under `name` down to the data map of the value it holds.
+// Returns the data map along with the names of the chunks traversed on the way, including `name`.
+fn value_data_map(
+    client: &impl Client,
+    name: &XorName,
+    decryption_key: Option<shared_secretbox::Key>,
+) -> Box<CoreFuture<(DataMap, Vec<XorName>)>> {
+    let client = client.clone();
+    let names = vec![*name];
+
+    client
+        .get_idata(*name)
+        .and_then(move |data| {
+            future::loop_fn((data, names), move |(data, mut names)| {
+                match fry!(deserialise(data.value())) {
+                    DataTypeEncoding::Serialised(value) => {
+                        let data_map: DataMap = if let Some(ref key) = decryption_key {
+                            let plain_text = fry!(utils::symmetric_decrypt(&value, key));
+                            fry!(deserialise(&plain_text))
+                        } else {
+                            fry!(deserialise(&value))
+                        };
+                        ok!(Loop::Break((data_map, names)))
+                    }
+                    DataTypeEncoding::DataMap(data_map) => {
+                        names.extend(data_map_chunk_names(&data_map));
+
+                        read_all(client.clone(), data_map, DEFAULT_CONCURRENCY)
+                            .and_then(move |serialised_data| {
+                                let data = deserialise(&serialised_data)?;
+                                Ok(Loop::Continue((data, names)))
+                            }).into_box()
+                    }
+                }
+            })
+        }).into_box()
+}
+
 fn pack(client: impl Client, value: Vec<u8>) -> Box<CoreFuture<ImmutableData>> {
     let data = ImmutableData::new(value);
     let serialised_data = fry!(serialise(&data));
@@ -117,28 +383,71 @@ fn pack(client: impl Client, value: Vec<u8>) -> Box<CoreFuture<ImmutableData>> {
     }
 }
 
-fn unpack(client: impl Client, data: &ImmutableData) -> Box<CoreFuture<Vec<u8>>> {
+fn unpack(
+    client: impl Client,
+    data: &ImmutableData,
+    concurrency: usize,
+) -> Box<CoreFuture<Vec<u8>>> {
     match fry!(deserialise(data.value())) {
         DataTypeEncoding::Serialised(value) => ok!(value),
-        DataTypeEncoding::DataMap(data_map) => {
+        DataTypeEncoding::DataMap(data_map) => read_all(client.clone(), data_map, concurrency)
+            .and_then(move |serialised_data| {
+                let data = fry!(deserialise(&serialised_data));
+                unpack(client, &data, concurrency)
+            }).into_box(),
+    }
+}
+
+// Read the whole content described by the data map. Every chunk is read by its own
+// self-encryptor, so up to `concurrency` of them are fetched from the network at the same time.
+fn read_all(
+    client: impl Client,
+    data_map: DataMap,
+    concurrency: usize,
+) -> Box<CoreFuture<Vec<u8>>> {
+    let ranges = chunk_ranges(&data_map, 0);
+    let reads = stream::iter_ok::<_, CoreError>(ranges)
+        .map(move |(position, length)| {
             let storage = SelfEncryptionStorage::new(client.clone());
-            let self_encryptor = fry!(SelfEncryptor::new(storage, data_map));
-            let length = self_encryptor.len();
+            let self_encryptor = fry!(SelfEncryptor::new(storage, data_map.clone()));
             self_encryptor
-                .read(0, length)
+                .read(position, length)
                 .map_err(From::from)
-                .and_then(move |serialised_data| {
-                    let data = fry!(deserialise(&serialised_data));
-                    unpack(client, &data)
-                }).into_box()
+                .into_box()
+        }).buffered(cmp::max(concurrency, 1));
+
+    reads.concat2().into_box()
+}
+
+// Split the data starting at `position` into `(position, length)` ranges aligned with the chunk
+// boundaries of the data map.
+pub(crate) fn chunk_ranges(data_map: &DataMap, position: u64) -> Vec<(u64, u64)> {
+    let sizes = match *data_map {
+        DataMap::Chunks(ref chunks) => chunks.iter().map(|chunk| chunk.source_size).collect(),
+        DataMap::Content(ref content) => vec![content.len() as u64],
+        DataMap::None => Vec::new(),
+    };
+
+    let mut ranges = Vec::with_capacity(sizes.len());
+    let mut start = 0;
+
+    for size in sizes {
+        let end = start + size;
+        if end > position {
+            let from = cmp::max(start, position);
+            ranges.push((from, end - from));
         }
+        start = end;
     }
+
+    ranges
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use futures::Future;
+    use rand;
     use crate::utils;
     use crate::utils::test_utils::{finish, random_client};
 
@@ -167,6 +476,142 @@ mod tests {
         create_and_retrieve(10 * 1024 * 1024)
     }
 
+    // Test retrieving a value fetching its chunks one by one, and many at a time.
+    #[test]
+    fn retrieve_with_concurrency() {
+        let value = unwrap!(utils::generate_random_vector(5 * 1024 * 1024));
+
+        random_client(move |client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+
+            create(client, &value, None)
+                .and_then(move |data| {
+                    let data_name = *data.name();
+                    client2.put_idata(data).map(move |_| data_name)
+                }).and_then(move |data_name| {
+                    let sequential = get_value_with_concurrency(&client3, &data_name, None, 1);
+                    let parallel = get_value_with_concurrency(&client3, &data_name, None, 16);
+                    sequential.join(parallel)
+                }).map(move |(sequential, parallel)| {
+                    assert_eq!(sequential, value);
+                    assert_eq!(parallel, value);
+                })
+        })
+    }
+
+    // Test that creating the same value again with deduplication skips its stored chunks.
+    #[test]
+    fn create_with_dedup() {
+        let value = unwrap!(utils::generate_random_vector(5 * 1024 * 1024));
+        let dedup_stats = DedupStats::new();
+        let dedup_stats2 = dedup_stats.clone();
+
+        random_client(move |client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+
+            create(client, &value, None)
+                .and_then(move |data| {
+                    assert_eq!(dedup_stats2.chunks(), 0);
+                    create_deduplicating(&client2, &value, None, dedup_stats2)
+                        .map(move |dedup_data| (data, dedup_data))
+                }).and_then(move |(data, dedup_data)| {
+                    assert_eq!(dedup_data, data);
+                    assert!(dedup_stats.chunks() > 1);
+                    assert!(dedup_stats.bytes() > 0);
+                    client3.put_idata(data)
+                })
+        })
+    }
+
+    // Test verifying a stored value, and a value which doesn't exist.
+    #[test]
+    fn verify_value() {
+        let value = unwrap!(utils::generate_random_vector(5 * 1024 * 1024));
+        let key = shared_secretbox::gen_key();
+        let missing: XorName = rand::random();
+
+        random_client(move |client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+
+            create(client, &value, Some(key.clone()))
+                .and_then(move |data| {
+                    let data_name = *data.name();
+                    client2.put_idata(data).map(move |_| data_name)
+                }).and_then(move |data_name| verify(&client3, &data_name, Some(key)))
+                .and_then(move |verification| {
+                    assert!(verification.is_ok());
+                    assert!(verification.checked > 1);
+                    verify(&client4, &missing, None)
+                }).map(move |verification| {
+                    assert_eq!(
+                        verification,
+                        Verification {
+                            checked: 1,
+                            missing: vec![missing],
+                            corrupt: Vec::new(),
+                        }
+                    );
+                })
+        })
+    }
+
+    // Benchmark retrieving a 10mb idata with different concurrency levels, with every response
+    // from the mock vault delayed to simulate network latency. The timings are logged at the info
+    // level. Run with
+    // `cargo test --release --features=use-mock-routing bench_get_value -- --ignored --nocapture`.
+    #[cfg(feature = "use-mock-routing")]
+    #[test]
+    #[ignore]
+    fn bench_get_value() {
+        use std::time::{Duration, Instant};
+
+        const SIZE: usize = 10 * 1024 * 1024;
+        const LATENCY_MS: u64 = 100;
+
+        // Another test may have initialised the logger already.
+        let _ = maidsafe_utilities::log::init(true);
+
+        random_client(|client| {
+            let client = client.clone();
+
+            stream::iter_ok::<_, CoreError>(vec![1, 2, 4, 8, 16])
+                .and_then(move |concurrency| {
+                    let client2 = client.clone();
+                    let client3 = client.clone();
+
+                    // Every run reads its own data, so none of the chunks are cached already.
+                    let value = unwrap!(utils::generate_random_vector(SIZE));
+                    client.set_simulated_latency(Duration::from_millis(0));
+
+                    create(&client, &value, None)
+                        .and_then(move |data| {
+                            let data_name = *data.name();
+                            client2.put_idata(data).map(move |_| data_name)
+                        }).and_then(move |data_name| {
+                            client3.set_simulated_latency(Duration::from_millis(LATENCY_MS));
+                            let start = Instant::now();
+
+                            get_value_with_concurrency(&client3, &data_name, None, concurrency).map(
+                                move |value_after| {
+                                    let elapsed = start.elapsed();
+                                    assert_eq!(value_after, value);
+                                    info!(
+                                        "Concurrency {:>2}: {:>5} ms",
+                                        concurrency,
+                                        elapsed.as_secs() * 1000
+                                            + u64::from(elapsed.subsec_millis())
+                                    );
+                                },
+                            )
+                        })
+                }).for_each(|()| Ok(()))
+        })
+    }
+
     fn create_and_retrieve(size: usize) {
         let value = unwrap!(utils::generate_random_vector(size));
 
@@ -156,6 +156,8 @@ pub mod utils;
 pub mod client;
 /// Config file handling.
 pub mod config_handler;
+/// Replicated data types stored in `MutableData`.
+pub mod crdt;
 /// Cryptographic utilities.
 pub mod crypto;
 /// Event loop handling.
@@ -166,19 +168,23 @@ pub mod immutable_data;
 pub mod ipc;
 /// NFS utilities.
 pub mod nfs;
+/// Links to public files stored as unencrypted `ImmutableData`.
+pub mod public_link;
 /// Implements the Self Encryption storage trait.
 pub mod self_encryption_storage;
 
 mod errors;
 mod event;
 
-pub use self::client::{mdata_info, recovery, Client, ClientKeys, MDataInfo};
+pub use self::client::{mdata_info, recovery, Client, ClientKeys, DiskCache, MDataInfo};
 #[cfg(feature = "use-mock-routing")]
 pub use self::client::{mock_vault_path, MockRouting};
 pub use self::errors::CoreError;
 pub use self::event::{CoreEvent, NetworkEvent, NetworkRx, NetworkTx};
 pub use self::event_loop::{CoreFuture, CoreMsg, CoreMsgRx, CoreMsgTx};
-pub use self::self_encryption_storage::{SelfEncryptionStorage, SelfEncryptionStorageError};
+pub use self::self_encryption_storage::{
+    DedupStats, SelfEncryptionStorage, SelfEncryptionStorageError,
+};
 pub use self::utils::FutureExt;
 
 /// All Maidsafe tagging should positive-offset from this.
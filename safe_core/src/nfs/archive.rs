@@ -0,0 +1,625 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Directory trees are archived in the POSIX pax interchange format, i.e. tar with extended
+//! headers. Every file is preceded by an extended header holding its modification time with
+//! nanosecond precision and, under the `SAFE.` vendor prefix, its creation time and base64-encoded
+//! user metadata. Archives created by other tools can be imported too: their directories and
+//! regular files are imported, GNU long name records are honoured and all other entries are
+//! skipped. Extended headers and long names are held in memory while importing, so archives with
+//! ones larger than `MAX_EXTENSION_SIZE` are rejected.
+//!
+//! Archives are read and written using blocking I/O calls.
+
+use crate::client::{Client, MDataInfo};
+use crate::crypto::shared_secretbox;
+use crate::nfs::file_helper;
+use crate::nfs::path_helper::{self, join_path, split_parent, Entry};
+use crate::nfs::{File, Mode, NfsError, NfsFuture, DEFAULT_READ_AHEAD};
+use crate::utils::FutureExt;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use data_encoding::BASE64;
+use futures::future::{self, Loop};
+use futures::{Future, Stream};
+use self_encryption::MAX_CHUNK_SIZE;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::iter;
+
+/// Maximum size of an extended header or a long name record accepted when importing.
+pub const MAX_EXTENSION_SIZE: u64 = 1024 * 1024;
+
+const BLOCK_SIZE: usize = 512;
+const NAME_LEN: usize = 100;
+// Largest value of the 12 bytes long octal size field.
+const MAX_HEADER_SIZE: u64 = 0o777_7777_7777;
+
+const PAX_PATH: &str = "path";
+const PAX_SIZE: &str = "size";
+const PAX_MTIME: &str = "mtime";
+const PAX_CREATED: &str = "SAFE.created";
+const PAX_USER_METADATA: &str = "SAFE.user_metadata";
+
+const TYPE_FILE: u8 = b'0';
+const TYPE_OLD_FILE: u8 = 0;
+const TYPE_CONTIGUOUS_FILE: u8 = b'7';
+const TYPE_DIR: u8 = b'5';
+const TYPE_PAX: u8 = b'x';
+const TYPE_GNU_LONG_NAME: u8 = b'L';
+const TYPE_GNU_LONG_LINK: u8 = b'K';
+
+enum Item {
+    Dir(String, MDataInfo),
+    File(String, Option<shared_secretbox::Key>, File),
+}
+
+struct Header {
+    path: String,
+    kind: u8,
+    size: u64,
+    mtime: u64,
+}
+
+/// Write the directory tree under `root` into `output` as a tar archive, streaming the file
+/// contents. Returns `output` after the end of the archive has been written.
+pub fn export<W>(client: impl Client, root: MDataInfo, output: W) -> Box<NfsFuture<W>>
+where
+    W: Write + 'static,
+{
+    trace!("Exporting directory tree into an archive.");
+
+    let pending = vec![Item::Dir(String::new(), root)];
+
+    future::loop_fn((pending, output), move |(mut pending, mut output)| {
+        match pending.pop() {
+            None => {
+                let res = output
+                    .write_all(&[0; 2 * BLOCK_SIZE])
+                    .map(move |()| Loop::Break(output))
+                    .map_err(NfsError::from);
+                future::result(res).into_box()
+            }
+            Some(Item::Dir(path, dir)) => {
+                if !path.is_empty() {
+                    fry!(write_dir_header(&mut output, &path));
+                }
+                let encryption_key = dir.enc_key().cloned();
+
+                path_helper::list(client.clone(), dir, "")
+                    .map(move |mut entries| {
+                        // Pushed in reverse order, so the entries get archived sorted by name.
+                        entries.sort_by(|a, b| b.0.cmp(&a.0));
+
+                        for (name, entry) in entries {
+                            let path = join_path(&path, &name);
+                            pending.push(match entry {
+                                Entry::Dir(subdir) => Item::Dir(path, subdir),
+                                Entry::File { file, .. } => {
+                                    Item::File(path, encryption_key.clone(), file)
+                                }
+                            });
+                        }
+
+                        Loop::Continue((pending, output))
+                    }).into_box()
+            }
+            Some(Item::File(path, encryption_key, file)) => file_helper::read_stream(
+                client.clone(),
+                &file,
+                encryption_key,
+                0,
+                DEFAULT_READ_AHEAD,
+            ).and_then(move |stream| {
+                let size = stream.size();
+                fry!(write_file_header(&mut output, &path, &file, size));
+
+                stream
+                    .fold(output, |mut output, data| {
+                        output.write_all(&data).map(move |()| output).map_err(NfsError::from)
+                    }).and_then(move |mut output| {
+                        output.write_all(&[0; BLOCK_SIZE][..padding(size)])?;
+                        Ok(Loop::Continue((pending, output)))
+                    }).into_box()
+            }).into_box(),
+        }
+    }).into_box()
+}
+
+/// Read a tar archive from `input` and store its directories and files under `root`, creating
+/// directories as needed and streaming the file contents. Returns `input` after the end of the
+/// archive has been reached.
+pub fn import<R>(client: impl Client, root: MDataInfo, input: R) -> Box<NfsFuture<R>>
+where
+    R: Read + 'static,
+{
+    trace!("Importing directory tree from an archive.");
+
+    future::loop_fn(
+        (input, BTreeMap::new()),
+        move |(mut input, mut extensions): (R, BTreeMap<String, String>)| {
+            let header = match fry!(read_header(&mut input)) {
+                Some(header) => header,
+                None => return ok!(Loop::Break(input)),
+            };
+
+            // Extended headers and long names apply to the following entry.
+            match header.kind {
+                TYPE_PAX => {
+                    let body = fry!(read_extension(&mut input, header.size));
+                    extensions.extend(fry!(parse_pax_records(&body)));
+                    return ok!(Loop::Continue((input, extensions)));
+                }
+                TYPE_GNU_LONG_NAME => {
+                    let body = fry!(read_extension(&mut input, header.size));
+                    let _ = extensions.insert(PAX_PATH.to_string(), fry!(parse_string(&body)));
+                    return ok!(Loop::Continue((input, extensions)));
+                }
+                TYPE_GNU_LONG_LINK => {
+                    let _ = fry!(read_extension(&mut input, header.size));
+                    return ok!(Loop::Continue((input, extensions)));
+                }
+                _ => (),
+            }
+
+            let path = extensions
+                .get(PAX_PATH)
+                .cloned()
+                .unwrap_or_else(|| header.path.clone());
+            let size = match extensions.get(PAX_SIZE) {
+                Some(size) => fry!(size.parse().map_err(|_| invalid_archive())),
+                None => header.size,
+            };
+
+            match header.kind {
+                TYPE_DIR => path_helper::ensure_dir(client.clone(), root.clone(), &path)
+                    .map(move |_| Loop::Continue((input, BTreeMap::new())))
+                    .into_box(),
+                TYPE_FILE | TYPE_OLD_FILE | TYPE_CONTIGUOUS_FILE => {
+                    let file = fry!(new_file(&header, &extensions));
+                    import_file(client.clone(), root.clone(), input, &path, file, size)
+                        .map(move |input| Loop::Continue((input, BTreeMap::new())))
+                        .into_box()
+                }
+                _ => {
+                    fry!(skip(&mut input, size + padding(size) as u64));
+                    ok!(Loop::Continue((input, BTreeMap::new())))
+                }
+            }
+        },
+    ).into_box()
+}
+
+fn import_file<R>(
+    client: impl Client,
+    root: MDataInfo,
+    input: R,
+    path: &str,
+    file: File,
+    size: u64,
+) -> Box<NfsFuture<R>>
+where
+    R: Read + 'static,
+{
+    let (parent_path, name) = split_parent(path);
+    if name.is_empty() {
+        return err!(NfsError::InvalidPath);
+    }
+    let name = name.to_string();
+    let created = *file.created_time();
+    let modified = *file.modified_time();
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    path_helper::ensure_dir(client, root, parent_path)
+        .and_then(move |parent| {
+            file_helper::write(client2, file, Mode::Overwrite, parent.enc_key().cloned())
+                .map(move |writer| (parent, writer))
+        }).and_then(move |(parent, writer)| {
+            future::loop_fn((input, writer, size), |(mut input, writer, remaining)| {
+                if remaining == 0 {
+                    return ok!(Loop::Break((input, writer)));
+                }
+
+                let mut data = vec![0; cmp::min(remaining, MAX_CHUNK_SIZE as u64) as usize];
+                fry!(input.read_exact(&mut data).map_err(NfsError::from));
+
+                let remaining = remaining - data.len() as u64;
+                writer
+                    .write(&data)
+                    .map(move |()| Loop::Continue((input, writer, remaining)))
+                    .into_box()
+            }).map(move |(input, writer)| (parent, input, writer))
+        }).and_then(move |(parent, mut input, writer)| {
+            fry!(skip(&mut input, padding(size) as u64));
+
+            writer
+                .close()
+                .and_then(move |mut file| {
+                    file.set_created_time(created);
+                    file.set_modified_time(modified);
+                    file_helper::insert(client3, parent, name, &file)
+                }).map(move |()| input)
+                .into_box()
+        }).into_box()
+}
+
+// Create the file to import, with the times and user metadata stored in the archive.
+fn new_file(header: &Header, extensions: &BTreeMap<String, String>) -> Result<File, NfsError> {
+    let user_metadata = match extensions.get(PAX_USER_METADATA) {
+        Some(encoded) => BASE64
+            .decode(encoded.as_bytes())
+            .map_err(|_| invalid_archive())?,
+        None => Vec::new(),
+    };
+    let modified = match extensions.get(PAX_MTIME) {
+        Some(mtime) => parse_time(mtime)?,
+        None => parse_time(&header.mtime.to_string())?,
+    };
+    let created = match extensions.get(PAX_CREATED) {
+        Some(created) => parse_time(created)?,
+        None => modified,
+    };
+
+    let mut file = File::new(user_metadata);
+    file.set_created_time(created);
+    file.set_modified_time(modified);
+    Ok(file)
+}
+
+fn write_dir_header(output: &mut impl Write, path: &str) -> Result<(), NfsError> {
+    let path = format!("{}/", path);
+
+    if path.len() > NAME_LEN {
+        write_pax_header(output, &path, &[(PAX_PATH, path.clone())])?;
+    }
+    write_header(output, &path, TYPE_DIR, 0, 0, 0o755)
+}
+
+fn write_file_header(
+    output: &mut impl Write,
+    path: &str,
+    file: &File,
+    size: u64,
+) -> Result<(), NfsError> {
+    let mut records = vec![
+        (PAX_MTIME, format_time(file.modified_time())),
+        (PAX_CREATED, format_time(file.created_time())),
+    ];
+    if !file.user_metadata().is_empty() {
+        records.push((PAX_USER_METADATA, BASE64.encode(file.user_metadata())));
+    }
+    if path.len() > NAME_LEN {
+        records.push((PAX_PATH, path.to_string()));
+    }
+    if size > MAX_HEADER_SIZE {
+        records.push((PAX_SIZE, size.to_string()));
+    }
+    write_pax_header(output, path, &records)?;
+
+    let mtime = cmp::max(file.modified_time().timestamp(), 0) as u64;
+    write_header(
+        output,
+        path,
+        TYPE_FILE,
+        cmp::min(size, MAX_HEADER_SIZE),
+        mtime,
+        0o644,
+    )
+}
+
+fn write_pax_header(
+    output: &mut impl Write,
+    path: &str,
+    records: &[(&str, String)],
+) -> Result<(), NfsError> {
+    let body: String = records
+        .iter()
+        .map(|&(key, ref value)| pax_record(key, value))
+        .collect();
+    let size = body.len() as u64;
+
+    write_header(
+        output,
+        &format!("PaxHeaders/{}", path),
+        TYPE_PAX,
+        size,
+        0,
+        0o644,
+    )?;
+    output.write_all(body.as_bytes())?;
+    output
+        .write_all(&[0; BLOCK_SIZE][..padding(size)])
+        .map_err(NfsError::from)
+}
+
+fn write_header(
+    output: &mut impl Write,
+    path: &str,
+    kind: u8,
+    size: u64,
+    mtime: u64,
+    mode: u64,
+) -> Result<(), NfsError> {
+    let mut header = [0u8; BLOCK_SIZE];
+
+    // Longer paths are stored in the extended header.
+    let mut name_len = cmp::min(path.len(), NAME_LEN);
+    while !path.is_char_boundary(name_len) {
+        name_len -= 1;
+    }
+    header[..name_len].copy_from_slice(&path.as_bytes()[..name_len]);
+
+    write_octal(&mut header[100..108], mode);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], mtime);
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    let checksum = checksum(&header);
+    write_octal(&mut header[148..155], checksum);
+    header[155] = b' ';
+
+    output.write_all(&header).map_err(NfsError::from)
+}
+
+// Read the next header, or return `None` at the end of the archive.
+fn read_header(input: &mut impl Read) -> Result<Option<Header>, NfsError> {
+    let mut header = [0u8; BLOCK_SIZE];
+    let mut read = 0;
+
+    while read < BLOCK_SIZE {
+        match input.read(&mut header[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(invalid_archive()),
+            Ok(len) => read += len,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    if header.iter().all(|byte| *byte == 0) {
+        return Ok(None);
+    }
+    if parse_octal(&header[148..156])? != checksum(&header) {
+        return Err(invalid_archive());
+    }
+
+    let name = parse_string(&header[..NAME_LEN])?;
+    let path = if &header[257..262] == b"ustar" {
+        let prefix = parse_string(&header[345..500])?;
+        if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        }
+    } else {
+        name
+    };
+
+    Ok(Some(Header {
+        path,
+        kind: header[156],
+        size: parse_octal(&header[124..136])?,
+        mtime: parse_octal(&header[136..148])?,
+    }))
+}
+
+// Read the body of an extended header or long name record, along with its padding.
+fn read_extension(input: &mut impl Read, size: u64) -> Result<Vec<u8>, NfsError> {
+    if size > MAX_EXTENSION_SIZE {
+        return Err(invalid_archive());
+    }
+
+    let mut body = vec![0; size as usize];
+    input.read_exact(&mut body)?;
+    skip(input, padding(size) as u64)?;
+    Ok(body)
+}
+
+fn parse_pax_records(mut body: &[u8]) -> Result<BTreeMap<String, String>, NfsError> {
+    let mut records = BTreeMap::new();
+
+    while !body.is_empty() {
+        let space = body
+            .iter()
+            .position(|byte| *byte == b' ')
+            .ok_or_else(invalid_archive)?;
+        let len: usize = parse_string(&body[..space])?
+            .parse()
+            .map_err(|_| invalid_archive())?;
+        if len <= space || len > body.len() || body[len - 1] != b'\n' {
+            return Err(invalid_archive());
+        }
+
+        let record = parse_string(&body[space + 1..len - 1])?;
+        let mut parts = record.splitn(2, '=');
+        let key = parts.next().unwrap_or("");
+        let value = parts.next().ok_or_else(invalid_archive)?;
+        let _ = records.insert(key.to_string(), value.to_string());
+
+        body = &body[len..];
+    }
+
+    Ok(records)
+}
+
+// Format a record of the extended header. The record starts with its own length in decimal.
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+
+    format!("{} {}={}\n", len, key, value)
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    format!("{}.{:09}", time.timestamp(), time.timestamp_subsec_nanos())
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, NfsError> {
+    let mut parts = value.splitn(2, '.');
+    let secs = parts
+        .next()
+        .unwrap_or("")
+        .parse()
+        .map_err(|_| invalid_archive())?;
+    let nanos = match parts.next() {
+        Some(fraction) => fraction
+            .chars()
+            .chain(iter::repeat('0'))
+            .take(9)
+            .collect::<String>()
+            .parse()
+            .map_err(|_| invalid_archive())?,
+        None => 0,
+    };
+
+    let naive = NaiveDateTime::from_timestamp_opt(secs, nanos).ok_or_else(invalid_archive)?;
+    Ok(DateTime::from_utc(naive, Utc))
+}
+
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    let len = field.len() - 1;
+    field[..len].copy_from_slice(&digits.as_bytes()[digits.len() - len..]);
+    field[len] = 0;
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64, NfsError> {
+    let digits = parse_string(field)?;
+    let digits = digits.trim_matches(|c| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid_archive())
+}
+
+fn parse_string(field: &[u8]) -> Result<String, NfsError> {
+    let len = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+    String::from_utf8(field[..len].to_vec()).map_err(|_| invalid_archive())
+}
+
+// Sum of the header bytes, with the checksum field itself counted as spaces.
+fn checksum(header: &[u8; BLOCK_SIZE]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| {
+            if index >= 148 && index < 156 {
+                u64::from(b' ')
+            } else {
+                u64::from(*byte)
+            }
+        }).sum()
+}
+
+fn skip(input: &mut impl Read, len: u64) -> Result<(), NfsError> {
+    let skipped = io::copy(&mut input.by_ref().take(len), &mut io::sink())?;
+    if skipped == len {
+        Ok(())
+    } else {
+        Err(invalid_archive())
+    }
+}
+
+// Number of zero bytes padding data of the given size to a whole number of blocks.
+fn padding(size: u64) -> usize {
+    (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE
+}
+
+fn invalid_archive() -> NfsError {
+    NfsError::from("Invalid archive")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfs::create_dir;
+    use crate::utils::test_utils::random_client;
+    use crate::DIR_TAG;
+    use std::io::Cursor;
+
+    fn write_gnu_long_name(output: &mut Vec<u8>, path: &str) {
+        let body = format!("{}\0", path);
+        let size = body.len() as u64;
+        unwrap!(write_header(
+            output,
+            "././@LongLink",
+            TYPE_GNU_LONG_NAME,
+            size,
+            0,
+            0o644
+        ));
+        output.extend_from_slice(body.as_bytes());
+        output.extend(iter::repeat(0).take(padding(size)));
+    }
+
+    // Test importing a file whose path is stored in a GNU long name record.
+    #[test]
+    fn import_gnu_long_name() {
+        let path = format!("{}/{}.txt", "d".repeat(60), "f".repeat(80));
+        let path2 = path.clone();
+
+        let mut data = Vec::new();
+        write_gnu_long_name(&mut data, &path);
+        unwrap!(write_header(&mut data, &path, TYPE_FILE, 3, 0, 0o644));
+        data.extend_from_slice(b"abc");
+        data.extend(iter::repeat(0).take(padding(3) + 2 * BLOCK_SIZE));
+
+        random_client(move |client| {
+            let c2 = client.clone();
+            let c3 = client.clone();
+            let root = unwrap!(MDataInfo::random_private(DIR_TAG));
+            let root2 = root.clone();
+
+            create_dir(client, &root, btree_map![], btree_map![])
+                .and_then(move |()| import(c2, root, Cursor::new(data)))
+                .and_then(move |_| path_helper::fetch_file(c3, root2, &path2))
+                .map(|(_, file)| {
+                    assert_eq!(file.size(), 3);
+                })
+        });
+    }
+
+    // Test that extended headers over the size limit are rejected.
+    #[test]
+    fn import_oversized_extension() {
+        let mut data = Vec::new();
+        unwrap!(write_header(
+            &mut data,
+            "PaxHeaders/big",
+            TYPE_PAX,
+            MAX_EXTENSION_SIZE + 1,
+            0,
+            0o644
+        ));
+
+        random_client(move |client| {
+            let c2 = client.clone();
+            let root = unwrap!(MDataInfo::random_private(DIR_TAG));
+
+            create_dir(client, &root, btree_map![], btree_map![])
+                .then(move |res| {
+                    unwrap!(res);
+                    import(c2, root, Cursor::new(data))
+                }).then(|res| -> Result<_, NfsError> {
+                    match res {
+                        Err(NfsError::Unexpected(_)) => (),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+                    Ok(())
+                })
+        });
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A batch collects inserts, updates and deletes of files in a single directory and commits them
+//! as one `mutate_mdata_entries` request, so either all of them are applied or none is. Deletes
+//! remove the file histories the same way as `file_helper::delete` does, and updates made with
+//! `update_with_history` record the replaced versions like `history::update`.
+//!
+//! If any of the entries conflicts with the current state of the directory, the whole batch is
+//! rejected with `NfsError::Conflict`, listing the offending files along with the reason. Batches
+//! containing names which aren't valid file names are rejected with `NfsError::InvalidPath`
+//! before anything is sent, as `file_helper::insert` does.
+
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use futures::{future, Future};
+use maidsafe_utilities::serialisation::serialise;
+use crate::nfs::file_helper::{convert_error, decode_file, lookup, validate_name, Version};
+use crate::nfs::history::{self, FileVersion};
+use crate::nfs::{File, NfsError, NfsFuture};
+use routing::{ClientError, EntryAction, EntryError, Value};
+use std::collections::BTreeMap;
+use crate::utils::FutureExt;
+
+#[derive(Clone, Debug)]
+enum Operation {
+    Insert(File),
+    // The flag tells whether the replaced version is recorded in the history.
+    Update(File, Version, bool),
+    Delete(Version),
+}
+
+/// Mutations of files in one directory, to be applied atomically.
+#[derive(Clone, Debug)]
+pub struct Batch {
+    parent: MDataInfo,
+    operations: BTreeMap<String, Operation>,
+}
+
+// Encrypted entry keys of a batched file, along with its current entry and history. The history
+// is only looked up for the operations which need it.
+struct Target {
+    name: String,
+    key: Vec<u8>,
+    history_key: Vec<u8>,
+    current: Option<Value>,
+    history: Option<Value>,
+}
+
+impl Batch {
+    /// Create an empty batch for the directory.
+    pub fn new(parent: MDataInfo) -> Self {
+        Batch {
+            parent,
+            operations: BTreeMap::new(),
+        }
+    }
+
+    /// Insert a new file. A later operation on the same name replaces the earlier one.
+    pub fn insert<S: Into<String>>(mut self, name: S, file: File) -> Self {
+        let _ = self.operations.insert(name.into(), Operation::Insert(file));
+        self
+    }
+
+    /// Update an existing file. Its history is left unchanged.
+    pub fn update<S: Into<String>>(mut self, name: S, file: File, version: Version) -> Self {
+        let _ = self
+            .operations
+            .insert(name.into(), Operation::Update(file, version, false));
+        self
+    }
+
+    /// Update an existing file, recording the replaced version in its history.
+    pub fn update_with_history<S: Into<String>>(
+        mut self,
+        name: S,
+        file: File,
+        version: Version,
+    ) -> Self {
+        let _ = self
+            .operations
+            .insert(name.into(), Operation::Update(file, version, true));
+        self
+    }
+
+    /// Delete a file, along with its history.
+    pub fn delete<S: Into<String>>(mut self, name: S, version: Version) -> Self {
+        let _ = self
+            .operations
+            .insert(name.into(), Operation::Delete(version));
+        self
+    }
+
+    /// Returns the number of files mutated by the batch.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Returns true if the batch mutates no files.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Apply all the mutations in a single request. Returns the new entry version of every
+    /// mutated file.
+    ///
+    /// If `Version::GetNext` is given for an update or a delete, the current version is first
+    /// retrieved from the network, and that version incremented by one is then used.
+    pub fn commit(self, client: impl Client) -> Box<NfsFuture<BTreeMap<String, u64>>> {
+        trace!("Committing a batch of {} file mutations", self.len());
+
+        let Batch { parent, operations } = self;
+        if operations.is_empty() {
+            return ok!(BTreeMap::new());
+        }
+        for name in operations.keys() {
+            if let Err(error) = validate_name(name) {
+                debug!("Rejecting batch with invalid file name '{}'", name);
+                return err!(error);
+            }
+        }
+
+        let targets = operations.iter().map(|(name, operation)| {
+            let key = fry!(parent.enc_entry_key(name.as_bytes()));
+            let history_key = fry!(history::history_key(&parent, name));
+            let name = name.clone();
+
+            let history_fut = match *operation {
+                Operation::Update(_, _, true) | Operation::Delete(_) => {
+                    lookup(&client, &parent, history_key.clone())
+                }
+                Operation::Insert(_) | Operation::Update(_, _, false) => ok!(None),
+            };
+
+            lookup(&client, &parent, key.clone())
+                .join(history_fut)
+                .map(move |(current, history)| Target {
+                    name,
+                    key,
+                    history_key,
+                    current,
+                    history,
+                }).into_box()
+        });
+        let targets = future::join_all(targets.collect::<Vec<_>>());
+
+        let parent2 = parent.clone();
+
+        targets
+            .and_then(move |targets| {
+                let mut actions = BTreeMap::new();
+                let mut names = BTreeMap::new();
+                let mut versions = BTreeMap::new();
+                let mut conflicts = BTreeMap::new();
+
+                for (target, (_, operation)) in targets.into_iter().zip(operations) {
+                    let version = match resolve_version(&target, &operation) {
+                        Ok(version) => version,
+                        Err(error) => {
+                            let _ = conflicts.insert(target.name, error);
+                            continue;
+                        }
+                    };
+
+                    for (key, action) in build_actions(&parent, &target, operation, version)? {
+                        let _ = names.insert(key.clone(), target.name.clone());
+                        let _ = actions.insert(key, action);
+                    }
+                    let _ = versions.insert(target.name, version);
+                }
+
+                if !conflicts.is_empty() {
+                    return Err(NfsError::Conflict(conflicts));
+                }
+
+                Ok((actions, names, versions))
+            }).and_then(move |(actions, names, versions)| {
+                client
+                    .mutate_mdata_entries(parent2.name, parent2.type_tag, actions)
+                    .map(move |()| versions)
+                    .map_err(move |error| match error {
+                        CoreError::RoutingClientError(ClientError::InvalidEntryActions(errors)) => {
+                            let conflicts = errors
+                                .into_iter()
+                                .filter_map(|(key, error)| {
+                                    names.get(&key).map(|name| (name.clone(), error))
+                                }).collect();
+                            NfsError::Conflict(conflicts)
+                        }
+                        error => convert_error(error),
+                    })
+            }).into_box()
+    }
+}
+
+// New entry version of the file, or the conflict detected before sending the mutation.
+fn resolve_version(target: &Target, operation: &Operation) -> Result<u64, EntryError> {
+    match *operation {
+        Operation::Insert(_) => Ok(0),
+        Operation::Update(_, Version::Custom(version), _)
+        | Operation::Delete(Version::Custom(version)) => Ok(version),
+        Operation::Update(_, Version::GetNext, _) | Operation::Delete(Version::GetNext) => target
+            .current
+            .as_ref()
+            .map(|value| value.entry_version + 1)
+            .ok_or(EntryError::NoSuchEntry),
+    }
+}
+
+// Entry actions implementing the operation on the target.
+fn build_actions(
+    parent: &MDataInfo,
+    target: &Target,
+    operation: Operation,
+    version: u64,
+) -> Result<Vec<(Vec<u8>, EntryAction)>, NfsError> {
+    let mut actions = Vec::new();
+
+    match operation {
+        Operation::Insert(file) => {
+            let content = parent.enc_entry_value(&serialise(&file)?)?;
+            actions.push((
+                target.key.clone(),
+                EntryAction::Ins(Value {
+                    content,
+                    entry_version: version,
+                }),
+            ));
+        }
+        Operation::Update(file, _, with_history) => {
+            let content = parent.enc_entry_value(&serialise(&file)?)?;
+            actions.push((
+                target.key.clone(),
+                EntryAction::Update(Value {
+                    content,
+                    entry_version: version,
+                }),
+            ));
+
+            if !with_history {
+                return Ok(actions);
+            }
+            if let Some(previous) = decode_file(parent, target.current.as_ref())? {
+                let previous = FileVersion {
+                    version: target.current.as_ref().map_or(0, |value| value.entry_version),
+                    file: previous,
+                };
+                let action = history::record(parent, target.history.as_ref(), previous)?;
+                actions.push((target.history_key.clone(), action));
+            }
+        }
+        Operation::Delete(_) => {
+            actions.push((target.key.clone(), EntryAction::Del(version)));
+
+            match target.history {
+                Some(ref history) if !history.content.is_empty() => {
+                    actions.push((
+                        target.history_key.clone(),
+                        EntryAction::Del(history.entry_version + 1),
+                    ));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Ok(actions)
+}
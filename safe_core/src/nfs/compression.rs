@@ -0,0 +1,200 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::nfs::NfsError;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression as Level, Decompress, FlushDecompress, Status};
+use std::cmp;
+use std::io::{self, Write};
+use std::mem;
+
+/// Compression applied to the contents of a file before they are self-encrypted.
+///
+/// Only deflate is supported for now. A faster, zstd-like codec is out of scope of this version;
+/// it can be added as a new variant later without affecting files which are already stored.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum Compression {
+    /// Contents are stored as they are.
+    None = 0,
+    /// Contents are compressed with deflate (RFC 1951).
+    Deflate = 1,
+}
+
+impl Compression {
+    /// Get the compression with the given numeric value, as used in the FFI.
+    pub fn from_u64(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+// Incrementally compresses the data written to a file.
+pub(crate) enum Compressor {
+    None,
+    Deflate(DeflateEncoder<Vec<u8>>),
+}
+
+impl Compressor {
+    pub fn new(compression: Compression) -> Self {
+        match compression {
+            Compression::None => Compressor::None,
+            Compression::Deflate => {
+                Compressor::Deflate(DeflateEncoder::new(Vec::new(), Level::best()))
+            }
+        }
+    }
+
+    pub fn compression(&self) -> Compression {
+        match *self {
+            Compressor::None => Compression::None,
+            Compressor::Deflate(_) => Compression::Deflate,
+        }
+    }
+
+    // Number of uncompressed bytes written so far, or `None` if no compression is applied.
+    pub fn total_in(&self) -> Option<u64> {
+        match *self {
+            Compressor::None => None,
+            Compressor::Deflate(ref encoder) => Some(encoder.total_in()),
+        }
+    }
+
+    // Compress the data, returning whatever compressed output is ready.
+    pub fn compress(&mut self, data: &[u8]) -> Result<Vec<u8>, NfsError> {
+        match *self {
+            Compressor::None => Ok(data.to_vec()),
+            Compressor::Deflate(ref mut encoder) => {
+                encoder.write_all(data)?;
+                Ok(mem::replace(encoder.get_mut(), Vec::new()))
+            }
+        }
+    }
+
+    // Finish the compressed stream, returning the remaining compressed output.
+    pub fn finish(self) -> Result<Vec<u8>, NfsError> {
+        match self {
+            Compressor::None => Ok(Vec::new()),
+            Compressor::Deflate(encoder) => encoder.finish().map_err(NfsError::from),
+        }
+    }
+}
+
+// Incrementally decompresses the contents of a file. The compressed data is queued with `push`
+// and decompressed in pieces of bounded size, so highly compressed data never has to be held in
+// memory all at once.
+pub(crate) struct Decompressor {
+    // `None` if the contents are stored uncompressed.
+    inflate: Option<Decompress>,
+    input: Vec<u8>,
+    consumed: usize,
+}
+
+impl Decompressor {
+    pub fn new(compression: Compression) -> Self {
+        let inflate = match compression {
+            Compression::None => None,
+            Compression::Deflate => Some(Decompress::new(false)),
+        };
+
+        Decompressor {
+            inflate,
+            input: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    // Queue more of the compressed data.
+    pub fn push(&mut self, data: &[u8]) {
+        let _ = self.input.drain(..self.consumed);
+        self.input.extend_from_slice(data);
+        self.consumed = 0;
+    }
+
+    // Decompress at most `max` bytes of output from the queued data. Returns an empty buffer
+    // once more data has to be pushed or, if `finish` is set, once the contents are complete.
+    pub fn decompress(&mut self, max: usize, finish: bool) -> Result<Vec<u8>, NfsError> {
+        let inflate = match self.inflate {
+            Some(ref mut inflate) => inflate,
+            None => {
+                let start = self.consumed;
+                self.consumed = cmp::min(start + max, self.input.len());
+                return Ok(self.input[start..self.consumed].to_vec());
+            }
+        };
+
+        let flush = if finish {
+            FlushDecompress::Finish
+        } else {
+            FlushDecompress::None
+        };
+        let mut output = Vec::with_capacity(max);
+
+        while output.len() < max {
+            let total_in = inflate.total_in();
+            let total_out = inflate.total_out();
+            let status = inflate
+                .decompress_vec(&self.input[self.consumed..], &mut output, flush)
+                .map_err(io::Error::from)?;
+            self.consumed += (inflate.total_in() - total_in) as usize;
+
+            let stalled = inflate.total_in() == total_in && inflate.total_out() == total_out;
+            if status == Status::StreamEnd || stalled {
+                break;
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that highly compressed data is decompressed in pieces of bounded size.
+    #[test]
+    fn decompress_bounded() {
+        const SIZE: usize = 1_000_000;
+        const MAX: usize = 4096;
+
+        let mut compressor = Compressor::new(Compression::Deflate);
+        let mut compressed = unwrap!(compressor.compress(&vec![7; SIZE]));
+        compressed.extend(unwrap!(compressor.finish()));
+        assert!(compressed.len() < MAX);
+
+        let mut decompressor = Decompressor::new(Compression::Deflate);
+        decompressor.push(&compressed);
+
+        let mut content = Vec::new();
+        loop {
+            let data = unwrap!(decompressor.decompress(MAX, true));
+            if data.is_empty() {
+                break;
+            }
+            assert!(data.len() <= MAX);
+            content.extend(data);
+        }
+        assert_eq!(content, vec![7; SIZE]);
+
+        // Uncompressed data is split the same way
+        let mut decompressor = Decompressor::new(Compression::None);
+        decompressor.push(&[7; MAX + 1]);
+        assert_eq!(unwrap!(decompressor.decompress(MAX, false)).len(), MAX);
+        assert_eq!(unwrap!(decompressor.decompress(MAX, false)).len(), 1);
+        assert!(unwrap!(decompressor.decompress(MAX, false)).is_empty());
+    }
+}
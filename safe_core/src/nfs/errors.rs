@@ -8,9 +8,13 @@
 
 use crate::errors::CoreError;
 use maidsafe_utilities::serialisation::SerialisationError;
+use routing::EntryError;
 use self_encryption::SelfEncryptionError;
 use crate::self_encryption_storage::SelfEncryptionStorageError;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::io;
+use std::path::Path;
 
 /// NFS Errors
 #[allow(clippy::large_enum_variant)]
@@ -23,6 +27,14 @@ pub enum NfsError {
     FileNotFound,
     /// Invalid byte range specified
     InvalidRange,
+    /// Directory still contains entries
+    DirectoryNotEmpty,
+    /// Invalid path specified
+    InvalidPath,
+    /// Entries conflicting with the current state of the directory, by file name
+    Conflict(BTreeMap<String, EntryError>),
+    /// Operation not supported on compressed files
+    CompressedFile,
     /// Unexpected error
     Unexpected(String),
     /// Unsuccessful Serialisation or Deserialisation
@@ -43,6 +55,12 @@ impl From<SerialisationError> for NfsError {
     }
 }
 
+impl From<io::Error> for NfsError {
+    fn from(error: io::Error) -> NfsError {
+        NfsError::Unexpected(error.to_string())
+    }
+}
+
 impl<'a> From<&'a str> for NfsError {
     fn from(error: &'a str) -> NfsError {
         NfsError::Unexpected(error.to_string())
@@ -55,6 +73,11 @@ impl From<SelfEncryptionError<SelfEncryptionStorageError>> for NfsError {
     }
 }
 
+/// Error from an I/O operation on the local file `path`.
+pub(crate) fn io_error(path: &Path, error: &io::Error) -> NfsError {
+    NfsError::Unexpected(format!("{}: {}", path.display(), error))
+}
+
 impl fmt::Display for NfsError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -65,6 +88,14 @@ impl fmt::Display for NfsError {
             NfsError::FileNotFound => write!(f, "File not found"),
 
             NfsError::InvalidRange => write!(f, "Invalid byte range specified"),
+            NfsError::DirectoryNotEmpty => write!(f, "Directory still contains entries"),
+            NfsError::InvalidPath => write!(f, "Invalid path specified"),
+            NfsError::Conflict(ref conflicts) => write!(
+                f,
+                "Entries conflict with the current state of the directory: {:?}",
+                conflicts
+            ),
+            NfsError::CompressedFile => write!(f, "Operation not supported on compressed files"),
             NfsError::Unexpected(ref error) => write!(f, "Unexpected error - {:?}", error),
             NfsError::EncodeDecodeError(ref error) => write!(
                 f,
@@ -87,6 +118,10 @@ impl fmt::Debug for NfsError {
             NfsError::FileExists => write!(f, "NfsError::FileExists"),
             NfsError::FileNotFound => write!(f, "NfsError::FileNotFound"),
             NfsError::InvalidRange => write!(f, "NfsError::InvalidRange"),
+            NfsError::DirectoryNotEmpty => write!(f, "NfsError::DirectoryNotEmpty"),
+            NfsError::InvalidPath => write!(f, "NfsError::InvalidPath"),
+            NfsError::Conflict(ref conflicts) => write!(f, "NfsError::Conflict -> {:?}", conflicts),
+            NfsError::CompressedFile => write!(f, "NfsError::CompressedFile"),
             NfsError::Unexpected(ref error) => write!(f, "NfsError::Unexpected -> {:?}", error),
             NfsError::EncodeDecodeError(ref error) => {
                 write!(f, "NfsError::EncodeDecodeError -> {:?}", error)
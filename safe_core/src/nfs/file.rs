@@ -7,21 +7,40 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use chrono::{DateTime, NaiveDateTime, Utc};
-use crate::ffi::nfs::File as FfiFile;
+use crate::ffi::nfs::{File as FfiFile, FileAttribute as FfiFileAttribute};
 use ffi_utils::{vec_into_raw_parts, ReprC};
 use crate::nfs::errors::NfsError;
+use crate::nfs::Compression;
 use routing::XorName;
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
 use std::slice;
 
+// Files are serialised as a sequence of fields. Since the introduction of layout versions, the
+// sequence starts with this marker followed by the layout version. Files serialised before that
+// start with their size instead, which can never be equal to the marker. Files which don't use
+// any of the newer fields are still serialised in the unversioned layout, so that they remain
+// readable by older clients.
+const VERSIONED_MARKER: u64 = u64::max_value();
+const LAYOUT_VERSION: u64 = 2;
+const MAX_FIELDS: usize = 10;
+const UNVERSIONED_FIELDS: usize = 5;
+
 /// Representation of a File to be put into the network. Could be any kind of
 /// file: text, music, video, etc.
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct File {
     size: u64,
     created: DateTime<Utc>,
     modified: DateTime<Utc>,
     user_metadata: Vec<u8>,
     data_map_name: XorName,
+    compression: Compression,
+    content_type: Option<String>,
+    attributes: BTreeMap<String, Vec<u8>>,
 }
 
 impl File {
@@ -33,15 +52,40 @@ impl File {
             modified: Utc::now(),
             user_metadata,
             data_map_name: XorName::default(),
+            compression: Compression::None,
+            content_type: None,
+            attributes: BTreeMap::new(),
         }
     }
 
     /// Construct FFI wrapper for the native rust `File`, consuming the file.
-    pub fn into_repr_c(self) -> FfiFile {
+    pub fn into_repr_c(mut self) -> FfiFile {
         // TODO: move the metadata, not clone.
         let user_metadata = self.user_metadata().to_vec();
         let (user_metadata_ptr, user_metadata_len, user_metadata_cap) =
             vec_into_raw_parts(user_metadata);
+        let content_type = self
+            .content_type
+            .take()
+            .map(String::into_bytes)
+            .unwrap_or_default();
+        let (content_type_ptr, content_type_len, content_type_cap) =
+            vec_into_raw_parts(content_type);
+        let attributes = mem::replace(&mut self.attributes, BTreeMap::new())
+            .into_iter()
+            .map(|(name, value)| {
+                let (name_ptr, name_len, name_cap) = vec_into_raw_parts(name.into_bytes());
+                let (value_ptr, value_len, value_cap) = vec_into_raw_parts(value);
+                FfiFileAttribute {
+                    name_ptr,
+                    name_len,
+                    name_cap,
+                    value_ptr,
+                    value_len,
+                    value_cap,
+                }
+            }).collect();
+        let (attributes_ptr, attributes_len, attributes_cap) = vec_into_raw_parts(attributes);
 
         FfiFile {
             size: self.size(),
@@ -53,6 +97,13 @@ impl File {
             user_metadata_len,
             user_metadata_cap,
             data_map_name: self.data_map_name().0,
+            compression: self.compression() as u64,
+            content_type_ptr,
+            content_type_len,
+            content_type_cap,
+            attributes_ptr,
+            attributes_len,
+            attributes_cap,
         }
     }
 
@@ -81,6 +132,26 @@ impl File {
         &self.user_metadata
     }
 
+    /// Get the compression applied to the contents
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Get the MIME content type, if known
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_ref().map(String::as_str)
+    }
+
+    /// Get all the extended attributes
+    pub fn attributes(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.attributes
+    }
+
+    /// Get the value of the extended attribute with the given name
+    pub fn attribute(&self, name: &str) -> Option<&[u8]> {
+        self.attributes.get(name).map(Vec::as_slice)
+    }
+
     /// Set the data-map name of the File
     pub fn set_data_map_name(&mut self, datamap_name: XorName) {
         self.data_map_name = datamap_name;
@@ -105,6 +176,121 @@ impl File {
     pub fn set_user_metadata(&mut self, user_metadata: Vec<u8>) {
         self.user_metadata = user_metadata;
     }
+
+    /// Set the compression to apply to the contents. Only takes effect when the contents are
+    /// written anew, see `Mode::Overwrite`.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Set or clear the MIME content type
+    pub fn set_content_type(&mut self, content_type: Option<String>) {
+        self.content_type = content_type;
+    }
+
+    /// Set the value of an extended attribute, returning the previous value if any
+    pub fn set_attribute(&mut self, name: String, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.attributes.insert(name, value)
+    }
+
+    /// Remove an extended attribute, returning its value if it was set
+    pub fn remove_attribute(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.attributes.remove(name)
+    }
+}
+
+impl Serialize for File {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let versioned = self.compression != Compression::None
+            || self.content_type.is_some()
+            || !self.attributes.is_empty();
+
+        let mut fields = if versioned {
+            let mut fields = serializer.serialize_tuple(MAX_FIELDS)?;
+            fields.serialize_element(&VERSIONED_MARKER)?;
+            fields.serialize_element(&LAYOUT_VERSION)?;
+            fields
+        } else {
+            serializer.serialize_tuple(UNVERSIONED_FIELDS)?
+        };
+        fields.serialize_element(&self.size)?;
+        fields.serialize_element(&self.created)?;
+        fields.serialize_element(&self.modified)?;
+        fields.serialize_element(&self.user_metadata)?;
+        fields.serialize_element(&self.data_map_name)?;
+        if versioned {
+            fields.serialize_element(&self.compression)?;
+            fields.serialize_element(&self.content_type)?;
+            fields.serialize_element(&self.attributes)?;
+        }
+        fields.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for File {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(MAX_FIELDS, FileVisitor)
+    }
+}
+
+struct FileVisitor;
+
+impl<'de> Visitor<'de> for FileVisitor {
+    type Value = File;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a serialised File")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<File, A::Error> {
+        let first = next_field(&mut seq, 0)?;
+        let (version, size) = if first == VERSIONED_MARKER {
+            (next_field(&mut seq, 1)?, next_field(&mut seq, 2)?)
+        } else {
+            (0, first)
+        };
+        if version > LAYOUT_VERSION {
+            return Err(de::Error::custom(format!(
+                "unsupported File layout version {}",
+                version
+            )));
+        }
+
+        let created = next_field(&mut seq, 3)?;
+        let modified = next_field(&mut seq, 4)?;
+        let user_metadata = next_field(&mut seq, 5)?;
+        let data_map_name = next_field(&mut seq, 6)?;
+        let compression = if version >= 1 {
+            next_field(&mut seq, 7)?
+        } else {
+            Compression::None
+        };
+        let (content_type, attributes) = if version >= 2 {
+            (next_field(&mut seq, 8)?, next_field(&mut seq, 9)?)
+        } else {
+            (None, BTreeMap::new())
+        };
+
+        Ok(File {
+            size,
+            created,
+            modified,
+            user_metadata,
+            data_map_name,
+            compression,
+            content_type,
+            attributes,
+        })
+    }
+}
+
+fn next_field<'de, A, T>(seq: &mut A, index: usize) -> Result<T, A::Error>
+where
+    A: SeqAccess<'de>,
+    T: Deserialize<'de>,
+{
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(index, &"a serialised File"))
 }
 
 impl ReprC for File {
@@ -114,15 +300,47 @@ impl ReprC for File {
     /// Convert to the native rust equivalent by cloning the internal data, preserving self.
     #[allow(unsafe_code)]
     unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
-        let user_metadata =
-            slice::from_raw_parts((*repr_c).user_metadata_ptr, (*repr_c).user_metadata_len)
-                .to_vec();
+        let user_metadata = raw_slice(
+            (*repr_c).user_metadata_ptr,
+            (*repr_c).user_metadata_len,
+            "user metadata",
+        )?.to_vec();
 
         let created = convert_date_time((*repr_c).created_sec, (*repr_c).created_nsec)?;
         let modified = convert_date_time((*repr_c).modified_sec, (*repr_c).modified_nsec)?;
 
+        let compression = Compression::from_u64((*repr_c).compression)
+            .ok_or_else(|| NfsError::Unexpected("Invalid compression".to_string()))?;
+
+        let content_type = raw_slice(
+            (*repr_c).content_type_ptr,
+            (*repr_c).content_type_len,
+            "content type",
+        )?.to_vec();
+        let content_type = if content_type.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8(content_type).map_err(|_| invalid_utf8("content type"))?)
+        };
+
         let mut file = File::new(user_metadata);
         file.set_size((*repr_c).size);
+        file.set_compression(compression);
+        file.set_content_type(content_type);
+
+        let attributes = raw_slice(
+            (*repr_c).attributes_ptr,
+            (*repr_c).attributes_len,
+            "attributes",
+        )?;
+        for attribute in attributes {
+            let name = raw_slice(attribute.name_ptr, attribute.name_len, "attribute name")?;
+            let value = raw_slice(attribute.value_ptr, attribute.value_len, "attribute value")?;
+            let name =
+                String::from_utf8(name.to_vec()).map_err(|_| invalid_utf8("attribute name"))?;
+            let _ = file.set_attribute(name, value.to_vec());
+        }
+
         file.set_created_time(created);
         file.set_modified_time(modified);
         file.set_data_map_name(XorName((*repr_c).data_map_name));
@@ -131,6 +349,18 @@ impl ReprC for File {
     }
 }
 
+// Slice of `len` items at `ptr`, which may only be null if `len` is zero.
+#[allow(unsafe_code)]
+unsafe fn raw_slice<'a, T>(ptr: *const T, len: usize, what: &str) -> Result<&'a [T], NfsError> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(NfsError::Unexpected(format!("Invalid {}: null pointer", what)))
+    } else {
+        Ok(slice::from_raw_parts(ptr, len))
+    }
+}
+
 #[inline]
 fn convert_date_time(sec: i64, nsec: u32) -> Result<DateTime<Utc>, NfsError> {
     let naive = NaiveDateTime::from_timestamp_opt(sec, nsec)
@@ -138,10 +368,15 @@ fn convert_date_time(sec: i64, nsec: u32) -> Result<DateTime<Utc>, NfsError> {
     Ok(DateTime::<Utc>::from_utc(naive, Utc))
 }
 
+fn invalid_utf8(what: &str) -> NfsError {
+    NfsError::Unexpected(format!("Invalid {}: not valid UTF-8", what))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use maidsafe_utilities::serialisation::{deserialise, serialise};
+    use std::ptr;
 
     // Test that serialising and deserialising a file restores the original file.
     #[test]
@@ -151,4 +386,98 @@ mod tests {
         let obj_after = unwrap!(deserialise(&serialised_data));
         assert_eq!(obj_before, obj_after);
     }
+
+    // Test that files serialised before the layout was versioned can still be deserialised, and
+    // that files which don't need the newer fields are serialised the same way.
+    #[test]
+    fn deserialise_unversioned() {
+        #[derive(Serialize)]
+        struct UnversionedFile {
+            size: u64,
+            created: DateTime<Utc>,
+            modified: DateTime<Utc>,
+            user_metadata: Vec<u8>,
+            data_map_name: XorName,
+        }
+
+        let mut file = File::new(vec![1, 2, 3]);
+        file.set_size(1234);
+        file.set_data_map_name(XorName([7; 32]));
+
+        let unversioned = UnversionedFile {
+            size: file.size(),
+            created: *file.created_time(),
+            modified: *file.modified_time(),
+            user_metadata: file.user_metadata().to_vec(),
+            data_map_name: *file.data_map_name(),
+        };
+        let serialised_data = unwrap!(serialise(&unversioned));
+        let deserialised: File = unwrap!(deserialise(&serialised_data));
+        assert_eq!(deserialised, file);
+        assert_eq!(deserialised.compression(), Compression::None);
+        assert_eq!(unwrap!(serialise(&file)), serialised_data);
+
+        file.set_compression(Compression::Deflate);
+        let serialised_data = unwrap!(serialise(&file));
+        let marker = unwrap!(serialise(&VERSIONED_MARKER));
+        assert!(serialised_data.starts_with(&marker));
+        assert_eq!(file, unwrap!(deserialise(&serialised_data)));
+    }
+
+    // Test that content type and attributes survive serialisation and conversion to and from FFI.
+    #[test]
+    #[allow(unsafe_code)]
+    fn attributes() {
+        let mut file = File::new(Vec::new());
+        assert_eq!(file.content_type(), None);
+        assert_eq!(file.attribute("user.tag"), None);
+
+        file.set_content_type(Some("text/plain".to_string()));
+        assert_eq!(file.set_attribute("user.tag".to_string(), vec![1, 2]), None);
+        assert_eq!(
+            file.set_attribute("user.tag".to_string(), vec![3]),
+            Some(vec![1, 2])
+        );
+        let _ = file.set_attribute("user.empty".to_string(), Vec::new());
+        assert_eq!(file.content_type(), Some("text/plain"));
+        assert_eq!(file.attribute("user.tag"), Some(&[3u8][..]));
+        assert_eq!(file.attributes().len(), 2);
+
+        let serialised_data = unwrap!(serialise(&file));
+        assert_eq!(file, unwrap!(deserialise(&serialised_data)));
+
+        let ffi_file = file.clone().into_repr_c();
+        assert_eq!(ffi_file.attributes_len, 2);
+        let file2 = unsafe { unwrap!(File::clone_from_repr_c(&ffi_file)) };
+        assert_eq!(file, file2);
+
+        assert_eq!(file.remove_attribute("user.tag"), Some(vec![3]));
+        assert_eq!(file.remove_attribute("user.tag"), None);
+        file.set_content_type(None);
+
+        let ffi_file = file.clone().into_repr_c();
+        let file2 = unsafe { unwrap!(File::clone_from_repr_c(&ffi_file)) };
+        assert_eq!(file, file2);
+
+        // Null pointers are accepted for empty fields only. The original pointers are put back
+        // before the FFI file is dropped.
+        let mut ffi_file = File::new(Vec::new()).into_repr_c();
+        let content_type_ptr = ffi_file.content_type_ptr;
+        let attributes_ptr = ffi_file.attributes_ptr;
+        ffi_file.content_type_ptr = ptr::null_mut();
+        ffi_file.attributes_ptr = ptr::null_mut();
+        let file2 = unsafe { unwrap!(File::clone_from_repr_c(&ffi_file)) };
+        assert_eq!(file2.content_type(), None);
+        assert!(file2.attributes().is_empty());
+
+        ffi_file.content_type_len = 1;
+        match unsafe { File::clone_from_repr_c(&ffi_file) } {
+            Err(NfsError::Unexpected(_)) => (),
+            x => panic!("Unexpected {:?}", x),
+        }
+
+        ffi_file.content_type_len = 0;
+        ffi_file.content_type_ptr = content_type_ptr;
+        ffi_file.attributes_ptr = attributes_ptr;
+    }
 }
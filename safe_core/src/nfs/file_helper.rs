@@ -6,14 +6,20 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use crate::client::mdata_info::is_hidden_entry;
 use crate::client::{Client, MDataInfo};
 use crate::crypto::shared_secretbox;
 use crate::errors::CoreError;
 use futures::{Future, IntoFuture};
+use crate::immutable_data::{self, Verification};
 use maidsafe_utilities::serialisation::{deserialise, serialise};
-use crate::nfs::{File, Mode, NfsError, NfsFuture, Reader, Writer};
-use routing::{ClientError, EntryActions};
-use crate::self_encryption_storage::SelfEncryptionStorage;
+use crate::nfs::history;
+use crate::nfs::path_helper::DIR_ENTRY_SUFFIX;
+use crate::nfs::{data_map, File, Mode, NfsError, NfsFuture, ReadStream, Reader, Writer};
+use routing::{ClientError, EntryAction, EntryActions, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::self_encryption_storage::{DedupStats, SelfEncryptionStorage};
 use crate::utils::FutureExt;
 
 /// Enum specifying which version should be used in places where a version is required.
@@ -25,7 +31,8 @@ pub enum Version {
     Custom(u64),
 }
 
-/// Insert the file into the directory.
+/// Insert the file into the directory. Fails with `NfsError::InvalidPath` if the name contains a
+/// `/`.
 pub fn insert<S>(client: impl Client, parent: MDataInfo, name: S, file: &File) -> Box<NfsFuture<()>>
 where
     S: AsRef<str>,
@@ -33,6 +40,8 @@ where
     let name = name.as_ref();
     trace!("Inserting file with name '{}'", name);
 
+    fry!(validate_name(name));
+
     serialise(&file)
         .map_err(From::from)
         .and_then(|encoded| {
@@ -75,6 +84,37 @@ where
         .into_box()
 }
 
+/// Get all files from the directory, along with their names and entry versions.
+///
+/// Deleted entries, the metadata entry, subdirectory entries and file histories are skipped.
+pub fn list(client: impl Client, parent: MDataInfo) -> Box<NfsFuture<Vec<(String, u64, File)>>> {
+    trace!("Listing files in the directory.");
+
+    client
+        .list_mdata_entries(parent.name, parent.type_tag)
+        .map_err(NfsError::from)
+        .and_then(move |entries| {
+            let mut files = Vec::with_capacity(entries.len());
+
+            for (key, value) in entries {
+                if is_hidden_entry(&key, &value) {
+                    continue;
+                }
+
+                let name = String::from_utf8(parent.decrypt(&key)?)
+                    .map_err(|_| NfsError::from("File name is not valid UTF-8"))?;
+                if name.ends_with(DIR_ENTRY_SUFFIX) || history::is_history_entry(&name) {
+                    continue;
+                }
+
+                let plaintext = parent.decrypt(&value.content)?;
+                files.push((name, value.entry_version, deserialise(&plaintext)?));
+            }
+
+            Ok(files)
+        }).into_box()
+}
+
 /// Return a Reader for reading the file contents.
 pub fn read<C: Client>(
     client: C,
@@ -90,7 +130,51 @@ pub fn read<C: Client>(
     )
 }
 
-/// Delete a file from the directory.
+/// Return a stream yielding the file contents starting at `position`, fetching up to
+/// `read_ahead` chunks in advance.
+pub fn read_stream(
+    client: impl Client,
+    file: &File,
+    encryption_key: Option<shared_secretbox::Key>,
+    position: u64,
+    read_ahead: usize,
+) -> Box<NfsFuture<ReadStream>> {
+    trace!("Streaming file {:?} from position {}", file, position);
+    ReadStream::new(client, file, encryption_key, position, read_ahead)
+}
+
+/// Check that every chunk of the file, and of its data map, can be fetched from the network and
+/// matches its name. Missing and corrupt chunks are reported in the returned `Verification`.
+/// The content of the file is never decrypted.
+pub fn verify(
+    client: impl Client,
+    file: &File,
+    encryption_key: Option<shared_secretbox::Key>,
+) -> Box<NfsFuture<Verification>> {
+    trace!("Verifying file {:?}", file);
+
+    let data_map_name = *file.data_map_name();
+    let client2 = client.clone();
+
+    immutable_data::verify(&client, &data_map_name, encryption_key.clone())
+        .map_err(NfsError::from)
+        .and_then(move |verification| {
+            if !verification.is_ok() {
+                return ok!(verification);
+            }
+
+            data_map::get(&client2, &data_map_name, encryption_key)
+                .and_then(move |data_map| {
+                    immutable_data::verify_data_map(&client2, &data_map).map_err(NfsError::from)
+                }).map(move |chunks| {
+                    let mut verification = verification;
+                    verification.merge(chunks);
+                    verification
+                }).into_box()
+        }).into_box()
+}
+
+/// Delete a file from the directory, along with its history.
 ///
 /// If `version` is `Version::GetNext`, the current version is first retrieved from the network, and
 /// that version incremented by one is then used as the actual version.
@@ -110,6 +194,8 @@ where
     trace!("Deleting file with name {}.", name);
 
     let key = fry!(parent.enc_entry_key(name.as_bytes()));
+    let history_key = fry!(history::history_key(&parent, name));
+    let history_fut = lookup(&client, &parent, history_key.clone());
 
     let version_fut = match version {
         Version::GetNext => client
@@ -121,20 +207,26 @@ where
     .map_err(NfsError::from);
 
     version_fut
-        .and_then(move |version| {
+        .join(history_fut)
+        .and_then(move |(version, history)| {
+            let mut actions = EntryActions::new().del(key, version);
+            match history {
+                Some(ref history) if !history.content.is_empty() => {
+                    actions = actions.del(history_key, history.entry_version + 1);
+                }
+                _ => (),
+            }
+
             client
-                .mutate_mdata_entries(
-                    parent.name,
-                    parent.type_tag,
-                    EntryActions::new().del(key, version).into(),
-                )
+                .mutate_mdata_entries(parent.name, parent.type_tag, actions.into())
                 .map(move |()| version)
                 .map_err(convert_error)
         })
         .into_box()
 }
 
-/// Update the file.
+/// Update the file. Its history is left unchanged, use `history::update` to record the replaced
+/// version in it.
 ///
 /// If `version` is `Version::GetNext`, the current version is first retrieved from the network, and
 /// that version incremented by one is then used as the actual version.
@@ -151,37 +243,181 @@ where
     let name = name.as_ref();
     trace!("Updating file with name '{}'", name);
 
+    let key = fry!(parent.enc_entry_key(name.as_bytes()));
+    let content = fry!(
+        serialise(&file)
+            .map_err(NfsError::from)
+            .and_then(|encoded| Ok(parent.enc_entry_value(&encoded)?))
+    );
+
     let client2 = client.clone();
 
-    serialise(&file)
-        .map_err(From::from)
-        .and_then(|encoded| {
-            let key = parent.enc_entry_key(name.as_bytes())?;
-            let content = parent.enc_entry_value(&encoded)?;
+    let version_fut = match version {
+        Version::GetNext => client
+            .get_mdata_value(parent.name, parent.type_tag, key.clone())
+            .map(move |value| value.entry_version + 1)
+            .into_box(),
+        Version::Custom(version) => ok!(version),
+    };
 
-            Ok((key, content))
-        })
-        .into_future()
-        .and_then(move |(key, content)| match version {
-            Version::GetNext => client
-                .get_mdata_value(parent.name, parent.type_tag, key.clone())
-                .map(move |value| (key, content, value.entry_version + 1, parent))
-                .into_box(),
-            Version::Custom(version) => ok!((key, content, version, parent)),
-        })
-        .and_then(move |(key, content, version, parent)| {
+    version_fut
+        .and_then(move |version| {
             client2
                 .mutate_mdata_entries(
                     parent.name,
                     parent.type_tag,
                     EntryActions::new().update(key, content, version).into(),
-                )
-                .map(move |()| version)
-        })
-        .map_err(convert_error)
+                ).map(move |()| version)
+        }).map_err(convert_error)
         .into_box()
 }
 
+/// Move the file `src_name` from `src_parent` to `dst_name` in `dst_parent`, along with its
+/// history.
+///
+/// Within a single directory the move is performed as one atomic mutation. Between two
+/// directories the file is first stored in the destination and only then deleted from the source.
+/// If the move gets interrupted in between, calling `rename` again with the same arguments
+/// completes it: when the destination already holds the file only the source entries get
+/// deleted, and when the source entry is gone but the destination exists the move is considered
+/// done. Fails with `NfsError::FileExists` if the destination holds a different file, and with
+/// `NfsError::Conflict` if any of the entries was changed concurrently, since the mutations only
+/// apply to the entry versions looked up at the start of the move.
+pub fn rename<S, T>(
+    client: impl Client,
+    src_parent: MDataInfo,
+    src_name: S,
+    dst_parent: MDataInfo,
+    dst_name: T,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: AsRef<str>,
+{
+    let src_name = src_name.as_ref().to_string();
+    let dst_name = dst_name.as_ref().to_string();
+    trace!("Moving file '{}' to '{}'", src_name, dst_name);
+
+    fry!(validate_name(&dst_name));
+    if src_parent == dst_parent && src_name == dst_name {
+        return ok!(());
+    }
+
+    let src_key = fry!(src_parent.enc_entry_key(src_name.as_bytes()));
+    let dst_key = fry!(dst_parent.enc_entry_key(dst_name.as_bytes()));
+    let src_history_key = fry!(history::history_key(&src_parent, &src_name));
+    let dst_history_key = fry!(history::history_key(&dst_parent, &dst_name));
+    let names = btree_map![
+        src_key.clone() => src_name.clone(),
+        src_history_key.clone() => src_name.clone(),
+        dst_key.clone() => dst_name.clone(),
+        dst_history_key.clone() => dst_name.clone()
+    ];
+
+    let src_fut = lookup(&client, &src_parent, src_key.clone());
+    let dst_fut = lookup(&client, &dst_parent, dst_key.clone());
+    let src_history_fut = lookup(&client, &src_parent, src_history_key.clone());
+    let dst_history_fut = lookup(&client, &dst_parent, dst_history_key.clone());
+    let client2 = client.clone();
+    let src_parent2 = src_parent.clone();
+    let dst_parent2 = dst_parent.clone();
+
+    src_fut
+        .join4(dst_fut, src_history_fut, dst_history_fut)
+        .and_then(move |(src_value, dst_value, src_history, dst_history)| {
+            let src_file = fry!(decode_file(&src_parent2, src_value.as_ref()));
+            let dst_file = fry!(decode_file(&dst_parent2, dst_value.as_ref()));
+
+            let src = match (src_value, src_file) {
+                (Some(value), Some(file)) => (value.entry_version, file),
+                // Already moved by an earlier, interrupted attempt.
+                (_, None) if dst_file.is_some() => return ok!(None),
+                _ => return err!(NfsError::FileNotFound),
+            };
+            let histories = (src_history, dst_history);
+
+            match dst_file {
+                Some(dst_file) => {
+                    is_same_file(&client2, &src.1, &src_parent2, &dst_file, &dst_parent2)
+                        .and_then(move |same| {
+                            if same {
+                                Ok(Some((src, None, histories)))
+                            } else {
+                                Err(NfsError::FileExists)
+                            }
+                        }).into_box()
+                }
+                None => ok!(Some((src, Some(dst_value), histories))),
+            }
+        }).and_then(move |state| {
+            let ((src_version, file), dst_value, (src_history, dst_history)) = match state {
+                Some(state) => state,
+                None => return ok!(()),
+            };
+
+            let mut src_actions = btree_map![src_key => EntryAction::Del(src_version + 1)];
+            if let Some(ref value) = src_history {
+                if !value.content.is_empty() {
+                    let action = EntryAction::Del(value.entry_version + 1);
+                    let _ = src_actions.insert(src_history_key, action);
+                }
+            }
+
+            // The destination already holds the file, only the source is left to be deleted.
+            let dst_value = match dst_value {
+                Some(dst_value) => dst_value,
+                None => return mutate_entries(&client, &src_parent, src_actions, names),
+            };
+
+            let client2 = client.clone();
+            let history_fut = history::relocate(
+                &client,
+                &src_parent,
+                src_history.as_ref(),
+                &dst_parent,
+                dst_history.as_ref(),
+            );
+
+            reencrypt_data_map(&client, file, &src_parent, &dst_parent)
+                .join(history_fut)
+                .and_then(move |(file, history_action)| {
+                    let encoded = serialise(&file)?;
+                    let content = dst_parent.enc_entry_value(&encoded)?;
+                    let dst_ins = match dst_value {
+                        Some(value) => EntryAction::Update(Value {
+                            content,
+                            entry_version: value.entry_version + 1,
+                        }),
+                        None => EntryAction::Ins(Value {
+                            content,
+                            entry_version: 0,
+                        }),
+                    };
+
+                    let mut dst_actions = btree_map![dst_key => dst_ins];
+                    if let Some(history_action) = history_action {
+                        let _ = dst_actions.insert(dst_history_key, history_action);
+                    }
+                    Ok((dst_parent, dst_actions))
+                }).and_then(move |(dst_parent, mut dst_actions)| {
+                    let same_parent = src_parent.name == dst_parent.name
+                        && src_parent.type_tag == dst_parent.type_tag;
+
+                    if same_parent {
+                        dst_actions.extend(src_actions);
+                        return mutate_entries(&client2, &src_parent, dst_actions, names);
+                    }
+
+                    let client3 = client2.clone();
+
+                    mutate_entries(&client2, &dst_parent, dst_actions, names.clone())
+                        .and_then(move |()| {
+                            mutate_entries(&client3, &src_parent, src_actions, names)
+                        }).into_box()
+                }).into_box()
+        }).into_box()
+}
+
 /// Helper function to update content of a file in a directory. A Writer
 /// object is returned, through which the data for the file can be written to
 /// the network. The file is actually saved in the directory listing only after
@@ -203,10 +439,154 @@ pub fn write<C: Client>(
     )
 }
 
+/// Returns a writer like `write`, which doesn't put the chunks that are stored on the network
+/// already. The skipped chunks are counted in `dedup_stats`, see
+/// `SelfEncryptionStorage::deduplicating`.
+pub fn write_deduplicating<C: Client>(
+    client: C,
+    file: File,
+    mode: Mode,
+    encryption_key: Option<shared_secretbox::Key>,
+    dedup_stats: DedupStats,
+) -> Box<NfsFuture<Writer<C>>> {
+    trace!("Creating a deduplicating writer for a file");
+
+    Writer::new(
+        &client.clone(),
+        SelfEncryptionStorage::deduplicating(client, dedup_stats),
+        file,
+        mode,
+        encryption_key,
+    )
+}
+
+/// Returns a writer resuming the upload from the checkpoint saved at `path`, see
+/// `Writer::checkpoint`.
+pub fn resume_write<C: Client, P: AsRef<Path>>(
+    client: C,
+    path: P,
+    encryption_key: Option<shared_secretbox::Key>,
+) -> Box<NfsFuture<Writer<C>>> {
+    trace!("Resuming a writer for a file");
+
+    Writer::resume(&client, path, encryption_key)
+}
+
+// Get the raw value of the directory entry, or `None` if there is no such entry.
+pub(crate) fn lookup(
+    client: &impl Client,
+    parent: &MDataInfo,
+    key: Vec<u8>,
+) -> Box<NfsFuture<Option<Value>>> {
+    client
+        .get_mdata_value(parent.name, parent.type_tag, key)
+        .map(Some)
+        .or_else(|error| match error {
+            CoreError::RoutingClientError(ClientError::NoSuchEntry) => Ok(None),
+            error => Err(NfsError::from(error)),
+        }).into_box()
+}
+
+// Decode the file stored in the directory entry. Returns `None` for missing or deleted entries.
+pub(crate) fn decode_file(
+    parent: &MDataInfo,
+    value: Option<&Value>,
+) -> Result<Option<File>, NfsError> {
+    match value {
+        Some(value) if !value.content.is_empty() => {
+            let plaintext = parent.decrypt(&value.content)?;
+            Ok(Some(deserialise(&plaintext)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Two copies of a file are the same if they differ at most in where their data map is stored.
+// Data maps stored under different names are fetched to compare their contents, as the same data
+// map is stored anew when a file is moved to a directory with a different key.
+fn is_same_file(
+    client: &impl Client,
+    a: &File,
+    a_parent: &MDataInfo,
+    b: &File,
+    b_parent: &MDataInfo,
+) -> Box<NfsFuture<bool>> {
+    let mut a_relocated = a.clone();
+    a_relocated.set_data_map_name(*b.data_map_name());
+    if a_relocated != *b {
+        return ok!(false);
+    }
+    if a.data_map_name() == b.data_map_name() {
+        return ok!(true);
+    }
+
+    let a_fut = data_map::get(client, a.data_map_name(), a_parent.enc_key().cloned());
+    let b_fut = data_map::get(client, b.data_map_name(), b_parent.enc_key().cloned());
+
+    a_fut
+        .join(b_fut)
+        .map(|(a_data_map, b_data_map)| a_data_map == b_data_map)
+        .into_box()
+}
+
+// The data map of a file is encrypted with the key of its directory, so moving the file to
+// a directory with a different key requires storing the data map anew.
+pub(crate) fn reencrypt_data_map(
+    client: &impl Client,
+    mut file: File,
+    src_parent: &MDataInfo,
+    dst_parent: &MDataInfo,
+) -> Box<NfsFuture<File>> {
+    if src_parent.enc_key() == dst_parent.enc_key() {
+        return ok!(file);
+    }
+
+    let client2 = client.clone();
+    let dst_key = dst_parent.enc_key().cloned();
+
+    data_map::get(client, file.data_map_name(), src_parent.enc_key().cloned())
+        .and_then(move |data_map| data_map::put(&client2, &data_map, dst_key))
+        .map(move |data_map_name| {
+            file.set_data_map_name(data_map_name);
+            file
+        }).into_box()
+}
+
+// File names may not contain the suffix of subdirectory entries, or they would clash with them.
+pub(crate) fn validate_name(name: &str) -> Result<(), NfsError> {
+    if name.contains(DIR_ENTRY_SUFFIX) {
+        Err(NfsError::InvalidPath)
+    } else {
+        Ok(())
+    }
+}
+
 // This is different from `impl From<CoreError> for NfsError`, because it maps
 // `NoSuchEntry` to `FileNotFound`.
 // TODO:  consider performing such conversion directly in the mentioned `impl From`.
-fn convert_error(err: CoreError) -> NfsError {
+// Apply the entry actions to the directory. Entry errors reported by the network are returned as
+// `NfsError::Conflict`, with the entry keys translated to file names using `names`.
+fn mutate_entries(
+    client: &impl Client,
+    parent: &MDataInfo,
+    actions: BTreeMap<Vec<u8>, EntryAction>,
+    names: BTreeMap<Vec<u8>, String>,
+) -> Box<NfsFuture<()>> {
+    client
+        .mutate_mdata_entries(parent.name, parent.type_tag, actions)
+        .map_err(move |error| match error {
+            CoreError::RoutingClientError(ClientError::InvalidEntryActions(errors)) => {
+                let conflicts = errors
+                    .into_iter()
+                    .filter_map(|(key, error)| names.get(&key).map(|name| (name.clone(), error)))
+                    .collect();
+                NfsError::Conflict(conflicts)
+            }
+            error => convert_error(error),
+        }).into_box()
+}
+
+pub(crate) fn convert_error(err: CoreError) -> NfsError {
     match err {
         CoreError::RoutingClientError(ClientError::NoSuchEntry) => NfsError::FileNotFound,
         _ => NfsError::from(err),
@@ -0,0 +1,258 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Keeping the previous versions of files is opt-in: whenever a file is updated through
+//! `history::update` (or `Batch::update_with_history`), the replaced `File` is recorded in the
+//! history of the file. Updates through `file_helper::update` leave the history unchanged.
+//!
+//! The history is stored as an entry of the parent directory, keyed by the file name followed by
+//! `HISTORY_ENTRY_SUFFIX`, and is updated in the same mutation as the file entry itself. Only the
+//! last `MAX_FILE_VERSIONS` versions are kept. As the history holds the serialised previous
+//! versions, it takes an entry of the directory and counts towards its size limit; a directory
+//! close to the limits of a `MutableData` may not fit the histories of its files. Deleting the
+//! file deletes its history too, and moving it with `file_helper::rename` moves its history along.
+
+use chrono::Utc;
+use crate::client::{Client, MDataInfo};
+use futures::future;
+use futures::Future;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use crate::nfs::file_helper::{self, convert_error, decode_file, lookup, Version};
+use crate::nfs::{File, NfsError, NfsFuture, Reader};
+use routing::{EntryAction, Value};
+use crate::utils::FutureExt;
+
+/// Suffix appended to a file name to form the entry key of its history in the parent directory.
+pub const HISTORY_ENTRY_SUFFIX: &str = "/history";
+/// Maximum number of previous versions kept in the history of a file.
+pub const MAX_FILE_VERSIONS: usize = 10;
+
+/// Previous version of a file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FileVersion {
+    /// Version of the directory entry holding this version of the file.
+    pub version: u64,
+    /// The file as it was at that version.
+    pub file: File,
+}
+
+/// Get the previous versions of the file, oldest first.
+pub fn list<S>(client: impl Client, parent: MDataInfo, name: S) -> Box<NfsFuture<Vec<FileVersion>>>
+where
+    S: AsRef<str>,
+{
+    trace!("Listing versions of file '{}'", name.as_ref());
+
+    let key = fry!(history_key(&parent, name.as_ref()));
+
+    file_helper::lookup(&client, &parent, key)
+        .and_then(move |value| decode(&parent, value.as_ref()))
+        .into_box()
+}
+
+/// Get the file as it was at the given entry version.
+pub fn fetch<S>(
+    client: impl Client,
+    parent: MDataInfo,
+    name: S,
+    version: u64,
+) -> Box<NfsFuture<File>>
+where
+    S: AsRef<str>,
+{
+    list(client, parent, name)
+        .and_then(move |versions| {
+            versions
+                .into_iter()
+                .find(|file_version| file_version.version == version)
+                .map(|file_version| file_version.file)
+                .ok_or(NfsError::FileNotFound)
+        }).into_box()
+}
+
+/// Return a Reader for reading the contents of the file as it was at the given entry version.
+pub fn read<C, S>(client: C, parent: MDataInfo, name: S, version: u64) -> Box<NfsFuture<Reader<C>>>
+where
+    C: Client,
+    S: AsRef<str>,
+{
+    let encryption_key = parent.enc_key().cloned();
+
+    fetch(client.clone(), parent, name, version)
+        .and_then(move |file| file_helper::read(client, &file, encryption_key))
+        .into_box()
+}
+
+/// Update the file, recording the replaced version in its history. Returns the new entry version
+/// of the file.
+///
+/// If `version` is `Version::GetNext`, the current version is first retrieved from the network, and
+/// that version incremented by one is then used as the actual version.
+pub fn update<S>(
+    client: impl Client,
+    parent: MDataInfo,
+    name: S,
+    file: &File,
+    version: Version,
+) -> Box<NfsFuture<u64>>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref();
+    trace!("Updating file with name '{}', keeping its history", name);
+
+    let key = fry!(parent.enc_entry_key(name.as_bytes()));
+    let history_key = fry!(history_key(&parent, name));
+    let content = fry!(
+        serialise(&file)
+            .map_err(NfsError::from)
+            .and_then(|encoded| Ok(parent.enc_entry_value(&encoded)?))
+    );
+
+    let client2 = client.clone();
+
+    lookup(&client, &parent, key.clone())
+        .join(lookup(&client, &parent, history_key.clone()))
+        .and_then(move |(current, history)| {
+            let version = match (version, current.as_ref()) {
+                (Version::GetNext, Some(value)) => value.entry_version + 1,
+                (Version::GetNext, None) => return Err(NfsError::FileNotFound),
+                (Version::Custom(version), _) => version,
+            };
+
+            let mut actions = btree_map![key => EntryAction::Update(Value {
+                content,
+                entry_version: version,
+            })];
+
+            if let Some(previous) = decode_file(&parent, current.as_ref())? {
+                let previous = FileVersion {
+                    version: current.map_or(0, |value| value.entry_version),
+                    file: previous,
+                };
+                let action = record(&parent, history.as_ref(), previous)?;
+                let _ = actions.insert(history_key, action);
+            }
+
+            Ok((parent, actions, version))
+        }).and_then(move |(parent, actions, version)| {
+            client2
+                .mutate_mdata_entries(parent.name, parent.type_tag, actions)
+                .map(move |()| version)
+                .map_err(convert_error)
+        }).into_box()
+}
+
+/// Restore the file to the given previous version. The version being replaced is recorded in the
+/// history as with `update`. Returns the new entry version of the file.
+pub fn restore<S>(
+    client: impl Client,
+    parent: MDataInfo,
+    name: S,
+    version: u64,
+) -> Box<NfsFuture<u64>>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref().to_string();
+    trace!("Restoring file '{}' to version {}", name, version);
+
+    fetch(client.clone(), parent.clone(), &name, version)
+        .and_then(move |mut file| {
+            file.set_modified_time(Utc::now());
+            update(client, parent, name, &file, Version::GetNext)
+        }).into_box()
+}
+
+// Encrypted key of the history entry of the file.
+pub(crate) fn history_key(parent: &MDataInfo, name: &str) -> Result<Vec<u8>, NfsError> {
+    let key = format!("{}{}", name, HISTORY_ENTRY_SUFFIX);
+    Ok(parent.enc_entry_key(key.as_bytes())?)
+}
+
+pub(crate) fn is_history_entry(name: &str) -> bool {
+    name.ends_with(HISTORY_ENTRY_SUFFIX)
+}
+
+// Action appending `previous` to the history currently stored in `current`, dropping the oldest
+// versions over the limit.
+pub(crate) fn record(
+    parent: &MDataInfo,
+    current: Option<&Value>,
+    previous: FileVersion,
+) -> Result<EntryAction, NfsError> {
+    let mut versions = decode(parent, current)?;
+    versions.push(previous);
+    if versions.len() > MAX_FILE_VERSIONS {
+        let excess = versions.len() - MAX_FILE_VERSIONS;
+        let _ = versions.drain(..excess);
+    }
+
+    let content = parent.enc_entry_value(&serialise(&versions)?)?;
+
+    Ok(match current {
+        Some(value) => EntryAction::Update(Value {
+            content,
+            entry_version: value.entry_version + 1,
+        }),
+        None => EntryAction::Ins(Value {
+            content,
+            entry_version: 0,
+        }),
+    })
+}
+
+// Action storing the history `src` of a file moved from `src_parent` in `dst_parent`, replacing
+// the history `dst` left there, or `None` if the file has no history. The data maps of the
+// previous versions are stored anew if the directories have different keys.
+pub(crate) fn relocate(
+    client: &impl Client,
+    src_parent: &MDataInfo,
+    src: Option<&Value>,
+    dst_parent: &MDataInfo,
+    dst: Option<&Value>,
+) -> Box<NfsFuture<Option<EntryAction>>> {
+    let versions = fry!(decode(src_parent, src));
+    if versions.is_empty() {
+        return ok!(None);
+    }
+
+    let dst_parent = dst_parent.clone();
+    let dst_version = dst.map(|value| value.entry_version + 1);
+    let futs: Vec<_> = versions
+        .into_iter()
+        .map(|FileVersion { version, file }| {
+            file_helper::reencrypt_data_map(client, file, src_parent, &dst_parent)
+                .map(move |file| FileVersion { version, file })
+        }).collect();
+
+    future::join_all(futs)
+        .and_then(move |versions| {
+            let content = dst_parent.enc_entry_value(&serialise(&versions)?)?;
+            Ok(Some(match dst_version {
+                Some(entry_version) => EntryAction::Update(Value {
+                    content,
+                    entry_version,
+                }),
+                None => EntryAction::Ins(Value {
+                    content,
+                    entry_version: 0,
+                }),
+            }))
+        }).into_box()
+}
+
+fn decode(parent: &MDataInfo, value: Option<&Value>) -> Result<Vec<FileVersion>, NfsError> {
+    match value {
+        Some(value) if !value.content.is_empty() => {
+            let plaintext = parent.decrypt(&value.content)?;
+            Ok(deserialise(&plaintext)?)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
@@ -6,21 +6,39 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+/// `Archive` provides functions for exporting and importing directory trees as tar archives.
+pub mod archive;
+/// `Batch` applies several file mutations in a directory atomically.
+pub mod batch;
 /// `FileHelper` provides functions for CRUD on file.
 pub mod file_helper;
+/// `History` provides functions for listing and restoring previous versions of files.
+pub mod history;
+/// `PathHelper` provides functions for working with nested directories by path.
+pub mod path_helper;
+/// `Reachability` provides functions for finding the data referenced from directory trees.
+pub mod reachability;
+/// `Sync` mirrors local directory trees into NFS directories.
+pub mod sync;
+/// `Watcher` reports changes made to directories by polling their versions.
+pub mod watcher;
 
+mod compression;
 mod data_map;
 mod dir;
 mod errors;
 mod file;
+mod read_stream;
 mod reader;
 #[cfg(test)]
 mod tests;
 mod writer;
 
+pub use self::compression::Compression;
 pub use self::dir::create_dir;
 pub use self::errors::NfsError;
 pub use self::file::File;
+pub use self::read_stream::{ReadStream, DEFAULT_READ_AHEAD};
 pub use self::reader::Reader;
 pub use self::writer::{Mode, Writer};
 use futures::Future;
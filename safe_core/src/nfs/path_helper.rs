@@ -0,0 +1,395 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Subdirectories are stored as entries of their parent directory, keyed by the directory name
+//! followed by `DIR_ENTRY_SUFFIX` and holding the serialised `MDataInfo` of the subdirectory.
+//! As file names may not contain a `/` (`file_helper` rejects them with `NfsError::InvalidPath`),
+//! directory entries never clash with file entries.
+
+use crate::client::mdata_info::is_hidden_entry;
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use futures::future::{self, Loop};
+use futures::Future;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use crate::nfs::file_helper::{self, convert_error};
+use crate::nfs::history;
+use crate::nfs::{create_dir, File, NfsError, NfsFuture};
+use routing::{ClientError, EntryActions, Value};
+use std::collections::BTreeMap;
+use crate::utils::FutureExt;
+use crate::DIR_TAG;
+
+/// Suffix appended to a subdirectory name to form its entry key in the parent directory.
+pub const DIR_ENTRY_SUFFIX: &str = "/";
+
+/// Item stored at a path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Entry {
+    /// A file, along with the version of its entry in the parent directory.
+    File {
+        /// Version of the entry.
+        version: u64,
+        /// The file itself.
+        file: File,
+    },
+    /// A subdirectory.
+    Dir(MDataInfo),
+}
+
+/// Split the path into its components. Empty components and `.` are skipped, while `..` is
+/// rejected.
+pub fn split_path(path: &str) -> Result<Vec<String>, NfsError> {
+    let mut components = Vec::new();
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => return Err(NfsError::InvalidPath),
+            name => components.push(name.to_string()),
+        }
+    }
+
+    Ok(components)
+}
+
+/// Resolve the directory at the given path, relative to `root`.
+pub fn resolve(client: impl Client, root: MDataInfo, path: &str) -> Box<NfsFuture<MDataInfo>> {
+    trace!("Resolving directory '{}'", path);
+
+    let components = fry!(split_path(path));
+    walk(client, root, components, false)
+}
+
+/// Get the file or directory at the given path.
+pub fn stat(client: impl Client, root: MDataInfo, path: &str) -> Box<NfsFuture<Entry>> {
+    trace!("Stat for '{}'", path);
+
+    let mut components = fry!(split_path(path));
+    let name = match components.pop() {
+        Some(name) => name,
+        None => return ok!(Entry::Dir(root)),
+    };
+
+    walk(client.clone(), root, components, false)
+        .and_then(move |parent| {
+            fetch_subdir(&client, &parent, &name)
+                .map(|(_, dir)| Entry::Dir(dir))
+                .or_else(move |error| match error {
+                    NfsError::FileNotFound => file_helper::fetch(client, parent, name)
+                        .map(|(version, file)| Entry::File { version, file })
+                        .into_box(),
+                    error => err!(error),
+                })
+        }).into_box()
+}
+
+/// List the files and subdirectories of the directory at the given path.
+pub fn list(
+    client: impl Client,
+    root: MDataInfo,
+    path: &str,
+) -> Box<NfsFuture<Vec<(String, Entry)>>> {
+    trace!("Listing directory '{}'", path);
+
+    resolve(client.clone(), root, path)
+        .and_then(move |dir| {
+            client
+                .list_mdata_entries(dir.name, dir.type_tag)
+                .map_err(NfsError::from)
+                .and_then(move |entries| decode_entries(&dir, entries))
+        }).into_box()
+}
+
+/// Create the directory at the given path, along with any missing intermediate directories.
+/// Fails with `NfsError::FileExists` if the directory already exists.
+pub fn mkdir(client: impl Client, root: MDataInfo, path: &str) -> Box<NfsFuture<MDataInfo>> {
+    trace!("Creating directory '{}'", path);
+
+    let mut components = fry!(split_path(path));
+    let name = match components.pop() {
+        Some(name) => name,
+        None => return err!(NfsError::FileExists),
+    };
+
+    walk(client.clone(), root, components, true)
+        .and_then(move |parent| {
+            let key = fry!(subdir_key(&parent, &name));
+            file_helper::lookup(&client, &parent, key)
+                .and_then(move |current| match current {
+                    Some(ref value) if !value.content.is_empty() => err!(NfsError::FileExists),
+                    current => create_subdir(client, parent, &name, current),
+                }).into_box()
+        }).into_box()
+}
+
+/// Get the directory at the given path, creating it and any missing intermediate directories.
+pub fn ensure_dir(client: impl Client, root: MDataInfo, path: &str) -> Box<NfsFuture<MDataInfo>> {
+    trace!("Ensuring directory '{}' exists", path);
+
+    let components = fry!(split_path(path));
+    walk(client, root, components, true)
+}
+
+/// Remove the directory at the given path. The directory has to be empty.
+pub fn rmdir(client: impl Client, root: MDataInfo, path: &str) -> Box<NfsFuture<()>> {
+    trace!("Removing directory '{}'", path);
+
+    let mut components = fry!(split_path(path));
+    let name = match components.pop() {
+        Some(name) => name,
+        None => return err!(NfsError::InvalidPath),
+    };
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    walk(client.clone(), root, components, false)
+        .and_then(move |parent| {
+            fetch_subdir(&client, &parent, &name)
+                .map(move |(version, dir)| (parent, name, version, dir))
+        }).and_then(move |(parent, name, version, dir)| {
+            client2
+                .list_mdata_entries(dir.name, dir.type_tag)
+                .map_err(NfsError::from)
+                .and_then(move |entries| {
+                    if !decode_entries(&dir, entries)?.is_empty() {
+                        Err(NfsError::DirectoryNotEmpty)
+                    } else {
+                        Ok((parent, name, version))
+                    }
+                })
+        }).and_then(move |(parent, name, version)| {
+            let key = fry!(parent.enc_entry_key(dir_entry_key(&name).as_bytes()));
+            client3
+                .mutate_mdata_entries(
+                    parent.name,
+                    parent.type_tag,
+                    EntryActions::new().del(key, version + 1).into(),
+                ).map_err(convert_error)
+                .into_box()
+        }).into_box()
+}
+
+/// Insert the file at the given path, creating any missing intermediate directories.
+pub fn insert_file(
+    client: impl Client,
+    root: MDataInfo,
+    path: &str,
+    file: &File,
+) -> Box<NfsFuture<()>> {
+    trace!("Inserting file at '{}'", path);
+
+    let mut components = fry!(split_path(path));
+    let name = match components.pop() {
+        Some(name) => name,
+        None => return err!(NfsError::InvalidPath),
+    };
+    let file = file.clone();
+
+    walk(client.clone(), root, components, true)
+        .and_then(move |parent| file_helper::insert(client, parent, name, &file))
+        .into_box()
+}
+
+/// Get the file at the given path, along with the version of its entry.
+pub fn fetch_file(
+    client: impl Client,
+    root: MDataInfo,
+    path: &str,
+) -> Box<NfsFuture<(u64, File)>> {
+    trace!("Fetching file at '{}'", path);
+
+    let mut components = fry!(split_path(path));
+    let name = match components.pop() {
+        Some(name) => name,
+        None => return err!(NfsError::InvalidPath),
+    };
+
+    walk(client.clone(), root, components, false)
+        .and_then(move |parent| file_helper::fetch(client, parent, name))
+        .into_box()
+}
+
+// Descend from `root` through the given path components. If `create` is true, missing
+// directories are created on the way.
+fn walk(
+    client: impl Client,
+    root: MDataInfo,
+    components: Vec<String>,
+    create: bool,
+) -> Box<NfsFuture<MDataInfo>> {
+    future::loop_fn(
+        (root, components.into_iter()),
+        move |(dir, mut components)| match components.next() {
+            Some(name) => {
+                let fut = if create {
+                    ensure_subdir(client.clone(), dir, name)
+                } else {
+                    fetch_subdir(&client, &dir, &name)
+                        .map(|(_, subdir)| subdir)
+                        .into_box()
+                };
+                fut.map(move |subdir| Loop::Continue((subdir, components)))
+                    .into_box()
+            }
+            None => ok!(Loop::Break(dir)),
+        },
+    ).into_box()
+}
+
+// Get the subdirectory with the given name, creating it if it doesn't exist yet.
+fn ensure_subdir(
+    client: impl Client,
+    parent: MDataInfo,
+    name: String,
+) -> Box<NfsFuture<MDataInfo>> {
+    let key = fry!(subdir_key(&parent, &name));
+    file_helper::lookup(&client, &parent, key)
+        .and_then(move |current| match current {
+            Some(ref value) if !value.content.is_empty() => {
+                future::result(decode_subdir(&parent, value)).into_box()
+            }
+            current => {
+                let client2 = client.clone();
+                let parent2 = parent.clone();
+
+                // Someone else might have created the directory in the meantime.
+                create_subdir(client, parent, &name, current)
+                    .or_else(move |error| match error {
+                        NfsError::FileExists => fetch_subdir(&client2, &parent2, &name)
+                            .map(|(_, dir)| dir)
+                            .into_box(),
+                        error => err!(error),
+                    }).into_box()
+            }
+        }).into_box()
+}
+
+// Create a new directory and link it into `parent` under `name`. `current` is the current value
+// of the entry in `parent` (if any), which is expected to be deleted.
+fn create_subdir(
+    client: impl Client,
+    parent: MDataInfo,
+    name: &str,
+    current: Option<Value>,
+) -> Box<NfsFuture<MDataInfo>> {
+    let dir = if parent.enc_info.is_some() {
+        fry!(MDataInfo::random_private(DIR_TAG))
+    } else {
+        fry!(MDataInfo::random_public(DIR_TAG))
+    };
+
+    let key = fry!(subdir_key(&parent, name));
+    let content = fry!(serialise(&dir)
+        .map_err(CoreError::from)
+        .and_then(|encoded| parent.enc_entry_value(&encoded)));
+    let actions = match current {
+        Some(value) => EntryActions::new().update(key, content, value.entry_version + 1),
+        None => EntryActions::new().ins(key, content, 0),
+    };
+
+    let client2 = client.clone();
+
+    create_dir(&client, &dir, btree_map![], btree_map![])
+        .and_then(move |()| {
+            client2
+                .mutate_mdata_entries(parent.name, parent.type_tag, actions.into())
+                .map_err(|error| match error {
+                    CoreError::RoutingClientError(ClientError::InvalidEntryActions(_)) => {
+                        NfsError::FileExists
+                    }
+                    error => NfsError::from(error),
+                })
+        }).map(move |()| dir)
+        .into_box()
+}
+
+// Get the subdirectory entry with the given name, returning its version and `MDataInfo`.
+fn fetch_subdir(
+    client: &impl Client,
+    parent: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<(u64, MDataInfo)>> {
+    let key = fry!(subdir_key(parent, name));
+    let parent = parent.clone();
+
+    file_helper::lookup(client, &parent, key)
+        .and_then(move |current| match current {
+            Some(ref value) if !value.content.is_empty() => {
+                Ok((value.entry_version, decode_subdir(&parent, value)?))
+            }
+            _ => Err(NfsError::FileNotFound),
+        }).into_box()
+}
+
+// Encrypted key of the subdirectory entry with the given name.
+fn subdir_key(parent: &MDataInfo, name: &str) -> Result<Vec<u8>, NfsError> {
+    Ok(parent.enc_entry_key(dir_entry_key(name).as_bytes())?)
+}
+
+fn decode_subdir(parent: &MDataInfo, value: &Value) -> Result<MDataInfo, NfsError> {
+    let plaintext = parent.decrypt(&value.content)?;
+    Ok(deserialise(&plaintext)?)
+}
+
+fn decode_entries(
+    dir: &MDataInfo,
+    entries: BTreeMap<Vec<u8>, Value>,
+) -> Result<Vec<(String, Entry)>, NfsError> {
+    let mut output = Vec::with_capacity(entries.len());
+
+    for (key, value) in entries {
+        if is_hidden_entry(&key, &value) {
+            continue;
+        }
+
+        let name = String::from_utf8(dir.decrypt(&key)?)
+            .map_err(|_| NfsError::from("Entry name is not valid UTF-8"))?;
+        if history::is_history_entry(&name) {
+            continue;
+        }
+
+        let plaintext = dir.decrypt(&value.content)?;
+
+        if name.ends_with(DIR_ENTRY_SUFFIX) {
+            let name = name[..name.len() - DIR_ENTRY_SUFFIX.len()].to_string();
+            output.push((name, Entry::Dir(deserialise(&plaintext)?)));
+        } else {
+            let file = deserialise(&plaintext)?;
+            output.push((
+                name,
+                Entry::File {
+                    version: value.entry_version,
+                    file,
+                },
+            ));
+        }
+    }
+
+    Ok(output)
+}
+
+fn dir_entry_key(name: &str) -> String {
+    format!("{}{}", name, DIR_ENTRY_SUFFIX)
+}
+
+// Split the path into the path of the parent directory and the last component.
+pub(crate) fn split_parent(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(index) => (&path[..index], &path[index + 1..]),
+        None => ("", path),
+    }
+}
+
+pub(crate) fn join_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
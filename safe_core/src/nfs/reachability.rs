@@ -0,0 +1,198 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Updating or deleting a file leaves its previous data map and content chunks on the network,
+//! unreachable from any directory. The walker in this module traverses directory trees and
+//! collects the names of every `ImmutableData` still referenced from them: the data maps of all
+//! the files, including the versions kept in their history, along with the self-encryption chunks
+//! of both the data maps and the file contents. Comparing those against the data known to be
+//! stored gives the orphaned chunks.
+//!
+//! Entries that can't be decoded as directory or file entries (e.g. application specific data
+//! kept in a container) are skipped and counted in the report. Directories which can't be listed
+//! and files whose data maps can't be fetched are recorded as failures, and the walk carries on
+//! with the rest of the tree.
+
+use crate::client::mdata_info::is_hidden_entry;
+use crate::client::{Client, MDataInfo};
+use crate::crypto::shared_secretbox;
+use crate::immutable_data;
+use crate::nfs::history::{self, FileVersion};
+use crate::nfs::path_helper::DIR_ENTRY_SUFFIX;
+use crate::nfs::{File, NfsError, NfsFuture};
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::Future;
+use maidsafe_utilities::serialisation::deserialise;
+use routing::{Value, XorName};
+use self_encryption::DataMap;
+use std::collections::BTreeSet;
+
+/// Data found reachable from the traversed directories.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Reachability {
+    /// Mutable data visited, as `(name, type_tag)` pairs.
+    pub mdata: BTreeSet<(XorName, u64)>,
+    /// Names of the referenced `ImmutableData`.
+    pub idata: BTreeSet<XorName>,
+    /// Number of file versions found, counting both current files and their history.
+    pub files: u64,
+    /// Number of entries skipped because they couldn't be decoded.
+    pub skipped_entries: u64,
+    /// Directories and entries whose data couldn't be fetched.
+    pub failures: Vec<Failure>,
+}
+
+/// Data which couldn't be fetched during the walk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Failure {
+    /// Name and type tag of the directory.
+    pub dir: (XorName, u64),
+    /// Name of the entry referencing the data, or `None` if the directory itself couldn't be
+    /// listed.
+    pub entry: Option<String>,
+    /// Description of the error.
+    pub error: String,
+}
+
+impl Reachability {
+    /// Out of the given names of stored `ImmutableData`, return the ones that are not referenced.
+    /// Returns `None` if the walk recorded any failures, as data referenced from the failed
+    /// directories and entries is missing from `idata` and would be reported as orphaned.
+    pub fn orphaned<I>(&self, stored: I) -> Option<BTreeSet<XorName>>
+    where
+        I: IntoIterator<Item = XorName>,
+    {
+        if !self.failures.is_empty() {
+            return None;
+        }
+
+        Some(
+            stored
+                .into_iter()
+                .filter(|name| !self.idata.contains(name))
+                .collect(),
+        )
+    }
+}
+
+enum DecodedEntry {
+    Dir(MDataInfo),
+    Files(Vec<File>),
+}
+
+/// Traverse the directory trees starting at `roots` and collect all the data reachable from them.
+/// Each directory is visited only once, even if it is linked from several places.
+pub fn walk(client: impl Client, roots: Vec<MDataInfo>) -> Box<NfsFuture<Reachability>> {
+    trace!("Walking {} directory trees", roots.len());
+
+    future::loop_fn(
+        (roots, Reachability::default()),
+        move |(mut pending, mut reachability)| {
+            let dir = match pending.pop() {
+                Some(dir) => dir,
+                None => return ok!(Loop::Break(reachability)),
+            };
+            if !reachability.mdata.insert((dir.name, dir.type_tag)) {
+                return ok!(Loop::Continue((pending, reachability)));
+            }
+
+            let client2 = client.clone();
+
+            client
+                .list_mdata_entries(dir.name, dir.type_tag)
+                .then(move |result| {
+                    let entries = match result {
+                        Ok(entries) => entries,
+                        Err(error) => {
+                            reachability.failures.push(Failure {
+                                dir: (dir.name, dir.type_tag),
+                                entry: None,
+                                error: error.to_string(),
+                            });
+                            return ok!(Loop::Continue((pending, reachability)));
+                        }
+                    };
+                    let mut files = Vec::new();
+
+                    for (key, value) in entries {
+                        if is_hidden_entry(&key, &value) {
+                            continue;
+                        }
+                        match decode_entry(&dir, &key, &value) {
+                            Some((_, DecodedEntry::Dir(subdir))) => pending.push(subdir),
+                            Some((name, DecodedEntry::Files(versions))) => {
+                                files.extend(versions.into_iter().map(|file| (name.clone(), file)))
+                            }
+                            None => reachability.skipped_entries += 1,
+                        }
+                    }
+
+                    reachability.files += files.len() as u64;
+
+                    let encryption_key = dir.enc_key().cloned();
+                    let chunks = files.into_iter().map(|(name, file)| {
+                        file_chunks(&client2, &file, encryption_key.clone())
+                            .then(move |result| Ok::<_, NfsError>((name, result)))
+                    });
+
+                    future::join_all(chunks)
+                        .map(move |chunks| {
+                            for (name, result) in chunks {
+                                match result {
+                                    Ok(names) => reachability.idata.extend(names),
+                                    Err(error) => reachability.failures.push(Failure {
+                                        dir: (dir.name, dir.type_tag),
+                                        entry: Some(name),
+                                        error: error.to_string(),
+                                    }),
+                                }
+                            }
+                            Loop::Continue((pending, reachability))
+                        }).into_box()
+                }).into_box()
+        },
+    ).into_box()
+}
+
+/// Get the names of all the `ImmutableData` referenced by the file: its data map and the chunks
+/// holding both the data map and the file contents.
+pub fn file_chunks(
+    client: &impl Client,
+    file: &File,
+    encryption_key: Option<shared_secretbox::Key>,
+) -> Box<NfsFuture<Vec<XorName>>> {
+    // Files which have never been written to don't reference any data.
+    if *file.data_map_name() == XorName::default() {
+        return ok!(Vec::new());
+    }
+
+    immutable_data::get_value_with_chunk_names(client, file.data_map_name(), encryption_key)
+        .map_err(NfsError::from)
+        .and_then(|(content, mut names)| {
+            let data_map: DataMap = deserialise(&content)?;
+            names.extend(immutable_data::data_map_chunk_names(&data_map));
+            Ok(names)
+        }).into_box()
+}
+
+// Decode the entry, returning its name along with the decoded content.
+fn decode_entry(dir: &MDataInfo, key: &[u8], value: &Value) -> Option<(String, DecodedEntry)> {
+    let name = String::from_utf8(dir.decrypt(key).ok()?).ok()?;
+    let plaintext = dir.decrypt(&value.content).ok()?;
+
+    let entry = if name.ends_with(DIR_ENTRY_SUFFIX) {
+        DecodedEntry::Dir(deserialise(&plaintext).ok()?)
+    } else if history::is_history_entry(&name) {
+        let versions: Vec<FileVersion> = deserialise(&plaintext).ok()?;
+        DecodedEntry::Files(versions.into_iter().map(|version| version.file).collect())
+    } else {
+        DecodedEntry::Files(vec![deserialise(&plaintext).ok()?])
+    };
+    Some((name, entry))
+}
@@ -0,0 +1,234 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::client::Client;
+use crate::crypto::shared_secretbox;
+use crate::immutable_data::chunk_ranges;
+use crate::nfs::compression::Decompressor;
+use crate::nfs::{data_map, Compression, File, NfsError, NfsFuture};
+use crate::self_encryption_storage::SelfEncryptionStorage;
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::stream::{self, Stream};
+use futures::{Async, Future, Poll};
+use self_encryption::{DataMap, SelfEncryptor, MAX_CHUNK_SIZE};
+use std::cmp;
+
+/// Default number of chunks fetched ahead of the one currently being read.
+pub const DEFAULT_READ_AHEAD: usize = 4;
+
+/// Stream over the contents of a File. Each item holds the data of at most one self-encryption
+/// chunk, or at most `MAX_CHUNK_SIZE` decompressed bytes for compressed files, and the following
+/// `read_ahead` chunks are fetched from the network in parallel while the current one is being
+/// consumed.
+pub struct ReadStream {
+    size: u64,
+    inner: Box<Stream<Item = Vec<u8>, Error = NfsError>>,
+}
+
+impl ReadStream {
+    /// Create a new stream reading the file contents starting at `position`.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new<C: Client>(
+        client: C,
+        file: &File,
+        encryption_key: Option<shared_secretbox::Key>,
+        position: u64,
+        read_ahead: usize,
+    ) -> Box<NfsFuture<Self>> {
+        let compression = file.compression();
+        let file_size = file.size();
+
+        data_map::get(&client, file.data_map_name(), encryption_key)
+            .and_then(move |data_map| {
+                if compression != Compression::None {
+                    if position > file_size {
+                        return Err(NfsError::InvalidRange);
+                    }
+                    let chunks = read_chunks(client, data_map, 0, read_ahead);
+                    return Ok(ReadStream {
+                        size: file_size,
+                        inner: decompress(chunks, compression, position),
+                    });
+                }
+
+                let size = data_map.len();
+                if position > size {
+                    return Err(NfsError::InvalidRange);
+                }
+
+                Ok(ReadStream {
+                    size,
+                    inner: read_chunks(client, data_map, position, read_ahead),
+                })
+            }).into_box()
+    }
+
+    /// Returns the total size of the file/blob.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Stream for ReadStream {
+    type Item = Vec<u8>;
+    type Error = NfsError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll()
+    }
+}
+
+// Position in the decompressed contents of a file. Reading from it continues decompressing
+// where the previous read stopped, so sequential reads don't decompress the contents again.
+pub(crate) struct DecompressedCursor {
+    position: u64,
+    stream: Box<Stream<Item = Vec<u8>, Error = NfsError>>,
+    // Data decompressed past `position` which hasn't been read yet.
+    pending: Vec<u8>,
+}
+
+impl DecompressedCursor {
+    // Create a cursor at `position`. The contents are decompressed from the start, but the data
+    // before `position` is discarded.
+    pub fn new<C: Client>(
+        client: C,
+        data_map: DataMap,
+        compression: Compression,
+        position: u64,
+    ) -> Self {
+        let chunks = read_chunks(client, data_map, 0, DEFAULT_READ_AHEAD);
+
+        DecompressedCursor {
+            position,
+            stream: decompress(chunks, compression, position),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // Read `length` bytes, returning them along with the cursor moved past them.
+    pub fn read(self, length: u64) -> Box<NfsFuture<(Vec<u8>, Self)>> {
+        let DecompressedCursor {
+            position,
+            stream,
+            pending,
+        } = self;
+        let length = length as usize;
+
+        future::loop_fn((stream, pending), move |(stream, mut content)| {
+            if content.len() >= length {
+                return ok!(Loop::Break((stream, content)));
+            }
+
+            stream
+                .into_future()
+                .map_err(|(error, _)| error)
+                .map(move |(data, stream)| match data {
+                    Some(data) => {
+                        content.extend_from_slice(&data);
+                        Loop::Continue((stream, content))
+                    }
+                    None => Loop::Break((stream, content)),
+                }).into_box()
+        }).map(move |(stream, mut content)| {
+            let pending = content.split_off(cmp::min(length, content.len()));
+            let cursor = DecompressedCursor {
+                position: position + content.len() as u64,
+                stream,
+                pending,
+            };
+            (content, cursor)
+        }).into_box()
+    }
+}
+
+// Stream over the data chunks as they are stored, starting at `position`.
+fn read_chunks<C: Client>(
+    client: C,
+    data_map: DataMap,
+    position: u64,
+    read_ahead: usize,
+) -> Box<Stream<Item = Vec<u8>, Error = NfsError>> {
+    let ranges = chunk_ranges(&data_map, position);
+    let inner = stream::iter_ok::<_, NfsError>(ranges)
+        .map(move |(position, length)| {
+            // Every range is read by its own self-encryptor so the decrypted chunks
+            // are released as soon as they are consumed.
+            let storage = SelfEncryptionStorage::new(client.clone());
+            let self_encryptor = fry!(SelfEncryptor::new(storage, data_map.clone()));
+            self_encryptor
+                .read(position, length)
+                .map_err(From::from)
+                .into_box()
+        }).buffered(read_ahead + 1);
+
+    Box::new(inner)
+}
+
+// Decompress the stored chunks, skipping the decompressed data before `position`.
+fn decompress(
+    chunks: Box<Stream<Item = Vec<u8>, Error = NfsError>>,
+    compression: Compression,
+    position: u64,
+) -> Box<Stream<Item = Vec<u8>, Error = NfsError>> {
+    let mut skip = position;
+
+    let inner = Decompress {
+        chunks,
+        decompressor: Decompressor::new(compression),
+        chunks_done: false,
+    }.filter_map(move |mut data| {
+        if skip >= data.len() as u64 {
+            skip -= data.len() as u64;
+            return None;
+        }
+        let _ = data.drain(..skip as usize);
+        skip = 0;
+        Some(data)
+    });
+
+    Box::new(inner)
+}
+
+// Stream over the decompressed contents of the stored chunks. Each item holds at most
+// `MAX_CHUNK_SIZE` bytes, however well the chunks were compressed, and the next chunk is only
+// polled once the previous one has been fully decompressed.
+struct Decompress {
+    chunks: Box<Stream<Item = Vec<u8>, Error = NfsError>>,
+    decompressor: Decompressor,
+    chunks_done: bool,
+}
+
+impl Stream for Decompress {
+    type Item = Vec<u8>;
+    type Error = NfsError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let data = self
+                .decompressor
+                .decompress(MAX_CHUNK_SIZE as usize, self.chunks_done)?;
+            if !data.is_empty() {
+                return Ok(Async::Ready(Some(data)));
+            }
+            if self.chunks_done {
+                return Ok(Async::Ready(None));
+            }
+
+            match self.chunks.poll()? {
+                Async::Ready(Some(chunk)) => self.decompressor.push(&chunk),
+                Async::Ready(None) => self.chunks_done = true,
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
@@ -8,18 +8,36 @@
 
 use crate::client::Client;
 use crate::crypto::shared_secretbox;
-use futures::Future;
-use crate::nfs::{data_map, File, NfsError, NfsFuture};
-use self_encryption::SelfEncryptor;
+use crate::nfs::read_stream::DecompressedCursor;
+use crate::nfs::{data_map, Compression, File, NfsError, NfsFuture};
 use crate::self_encryption_storage::SelfEncryptionStorage;
 use crate::utils::FutureExt;
+use futures::Future;
+use self_encryption::{DataMap, SelfEncryptor};
+use std::cell::RefCell;
+use std::rc::Rc;
 
 /// Reader is used to read contents of a File. It can read in chunks if the
-/// file happens to be very large.
+/// file happens to be very large. Compressed files can't be read from an
+/// arbitrary position without decompressing everything before it. The Reader
+/// keeps its place in the decompressed contents, so a read which starts where
+/// the previous one ended continues from there, while any other read streams
+/// the contents from the start, keeping only the requested range in memory.
 #[allow(dead_code)]
 pub struct Reader<C: Client> {
     client: C,
     self_encryptor: SelfEncryptor<SelfEncryptionStorage<C>>,
+    compressed: Option<Compressed>,
+}
+
+// Compressed contents of a file.
+struct Compressed {
+    compression: Compression,
+    data_map: DataMap,
+    // Size of the decompressed contents.
+    size: u64,
+    // Where the last read stopped. `None` before the first read, and while a read is in progress.
+    cursor: Rc<RefCell<Option<DecompressedCursor>>>,
 }
 
 impl<C: Client> Reader<C> {
@@ -31,21 +49,37 @@ impl<C: Client> Reader<C> {
         file: &File,
         encryption_key: Option<shared_secretbox::Key>,
     ) -> Box<NfsFuture<Self>> {
+        let compression = file.compression();
+        let size = file.size();
+
         data_map::get(&client, file.data_map_name(), encryption_key)
             .and_then(move |data_map| {
+                let compressed = if compression == Compression::None {
+                    None
+                } else {
+                    Some(Compressed {
+                        compression,
+                        data_map: data_map.clone(),
+                        size,
+                        cursor: Rc::new(RefCell::new(None)),
+                    })
+                };
                 let self_encryptor = SelfEncryptor::new(storage, data_map)?;
 
                 Ok(Self {
                     client,
                     self_encryptor,
+                    compressed,
                 })
-            })
-            .into_box()
+            }).into_box()
     }
 
     /// Returns the total size of the file/blob.
     pub fn size(&self) -> u64 {
-        self.self_encryptor.len()
+        match self.compressed {
+            Some(ref compressed) => compressed.size,
+            None => self.self_encryptor.len(),
+        }
     }
 
     /// Read data from file/blob.
@@ -64,6 +98,31 @@ impl<C: Client> Reader<C> {
                 len = length,
                 pos = position
             );
+            if let Some(ref compressed) = self.compressed {
+                let client = self.client.clone();
+                let cursor = compressed
+                    .cursor
+                    .borrow_mut()
+                    .take()
+                    .filter(|cursor| cursor.position() == position)
+                    .unwrap_or_else(|| {
+                        DecompressedCursor::new(
+                            client,
+                            compressed.data_map.clone(),
+                            compressed.compression,
+                            position,
+                        )
+                    });
+                let saved = Rc::clone(&compressed.cursor);
+
+                return cursor
+                    .read(length)
+                    .map(move |(data, cursor)| {
+                        *saved.borrow_mut() = Some(cursor);
+                        data
+                    }).into_box();
+            }
+
             self.self_encryptor
                 .read(position, length)
                 .map_err(From::from)
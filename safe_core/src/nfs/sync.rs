@@ -0,0 +1,346 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A remote file is considered up to date if it has the same size and modification time as the
+//! local one. Files of the same size but with a different modification time are compared by the
+//! hash of their contents, which requires streaming the remote file. Uploaded files are given the
+//! modification time of the local file, so on the following runs unchanged files are detected
+//! from their metadata alone.
+//!
+//! The local directory is read using blocking filesystem calls. Local files are read in pieces of
+//! `READ_BUFFER_SIZE` bytes, both when hashing and when uploading them, so they are never held in
+//! memory as a whole.
+
+use crate::client::{Client, MDataInfo};
+use crate::nfs::errors::io_error;
+use crate::nfs::file_helper::{self, Version};
+use crate::nfs::path_helper::{self, join_path, split_parent, Entry};
+use crate::nfs::{File, Mode, NfsError, NfsFuture, Writer, DEFAULT_READ_AHEAD};
+use crate::utils::FutureExt;
+use chrono::{DateTime, Utc};
+use futures::future::{self, Loop};
+use futures::{Future, Stream};
+use tiny_keccak::Keccak;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Size of the pieces local files are read in.
+pub const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Action taken to bring the remote directory in sync with the local one. Paths are relative to
+/// the synchronised directories, with components separated by `/`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SyncAction {
+    /// Create the remote directory.
+    CreateDir(String),
+    /// Upload a local file missing from the remote directory.
+    Upload(String),
+    /// Replace the remote file with the local one.
+    Update(String),
+    /// Delete the remote file missing from the local directory.
+    Delete(String),
+    /// Remove the remote directory missing from the local directory.
+    RemoveDir(String),
+}
+
+enum LocalEntry {
+    Dir,
+    File { size: u64, modified: DateTime<Utc> },
+}
+
+/// Mirror the local directory `local` into the remote directory `root`, uploading new and changed
+/// files and deleting remote entries missing locally. Returns the actions in the order they are
+/// applied. If `dry_run` is true, the actions are only planned and the remote directory is left
+/// untouched.
+pub fn sync(
+    client: impl Client,
+    local: &Path,
+    root: MDataInfo,
+    dry_run: bool,
+) -> Box<NfsFuture<Vec<SyncAction>>> {
+    trace!("Synchronising local directory {:?}, dry run: {}", local, dry_run);
+
+    let local_entries = fry!(scan_local(local));
+    let local = local.to_path_buf();
+    let local2 = local.clone();
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let root2 = root.clone();
+
+    scan_remote(client, root.clone())
+        .and_then(move |remote_entries| plan(client2, &local, local_entries, remote_entries))
+        .and_then(move |actions| {
+            if dry_run {
+                return ok!(actions);
+            }
+
+            future::loop_fn((actions, 0), move |(actions, index)| {
+                if index == actions.len() {
+                    return ok!(Loop::Break(actions));
+                }
+
+                apply(client3.clone(), root2.clone(), &local2, &actions[index])
+                    .map(move |()| Loop::Continue((actions, index + 1)))
+                    .into_box()
+            }).into_box()
+        }).into_box()
+}
+
+// Work out the actions: deletions first, children before their parents, then creations and
+// uploads, parents before their children.
+fn plan(
+    client: impl Client,
+    local: &Path,
+    local_entries: BTreeMap<String, LocalEntry>,
+    remote_entries: BTreeMap<String, (MDataInfo, Entry)>,
+) -> Box<NfsFuture<Vec<SyncAction>>> {
+    let mut actions = Vec::new();
+
+    for (path, &(_, ref entry)) in remote_entries.iter().rev() {
+        match (entry, local_entries.get(path)) {
+            (&Entry::Dir(_), Some(&LocalEntry::Dir))
+            | (&Entry::File { .. }, Some(&LocalEntry::File { .. })) => (),
+            (&Entry::Dir(_), _) => actions.push(SyncAction::RemoveDir(path.clone())),
+            (&Entry::File { .. }, _) => actions.push(SyncAction::Delete(path.clone())),
+        }
+    }
+
+    let mut unsure = Vec::new();
+
+    for (path, entry) in local_entries {
+        match (entry, remote_entries.get(&path)) {
+            (LocalEntry::Dir, Some(&(_, Entry::Dir(_)))) => (),
+            (LocalEntry::Dir, _) => actions.push(SyncAction::CreateDir(path)),
+            (
+                LocalEntry::File { size, modified },
+                Some(&(ref parent, Entry::File { ref file, .. })),
+            ) => {
+                if file.size() != size {
+                    actions.push(SyncAction::Update(path));
+                } else if *file.modified_time() != modified {
+                    unsure.push((path, parent.clone(), file.clone()));
+                }
+            }
+            (LocalEntry::File { .. }, _) => actions.push(SyncAction::Upload(path)),
+        }
+    }
+
+    // Updated files already exist remotely, so they may be appended after all other actions.
+    let checks = unsure.into_iter().map(|(path, parent, file)| {
+        let local_hash = fry!(hash_local(&local.join(&path)));
+
+        hash_remote(client.clone(), &file, &parent)
+            .map(move |remote_hash| {
+                if remote_hash == local_hash {
+                    None
+                } else {
+                    Some(SyncAction::Update(path))
+                }
+            }).into_box()
+    });
+
+    future::join_all(checks)
+        .map(move |updates| {
+            actions.extend(updates.into_iter().filter_map(|update| update));
+            actions
+        }).into_box()
+}
+
+fn apply(
+    client: impl Client,
+    root: MDataInfo,
+    local: &Path,
+    action: &SyncAction,
+) -> Box<NfsFuture<()>> {
+    trace!("Applying {:?}", action);
+
+    match *action {
+        SyncAction::CreateDir(ref path) => path_helper::mkdir(client, root, path)
+            .map(|_| ())
+            .into_box(),
+        SyncAction::RemoveDir(ref path) => path_helper::rmdir(client, root, path),
+        SyncAction::Delete(ref path) => {
+            let (parent_path, name) = split_parent(path);
+            let name = name.to_string();
+            path_helper::resolve(client.clone(), root, parent_path)
+                .and_then(move |parent| {
+                    file_helper::delete(client, parent, name, Version::GetNext).map(|_| ())
+                }).into_box()
+        }
+        SyncAction::Upload(ref path) => upload(client, root, local, path, false),
+        SyncAction::Update(ref path) => upload(client, root, local, path, true),
+    }
+}
+
+fn upload(
+    client: impl Client,
+    root: MDataInfo,
+    local: &Path,
+    path: &str,
+    update: bool,
+) -> Box<NfsFuture<()>> {
+    let local_path = local.join(path);
+    let (local_file, modified) = fry!(fs::File::open(&local_path)
+        .and_then(|file| {
+            let modified = file.metadata()?.modified()?;
+            Ok((file, modified))
+        }).map_err(|error| io_error(&local_path, &error)));
+    let modified = DateTime::<Utc>::from(modified);
+
+    let (parent_path, name) = split_parent(path);
+    let name = name.to_string();
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    path_helper::resolve(client.clone(), root, parent_path)
+        .and_then(move |parent| {
+            // Updates keep the creation time and user metadata of the remote file.
+            let fut = if update {
+                file_helper::fetch(client, parent.clone(), &name)
+                    .map(|(_, file)| file)
+                    .into_box()
+            } else {
+                ok!(File::new(Vec::new()))
+            };
+            fut.map(move |file| (parent, name, file))
+        }).and_then(move |(parent, name, file)| {
+            file_helper::write(client2, file, Mode::Overwrite, parent.enc_key().cloned())
+                .and_then(move |writer| write_local(writer, local_file, local_path))
+                .and_then(|writer| writer.close())
+                .map(move |file| (parent, name, file))
+        }).and_then(move |(parent, name, mut file)| {
+            file.set_modified_time(modified);
+            if update {
+                file_helper::update(client3, parent, name, &file, Version::GetNext)
+                    .map(|_| ())
+                    .into_box()
+            } else {
+                file_helper::insert(client3, parent, name, &file)
+            }
+        }).into_box()
+}
+
+// Write the contents of the local file through the writer, piece by piece.
+fn write_local<C: Client>(
+    writer: Writer<C>,
+    file: fs::File,
+    path: PathBuf,
+) -> Box<NfsFuture<Writer<C>>> {
+    future::loop_fn((writer, file), move |(writer, mut file)| {
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        let len = fry!(file.read(&mut buffer).map_err(|error| io_error(&path, &error)));
+        if len == 0 {
+            return ok!(Loop::Break(writer));
+        }
+
+        writer
+            .write(&buffer[..len])
+            .map(move |()| Loop::Continue((writer, file)))
+            .into_box()
+    }).into_box()
+}
+
+// SHA3-256 hash of the contents of the local file.
+fn hash_local(path: &Path) -> Result<[u8; 32], NfsError> {
+    let mut file = fs::File::open(path).map_err(|error| io_error(path, &error))?;
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    let mut hasher = Keccak::new_sha3_256();
+
+    loop {
+        let len = file.read(&mut buffer).map_err(|error| io_error(path, &error))?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buffer[..len]);
+    }
+
+    let mut hash = [0; 32];
+    hasher.finalize(&mut hash);
+    Ok(hash)
+}
+
+// SHA3-256 hash of the contents of the remote file, streamed from the network.
+fn hash_remote(client: impl Client, file: &File, parent: &MDataInfo) -> Box<NfsFuture<[u8; 32]>> {
+    file_helper::read_stream(client, file, parent.enc_key().cloned(), 0, DEFAULT_READ_AHEAD)
+        .and_then(|stream| {
+            stream.fold(Keccak::new_sha3_256(), |mut hasher, content| {
+                hasher.update(&content);
+                Ok::<_, NfsError>(hasher)
+            })
+        }).map(|hasher| {
+            let mut hash = [0; 32];
+            hasher.finalize(&mut hash);
+            hash
+        }).into_box()
+}
+
+fn scan_local(root: &Path) -> Result<BTreeMap<String, LocalEntry>, NfsError> {
+    let mut entries = BTreeMap::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+
+    while let Some((dir, prefix)) = pending.pop() {
+        for entry in fs::read_dir(&dir).map_err(|error| io_error(&dir, &error))? {
+            let entry = entry.map_err(|error| io_error(&dir, &error))?;
+            let metadata = entry
+                .metadata()
+                .map_err(|error| io_error(&entry.path(), &error))?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|_| NfsError::from("File name is not valid UTF-8"))?;
+            let path = join_path(&prefix, &name);
+
+            if metadata.is_dir() {
+                pending.push((entry.path(), path.clone()));
+                let _ = entries.insert(path, LocalEntry::Dir);
+            } else if metadata.is_file() {
+                let modified = metadata
+                    .modified()
+                    .map_err(|error| io_error(&entry.path(), &error))?;
+                let _ = entries.insert(
+                    path,
+                    LocalEntry::File {
+                        size: metadata.len(),
+                        modified: DateTime::from(modified),
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+// Collect all the entries of the remote tree along with the directories holding them.
+fn scan_remote(
+    client: impl Client,
+    root: MDataInfo,
+) -> Box<NfsFuture<BTreeMap<String, (MDataInfo, Entry)>>> {
+    let pending = vec![(String::new(), root)];
+
+    future::loop_fn((pending, BTreeMap::new()), move |(mut pending, mut entries)| {
+        let (prefix, dir) = match pending.pop() {
+            Some(next) => next,
+            None => return ok!(Loop::Break(entries)),
+        };
+
+        path_helper::list(client.clone(), dir.clone(), "")
+            .map(move |listing| {
+                for (name, entry) in listing {
+                    let path = join_path(&prefix, &name);
+                    if let Entry::Dir(ref subdir) = entry {
+                        pending.push((path.clone(), subdir.clone()));
+                    }
+                    let _ = entries.insert(path, (dir.clone(), entry));
+                }
+                Loop::Continue((pending, entries))
+            }).into_box()
+    }).into_box()
+}
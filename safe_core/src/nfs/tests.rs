@@ -7,19 +7,31 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::client::core_client::CoreClient;
-use crate::client::MDataInfo;
+use crate::client::{Client, MDataInfo};
 use crate::crypto::shared_secretbox;
 use crate::errors::CoreError;
 use futures::future::{self, Loop};
-use futures::Future;
+use futures::{Future, Stream};
+use crate::nfs::archive;
+use crate::nfs::batch::Batch;
+use crate::nfs::data_map;
 use crate::nfs::file_helper::{self, Version};
+use crate::nfs::history;
+use crate::nfs::path_helper::{self, Entry};
+use crate::nfs::sync::{self, SyncAction};
+use crate::nfs::watcher::{Backoff, Change, ChangeKind, Watcher};
 use crate::nfs::reader::Reader;
 use crate::nfs::writer::Writer;
-use crate::nfs::{create_dir, File, Mode, NfsError, NfsFuture};
+use crate::nfs::{create_dir, Compression, File, Mode, NfsError, NfsFuture};
+use crate::self_encryption_storage::DedupStats;
 use rand::{self, Rng};
+use routing::{EntryError, XorName};
 use rust_sodium::crypto::secretbox;
-use self_encryption::MIN_CHUNK_SIZE;
+use self_encryption::{DataMap, MIN_CHUNK_SIZE};
 use std;
+use std::io::Cursor;
+use std::time::Duration;
+use crate::utils;
 use crate::utils::test_utils::random_client;
 use crate::utils::FutureExt;
 use crate::DIR_TAG;
@@ -247,6 +259,54 @@ fn file_read_chunks() {
 }
 
 // Test writing to files in chunks.
+// Test streaming the file contents with read-ahead.
+// 1. Stream the whole file and check that it's yielded one chunk at a time.
+// 2. Stream the file from an offset.
+// 3. Try streaming from past the end of the file, which should fail.
+#[test]
+fn file_read_stream() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::read_stream(c2, &file, dir.enc_key().cloned(), 0, 2)
+                    .and_then(|stream| {
+                        assert_eq!(stream.size(), ORIG_SIZE as u64);
+                        stream.collect()
+                    }).map(move |buffers| (dir, file, buffers))
+            }).then(move |res| {
+                let (dir, file, buffers) = unwrap!(res);
+
+                // A file of this size is split into three chunks
+                assert_eq!(buffers.len(), 3);
+                assert_eq!(buffers.concat(), vec![0u8; ORIG_SIZE]);
+
+                let position = (ORIG_SIZE - NEW_SIZE) as u64;
+                file_helper::read_stream(c3, &file, dir.enc_key().cloned(), position, 2)
+                    .and_then(|stream| stream.collect())
+                    .map(move |buffers| (dir, file, buffers))
+            }).then(move |res| {
+                let (dir, file, buffers) = unwrap!(res);
+                assert_eq!(buffers.concat(), vec![0u8; NEW_SIZE]);
+
+                let position = ORIG_SIZE as u64 + 1;
+                file_helper::read_stream(c4, &file, dir.enc_key().cloned(), position, 2)
+            }).then(|res| {
+                match res {
+                    Err(NfsError::InvalidRange) => (),
+                    Ok(_) => panic!("Unexpected success"),
+                    Err(err) => panic!("Unexpected {:?}", err),
+                }
+                Ok::<_, NfsError>(())
+            })
+    });
+}
+
 #[test]
 fn file_write_chunks() {
     const CHUNK_SIZE: usize = 1000;
@@ -256,7 +316,6 @@ fn file_write_chunks() {
     random_client(move |client| {
         let c2 = client.clone();
         let c3 = client.clone();
-        let c4 = client.clone();
 
         create_test_file(client)
             .then(move |res| {
@@ -456,6 +515,376 @@ fn file_update_append() {
     })
 }
 
+// Test positional writes, truncation and extension of an existing file.
+#[test]
+fn file_update_modify() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                // Positional writes are only allowed at the end in the sequential modes
+                file_helper::write(c2.clone(), file.clone(), Mode::Append, dir.enc_key().cloned())
+                    .then(move |res| {
+                        let writer = unwrap!(res);
+                        writer.write_at(0, &[1u8; NEW_SIZE])
+                    }).then(move |res| {
+                        match res {
+                            Err(NfsError::InvalidRange) => (),
+                            Ok(()) => panic!("Unexpected success"),
+                            Err(err) => panic!("Unexpected {:?}", err),
+                        }
+                        file_helper::write(c2, file, Mode::Modify, dir.enc_key().cloned())
+                            .map(move |writer| (dir, writer))
+                    })
+            }).then(move |res| {
+                let (dir, writer) = unwrap!(res);
+                assert_eq!(writer.size(), ORIG_SIZE as u64);
+
+                writer
+                    .write_at(APPEND_SIZE as u64, &[1u8; NEW_SIZE])
+                    .and_then(move |_| {
+                        writer
+                            .truncate((ORIG_SIZE - APPEND_SIZE) as u64)
+                            .map(move |_| writer)
+                    })
+                    .and_then(move |writer| {
+                        writer
+                            .write_at(ORIG_SIZE as u64, &[2u8; APPEND_SIZE])
+                            .map(move |_| writer)
+                    }).and_then(move |writer| writer.close())
+                    .map(move |file| (dir, file))
+            }).then(move |res| {
+                let (dir, file) = unwrap!(res);
+                assert_eq!(file.size(), (ORIG_SIZE + APPEND_SIZE) as u64);
+                file_helper::read(c3, &file, dir.enc_key().cloned())
+            }).then(move |res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                reader.read(0, size)
+            }).map(move |data| {
+                let modified_end = APPEND_SIZE + NEW_SIZE;
+
+                assert_eq!(data.len(), ORIG_SIZE + APPEND_SIZE);
+                assert_eq!(data[0..APPEND_SIZE].to_owned(), vec![0u8; APPEND_SIZE]);
+                assert_eq!(data[APPEND_SIZE..modified_end].to_owned(), vec![1u8; NEW_SIZE]);
+                // Data past the truncation point is zeroed when the file is extended again
+                assert_eq!(
+                    data[modified_end..ORIG_SIZE].to_owned(),
+                    vec![0u8; ORIG_SIZE - modified_end]
+                );
+                assert_eq!(&data[ORIG_SIZE..], [2u8; APPEND_SIZE]);
+            })
+    });
+}
+
+// Test applying several file mutations at once, and the conflicts reported for them.
+#[test]
+fn file_batch() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let mut updated = file.clone();
+                updated.set_user_metadata(vec![1, 2, 3]);
+
+                Batch::new(dir.clone())
+                    .insert("a.txt", file.clone())
+                    .insert("b.txt", file)
+                    .update_with_history("hello.txt", updated, Version::GetNext)
+                    .commit(c2)
+                    .map(move |versions| (dir, versions))
+            }).then(move |res| {
+                let (dir, versions) = unwrap!(res);
+                assert_eq!(
+                    versions,
+                    btree_map![
+                        "a.txt".to_string() => 0,
+                        "b.txt".to_string() => 0,
+                        "hello.txt".to_string() => 1
+                    ]
+                );
+
+                file_helper::list(c3, dir.clone())
+                    .join(history::list(c4, dir.clone(), "hello.txt"))
+                    .map(move |(files, versions)| {
+                        assert_eq!(files.len(), 3);
+                        assert_eq!(versions.len(), 1);
+                        dir
+                    })
+            }).then(move |res| {
+                let dir = unwrap!(res);
+
+                // Conflicts found before sending the mutation
+                Batch::new(dir.clone())
+                    .delete("b.txt", Version::GetNext)
+                    .update("missing.txt", File::new(Vec::new()), Version::GetNext)
+                    .commit(c5)
+                    .then(move |res| match res {
+                        Err(NfsError::Conflict(conflicts)) => {
+                            assert_eq!(conflicts.len(), 1);
+                            assert_eq!(conflicts["missing.txt"], EntryError::NoSuchEntry);
+                            Ok(dir)
+                        }
+                        Ok(_) => panic!("Unexpected success"),
+                        Err(err) => panic!("Unexpected {:?}", err),
+                    })
+            }).then(move |res| {
+                let dir = unwrap!(res);
+
+                // Conflicts reported by the network
+                Batch::new(dir.clone())
+                    .insert("a.txt", File::new(Vec::new()))
+                    .delete("b.txt", Version::Custom(1))
+                    .commit(c6)
+                    .then(move |res| match res {
+                        Err(NfsError::Conflict(conflicts)) => {
+                            assert_eq!(conflicts.len(), 1);
+                            assert_eq!(conflicts["a.txt"], EntryError::EntryExists(0));
+                            Ok(dir)
+                        }
+                        Ok(_) => panic!("Unexpected success"),
+                        Err(err) => panic!("Unexpected {:?}", err),
+                    })
+            }).then(move |res| {
+                let dir = unwrap!(res);
+
+                // Names which would clash with subdirectory or history entries
+                Batch::new(dir.clone())
+                    .insert("c.txt", File::new(Vec::new()))
+                    .insert("docs/", File::new(Vec::new()))
+                    .commit(c8)
+                    .then(move |res| match res {
+                        Err(NfsError::InvalidPath) => Ok(dir),
+                        Ok(_) => panic!("Unexpected success"),
+                        Err(err) => panic!("Unexpected {:?}", err),
+                    })
+            }).then(move |res| {
+                let dir = unwrap!(res);
+
+                // Nothing from the rejected batches has been applied
+                file_helper::fetch(c7.clone(), dir.clone(), "b.txt")
+                    .map(|(version, _)| assert_eq!(version, 0))
+                    .and_then(move |()| file_helper::fetch(c7, dir, "c.txt"))
+                    .then(|res| match res {
+                        Err(NfsError::FileNotFound) => Ok(()),
+                        res => panic!("Unexpected {:?}", res),
+                    })
+            })
+    });
+}
+
+// Test watching a directory for changes made to its entries.
+#[test]
+fn dir_watch() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::insert(c2, dir.clone(), "b.txt", &file).map(move |()| (dir, file))
+            }).then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let backoff = Backoff {
+                    min: Duration::from_millis(10),
+                    max: Duration::from_millis(40),
+                };
+                let watcher = Watcher::new(c3, vec![dir.clone()], backoff);
+
+                // Give the watcher time to record the initial state first.
+                let mutation = c4
+                    .delay(Duration::from_millis(100))
+                    .map_err(NfsError::from)
+                    .and_then(move |()| {
+                        Batch::new(dir)
+                            .insert("a.txt", file.clone())
+                            .update("b.txt", file, Version::GetNext)
+                            .delete("hello.txt", Version::GetNext)
+                            .commit(c5)
+                    });
+
+                watcher.take(3).collect().join(mutation)
+            }).map(|(changes, _)| {
+                let change = |name: &str, kind| Change {
+                    dir: 0,
+                    name: name.to_string(),
+                    kind,
+                };
+                assert_eq!(
+                    changes,
+                    vec![
+                        change("a.txt", ChangeKind::Created),
+                        change("b.txt", ChangeKind::Modified),
+                        change("hello.txt", ChangeKind::Deleted),
+                    ]
+                );
+            })
+    });
+}
+
+// Test resuming an interrupted upload from a checkpoint.
+#[test]
+fn file_resume_write() {
+    let mut rng = rand::thread_rng();
+    let part1: Vec<u8> = rng.gen_iter().take(5 * MIN_CHUNK_SIZE as usize).collect();
+    let part2: Vec<u8> = rng.gen_iter().take(4 * MIN_CHUNK_SIZE as usize).collect();
+    let content = [part1.clone(), part2.clone()].concat();
+
+    let path = std::env::temp_dir().join(format!("safe_core_upload_{}", rand::random::<u64>()));
+    let path2 = path.clone();
+    let path3 = path.clone();
+
+    random_client(move |client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::write(c2, file, Mode::Overwrite, dir.enc_key().cloned())
+                    .and_then(move |writer| writer.write(&part1).map(move |_| writer))
+                    .and_then(move |writer| writer.checkpoint(path))
+                    .map(move |writer| {
+                        assert_eq!(writer.size(), (5 * MIN_CHUNK_SIZE) as u64);
+                        // Drop the writer without closing it, as if the process was interrupted
+                        dir
+                    })
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                assert!(path2.exists());
+
+                file_helper::resume_write(c3, &path2, dir.enc_key().cloned())
+                    .and_then(move |writer| {
+                        assert_eq!(writer.size(), (5 * MIN_CHUNK_SIZE) as u64);
+                        writer.write(&part2).map(move |_| writer)
+                    }).and_then(|writer| writer.close())
+                    .map(move |file| (dir, file))
+            }).then(move |res| {
+                let (dir, file) = unwrap!(res);
+                assert_eq!(file.size(), content.len() as u64);
+                // The checkpoint is removed once the upload is complete
+                assert!(!path3.exists());
+
+                file_helper::read(c4, &file, dir.enc_key().cloned())
+                    .and_then(|reader| {
+                        let size = reader.size();
+                        reader.read(0, size)
+                    }).map(move |data| assert_eq!(data, content))
+            })
+    });
+}
+
+// Test writing and reading compressed files, both at random positions and sequentially.
+#[test]
+fn file_compression() {
+    const SIZE: usize = 100_000;
+    let content: Vec<u8> = (0..SIZE).map(|i| (i % 7) as u8).collect();
+    let content2 = content.clone();
+    let content3 = content.clone();
+
+    random_client(move |client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, mut file) = unwrap!(res);
+                file.set_compression(Compression::Deflate);
+
+                file_helper::write(c2, file, Mode::Overwrite, dir.enc_key().cloned())
+                    .and_then(move |writer| {
+                        writer
+                            .write(&content[..SIZE / 2])
+                            .and_then(move |_| writer.write(&content[SIZE / 2..]).map(|_| writer))
+                    }).and_then(move |writer| {
+                        assert_eq!(writer.size(), SIZE as u64);
+                        writer.close()
+                    }).map(move |file| (dir, file))
+            }).then(move |res| {
+                let (dir, file) = unwrap!(res);
+                assert_eq!(file.size(), SIZE as u64);
+                assert_eq!(file.compression(), Compression::Deflate);
+
+                // The stored data is much smaller than the file contents
+                data_map::get(&c3, file.data_map_name(), dir.enc_key().cloned())
+                    .map(move |data_map| {
+                        assert!(data_map.len() < (SIZE / 10) as u64);
+                        (dir, file)
+                    })
+            }).then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::read(c4, &file, dir.enc_key().cloned())
+                    .and_then(|reader| {
+                        assert_eq!(reader.size(), SIZE as u64);
+                        reader
+                            .read(NEW_SIZE as u64, ORIG_SIZE as u64)
+                            .map(move |data| (reader, data))
+                    }).and_then(move |(reader, data)| {
+                        assert_eq!(data, &content2[NEW_SIZE..NEW_SIZE + ORIG_SIZE]);
+
+                        // A sequential read continues where the previous one stopped
+                        let position = (NEW_SIZE + ORIG_SIZE) as u64;
+                        reader
+                            .read(position, SIZE as u64 - position)
+                            .map(move |data| (reader, content2, data))
+                    }).and_then(move |(reader, content2, data)| {
+                        assert_eq!(data, &content2[NEW_SIZE + ORIG_SIZE..]);
+                        reader.read(0, SIZE as u64)
+                    }).map(move |data| {
+                        assert_eq!(data, content3);
+                        (dir, file)
+                    })
+            }).then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                let position = (SIZE - ORIG_SIZE) as u64;
+                file_helper::read_stream(c5, &file, dir.enc_key().cloned(), position, 2)
+                    .and_then(|stream| {
+                        assert_eq!(stream.size(), SIZE as u64);
+                        stream.collect()
+                    }).map(move |buffers| {
+                        let expected: Vec<u8> =
+                            (SIZE - ORIG_SIZE..SIZE).map(|i| (i % 7) as u8).collect();
+                        assert_eq!(buffers.concat(), expected);
+                        (dir, file)
+                    })
+            }).then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                // Compressed files can only be overwritten
+                file_helper::write(c6, file, Mode::Append, dir.enc_key().cloned())
+            }).then(|res| {
+                match res {
+                    Err(NfsError::CompressedFile) => (),
+                    Ok(_) => panic!("Unexpected success"),
+                    Err(err) => panic!("Unexpected {:?}", err),
+                }
+                Ok::<_, NfsError>(())
+            })
+    });
+}
+
 #[test]
 fn file_update_metadata() {
     random_client(|client| {
@@ -476,12 +905,145 @@ fn file_update_metadata() {
             }).then(move |res| {
                 let dir = unwrap!(res);
 
-                file_helper::fetch(c3.clone(), dir, "hello.txt")
-            }).map(move |(_version, file)| {
+                file_helper::fetch(c3.clone(), dir.clone(), "hello.txt")
+                    .join(history::list(c3, dir, "hello.txt"))
+            }).map(move |((_version, file), versions)| {
                 assert_eq!(*file.user_metadata(), [12u8; 10][..]);
+                // Plain updates don't record the replaced version.
+                assert!(versions.is_empty());
             })
     });
 }
+// Test keeping and restoring previous versions of a file.
+// 1. Overwrite a file and check that the original version is kept in its history.
+// 2. Read the original version.
+// 3. Restore the original version and check the history now contains both previous versions.
+// 4. Delete the file, which should delete its history too.
+#[test]
+fn file_history() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::write(c2.clone(), file, Mode::Overwrite, dir.enc_key().cloned())
+                    .and_then(|writer| {
+                        writer
+                            .write(&[1u8; NEW_SIZE])
+                            .and_then(move |_| writer.close())
+                    }).and_then(move |file| {
+                        history::update(c2, dir.clone(), "hello.txt", &file, Version::GetNext)
+                            .map(move |version| {
+                                assert_eq!(version, 1);
+                                dir
+                            })
+                    })
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                history::list(c3, dir.clone(), "hello.txt").map(move |versions| (dir, versions))
+            }).then(move |res| {
+                let (dir, versions) = unwrap!(res);
+                assert_eq!(versions.len(), 1);
+                assert_eq!(versions[0].version, 0);
+                assert_eq!(versions[0].file.size(), ORIG_SIZE as u64);
+
+                history::read(c4, dir.clone(), "hello.txt", 0)
+                    .and_then(|reader| {
+                        let size = reader.size();
+                        reader.read(0, size)
+                    }).map(move |data| (dir, data))
+            }).then(move |res| {
+                let (dir, data) = unwrap!(res);
+                assert_eq!(data, vec![0u8; ORIG_SIZE]);
+
+                history::restore(c5.clone(), dir.clone(), "hello.txt", 0)
+                    .and_then(move |version| {
+                        assert_eq!(version, 2);
+                        file_helper::fetch(c5.clone(), dir.clone(), "hello.txt")
+                            .join(history::list(c5, dir.clone(), "hello.txt"))
+                            .map(move |res| (dir, res))
+                    })
+            }).then(move |res| {
+                let (dir, ((version, file), versions)) = unwrap!(res);
+                assert_eq!(version, 2);
+                assert_eq!(file.size(), ORIG_SIZE as u64);
+                assert_eq!(versions.len(), 2);
+                assert_eq!(versions[0].version, 0);
+                assert_eq!(versions[1].version, 1);
+                assert_eq!(versions[1].file.size(), NEW_SIZE as u64);
+
+                file_helper::delete(c6, dir.clone(), "hello.txt", Version::GetNext)
+                    .map(move |_| dir)
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                history::list(c7, dir, "hello.txt")
+            }).map(|versions| {
+                assert!(versions.is_empty());
+            })
+    });
+}
+
+// Test that moving a file moves its history along.
+// 1. Overwrite a file, so its original version is kept in its history.
+// 2. Move the file into a directory with a different key.
+// 3. Check the history has been moved and its previous version can still be read.
+#[test]
+fn file_rename_history() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        let dst = unwrap!(MDataInfo::random_private(DIR_TAG));
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::write(c2.clone(), file, Mode::Overwrite, dir.enc_key().cloned())
+                    .and_then(|writer| {
+                        writer
+                            .write(&[1u8; NEW_SIZE])
+                            .and_then(move |_| writer.close())
+                    }).and_then(move |file| {
+                        history::update(c2, dir.clone(), "hello.txt", &file, Version::GetNext)
+                            .map(move |_| dir)
+                    })
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                create_dir(&c3, &dst, btree_map![], btree_map![]).map(move |_| (dir, dst))
+            }).then(move |res| {
+                let (dir, dst) = unwrap!(res);
+                file_helper::rename(c4.clone(), dir.clone(), "hello.txt", dst.clone(), "moved.txt")
+                    .and_then(move |_| {
+                        history::list(c4.clone(), dir, "hello.txt")
+                            .join(history::list(c4, dst.clone(), "moved.txt"))
+                            .map(move |versions| (dst, versions))
+                    })
+            }).then(move |res| {
+                let (dst, (src_versions, dst_versions)) = unwrap!(res);
+                assert!(src_versions.is_empty());
+                assert_eq!(dst_versions.len(), 1);
+                assert_eq!(dst_versions[0].file.size(), ORIG_SIZE as u64);
+
+                history::read(c5, dst, "moved.txt", 0).and_then(|reader| {
+                    let size = reader.size();
+                    reader.read(0, size)
+                })
+            }).map(|data| {
+                assert_eq!(data, vec![0u8; ORIG_SIZE]);
+            })
+    });
+}
+
 #[test]
 fn file_delete() {
     random_client(|client| {
@@ -607,6 +1169,122 @@ fn file_open_close() {
     });
 }
 
+// Test verifying the chunks of a file.
+// 1. Store a file spanning several chunks and verify it.
+// 2. Replace the first chunk in its data map by one which doesn't exist.
+// 3. Verify the file again and check the missing chunk is reported.
+#[test]
+fn file_verify() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let content = unwrap!(utils::generate_random_vector(4 * MIN_CHUNK_SIZE as usize));
+        let key = shared_secretbox::gen_key();
+        let key2 = key.clone();
+        let key3 = key.clone();
+        let key4 = key.clone();
+        let key5 = key.clone();
+
+        file_helper::write(client.clone(), File::new(Vec::new()), Mode::Overwrite, Some(key))
+            .and_then(move |writer| writer.write(&content).and_then(move |_| writer.close()))
+            .and_then(move |file| {
+                file_helper::verify(c2, &file, Some(key2)).map(move |verification| {
+                    assert!(verification.is_ok());
+                    // The data map itself, plus the chunks of the content.
+                    assert!(verification.checked > 3);
+                    file
+                })
+            }).and_then(move |file| {
+                data_map::get(&c3, file.data_map_name(), Some(key3.clone()))
+                    .map(move |data_map| (file, data_map, key3))
+            }).and_then(move |(mut file, data_map, key)| {
+                let mut chunks = match data_map {
+                    DataMap::Chunks(chunks) => chunks,
+                    _ => panic!("Unexpected data map"),
+                };
+                let missing: XorName = rand::random();
+                chunks[0].hash = missing.0.to_vec();
+
+                data_map::put(&c4, &DataMap::Chunks(chunks), Some(key))
+                    .map(move |data_map_name| {
+                        file.set_data_map_name(data_map_name);
+                        (file, missing)
+                    })
+            }).and_then(move |(file, missing)| {
+                file_helper::verify(c5, &file, Some(key4)).map(move |verification| {
+                    assert!(!verification.is_ok());
+                    assert_eq!(verification.missing, vec![missing]);
+                    assert!(verification.corrupt.is_empty());
+                    file
+                })
+            }).and_then(move |file| {
+                // Reading the file fails.
+                file_helper::read(c6, &file, Some(key5))
+                    .and_then(|reader| {
+                        let size = reader.size();
+                        reader.read(0, size)
+                    }).then(|res| {
+                        assert!(res.is_err());
+                        Ok::<_, NfsError>(())
+                    })
+            })
+    });
+}
+
+// Test that uploading the same content again with a deduplicating storage doesn't put its chunks.
+// 1. Write a file spanning several chunks.
+// 2. Write the same content again, counting the mutations done.
+// 3. Check only the data map has been put and the chunks are reported as deduplicated.
+#[test]
+fn file_write_dedup() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let content = unwrap!(utils::generate_random_vector(4 * MIN_CHUNK_SIZE as usize));
+        let content2 = content.clone();
+        let dedup_stats = DedupStats::new();
+        let dedup_stats2 = dedup_stats.clone();
+
+        file_helper::write(client.clone(), File::new(Vec::new()), Mode::Overwrite, None)
+            .and_then(move |writer| writer.write(&content).and_then(move |_| writer.close()))
+            .and_then(move |_| c2.get_account_info().map_err(NfsError::from))
+            .and_then(move |before| {
+                file_helper::write_deduplicating(
+                    c3,
+                    File::new(Vec::new()),
+                    Mode::Overwrite,
+                    None,
+                    dedup_stats2,
+                ).map(move |writer| (writer, before))
+            }).and_then(move |(writer, before)| {
+                writer
+                    .write(&content2)
+                    .and_then(move |_| writer.close())
+                    .map(move |file| (file, before))
+            }).and_then(move |(file, before)| {
+                c4.get_account_info()
+                    .map_err(NfsError::from)
+                    .map(move |after| (file, before, after))
+            }).and_then(move |(file, before, after)| {
+                assert_eq!(after.mutations_done - before.mutations_done, 1);
+                assert_eq!(dedup_stats.chunks(), 3);
+                assert!(dedup_stats.bytes() > 0);
+
+                file_helper::read(c5, &file, None).and_then(|reader| {
+                    let size = reader.size();
+                    reader.read(0, size)
+                })
+            }).map(move |data| {
+                assert_eq!(data.len(), 4 * MIN_CHUNK_SIZE as usize);
+            })
+    });
+}
+
 // Create and store encrypted file and make sure it can only be read back with
 // the original encryption key.
 #[test]
@@ -665,3 +1343,464 @@ fn encryption() {
         })
     })
 }
+
+// Test creating nested directories by path, inserting files into them and listing them.
+#[test]
+fn path_mkdir_insert_list() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+
+        let root = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let root2 = root.clone();
+
+        create_dir(client, &root, btree_map![], btree_map![])
+            .then(move |res| {
+                unwrap!(res);
+                path_helper::mkdir(c2, root.clone(), "/photos/2018").map(move |_| root)
+            }).then(move |res| {
+                let root = unwrap!(res);
+                let file = File::new(Vec::new());
+                path_helper::insert_file(c3, root.clone(), "/photos/2018/a.jpg", &file)
+                    .map(move |_| root)
+            }).then(move |res| {
+                let root = unwrap!(res);
+                path_helper::list(c4, root.clone(), "/photos").map(move |entries| (root, entries))
+            }).then(move |res| {
+                let (root, entries) = unwrap!(res);
+                assert_eq!(entries.len(), 1);
+                match entries[0] {
+                    (ref name, Entry::Dir(_)) => assert_eq!(name, "2018"),
+                    ref x => panic!("Unexpected {:?}", x),
+                }
+
+                path_helper::list(c5, root.clone(), "photos/2018/")
+                    .map(move |entries| (root, entries))
+            }).then(move |res| {
+                let (root, entries) = unwrap!(res);
+                assert_eq!(entries.len(), 1);
+                match entries[0] {
+                    (ref name, Entry::File { version, .. }) => {
+                        assert_eq!(name, "a.jpg");
+                        assert_eq!(version, 0);
+                    }
+                    ref x => panic!("Unexpected {:?}", x),
+                }
+
+                // Intermediate directories are created on demand.
+                let file = File::new(Vec::new());
+                path_helper::insert_file(c6, root.clone(), "/docs/notes/b.txt", &file)
+                    .map(move |_| root)
+            }).then(move |res| {
+                let root = unwrap!(res);
+                path_helper::stat(c7, root.clone(), "/docs/notes/b.txt")
+                    .map(move |entry| (root, entry))
+            }).then(move |res| {
+                let (root, entry) = unwrap!(res);
+                match entry {
+                    Entry::File { .. } => (),
+                    x => panic!("Unexpected {:?}", x),
+                }
+
+                // Creating an existing directory fails.
+                path_helper::mkdir(c8, root.clone(), "/photos").map(move |_| root)
+            }).then(move |res| {
+                let root = match res {
+                    Err(NfsError::FileExists) => root2,
+                    x => panic!("Unexpected {:?}", x),
+                };
+
+                // File names which would clash with subdirectory entries are rejected.
+                file_helper::insert(c9, root, "photos/", &File::new(Vec::new()))
+            }).then(move |res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::InvalidPath) => (),
+                    x => panic!("Unexpected {:?}", x),
+                }
+                Ok(())
+            })
+    })
+}
+
+// Test removing directories by path.
+// 1. Removing a non-empty directory fails.
+// 2. Removing an empty directory succeeds and it can no longer be resolved.
+// 3. The removed directory can be created again.
+#[test]
+fn path_rmdir() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        let root = unwrap!(MDataInfo::random_public(DIR_TAG));
+
+        create_dir(client, &root, btree_map![], btree_map![])
+            .then(move |res| {
+                unwrap!(res);
+                path_helper::mkdir(c2, root.clone(), "/a/b").map(move |_| root)
+            }).then(move |res| {
+                let root = unwrap!(res);
+                path_helper::rmdir(c3, root.clone(), "/a").then(move |res| -> Result<_, NfsError> {
+                    match res {
+                        Err(NfsError::DirectoryNotEmpty) => Ok(root),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+                })
+            }).then(move |res| {
+                let root = unwrap!(res);
+                path_helper::rmdir(c4, root.clone(), "/a/b").map(move |_| root)
+            }).then(move |res| {
+                let root = unwrap!(res);
+                path_helper::resolve(c5, root.clone(), "/a/b").then(
+                    move |res| -> Result<_, NfsError> {
+                        match res {
+                            Err(NfsError::FileNotFound) => Ok(root),
+                            x => panic!("Unexpected {:?}", x),
+                        }
+                    },
+                )
+            }).then(move |res| {
+                let root = unwrap!(res);
+                path_helper::mkdir(c6, root.clone(), "/a/b").map(move |_| root)
+            }).then(move |res| {
+                let root = unwrap!(res);
+                path_helper::rmdir(c7, root, "/")
+            }).then(move |res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::InvalidPath) => (),
+                    x => panic!("Unexpected {:?}", x),
+                }
+                Ok(())
+            })
+    })
+}
+
+// Test listing files in a directory.
+// Subdirectories and deleted files should not be listed.
+#[test]
+fn file_list() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::insert(c2, dir.clone(), "deleted.txt", &file).map(move |_| dir)
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::delete(c3, dir.clone(), "deleted.txt", Version::GetNext)
+                    .map(move |_| dir)
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                path_helper::mkdir(c4, dir.clone(), "subdir").map(move |_| dir)
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list(c5, dir)
+            }).then(move |res| -> Result<_, NfsError> {
+                let files = unwrap!(res);
+                assert_eq!(files.len(), 1);
+
+                let (ref name, version, ref file) = files[0];
+                assert_eq!(name, "hello.txt");
+                assert_eq!(version, 0);
+                assert_eq!(file.size(), ORIG_SIZE as u64);
+                Ok(())
+            })
+    })
+}
+
+// Test renaming files and moving them between directories.
+// 1. Rename a file within its directory.
+// 2. Move the file to a directory with a different encryption key and read it back.
+// 3. Repeating the move is a no-op, as if resuming an interrupted move.
+#[test]
+fn file_rename() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+        let c10 = client.clone();
+
+        let dst = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let dst2 = dst.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, _file) = unwrap!(res);
+                file_helper::rename(c2, dir.clone(), "hello.txt", dir.clone(), "renamed.txt")
+                    .map(move |_| dir)
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list(c3, dir.clone()).map(move |files| (dir, files))
+            }).then(move |res| {
+                let (dir, files) = unwrap!(res);
+                assert_eq!(files.len(), 1);
+                assert_eq!(files[0].0, "renamed.txt");
+
+                create_dir(&c4, &dst, btree_map![], btree_map![]).map(move |_| (dir, dst))
+            }).then(move |res| {
+                let (dir, dst) = unwrap!(res);
+                file_helper::rename(c5, dir.clone(), "renamed.txt", dst, "moved.txt")
+                    .map(move |_| dir)
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list(c6, dir.clone()).map(move |files| (dir, files))
+            }).then(move |res| {
+                let (dir, files) = unwrap!(res);
+                assert!(files.is_empty());
+
+                // Moving again completes without errors.
+                file_helper::rename(c7, dir, "renamed.txt", dst2.clone(), "moved.txt")
+                    .map(move |_| dst2)
+            }).then(move |res| {
+                let dst = unwrap!(res);
+                file_helper::fetch(c8, dst.clone(), "moved.txt").map(move |(_, file)| (dst, file))
+            }).then(move |res| {
+                let (dst, file) = unwrap!(res);
+                file_helper::read(c9, &file, dst.enc_key().cloned())
+                    .map(move |reader| (dst, reader))
+            }).then(move |res| {
+                let (dst, reader) = unwrap!(res);
+                let size = reader.size();
+                reader.read(0, size).map(move |data| (dst, data))
+            }).then(move |res| {
+                let (dst, data) = unwrap!(res);
+                assert_eq!(data, vec![0u8; ORIG_SIZE]);
+
+                // Moving a non-existing file fails.
+                file_helper::rename(c10, dst.clone(), "missing.txt", dst, "other.txt")
+            }).then(move |res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::FileNotFound) => (),
+                    x => panic!("Unexpected {:?}", x),
+                }
+                Ok(())
+            })
+    })
+}
+
+// Test moving a file onto an existing one.
+// 1. Moving onto a copy of the file differing only in its content type fails.
+// 2. Moving onto an identical copy completes the move.
+#[test]
+fn file_rename_existing() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let mut other = file.clone();
+                other.set_content_type(Some("text/plain".to_string()));
+
+                file_helper::insert(c2, dir.clone(), "other.txt", &other).map(move |_| (dir, file))
+            }).then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::rename(c3, dir.clone(), "hello.txt", dir.clone(), "other.txt")
+                    .then(move |res| {
+                        match res {
+                            Err(NfsError::FileExists) => (),
+                            x => panic!("Unexpected {:?}", x),
+                        }
+                        file_helper::insert(c4, dir.clone(), "same.txt", &file).map(move |_| dir)
+                    })
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::rename(c5, dir.clone(), "hello.txt", dir.clone(), "same.txt")
+                    .map(move |_| dir)
+            }).then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list(c6, dir)
+            }).then(move |res| -> Result<_, NfsError> {
+                let mut names: Vec<_> = unwrap!(res).into_iter().map(|(name, _, _)| name).collect();
+                names.sort();
+                assert_eq!(names, vec!["other.txt", "same.txt"]);
+                Ok(())
+            })
+    })
+}
+
+// Test mirroring a local directory tree into a remote directory.
+// 1. Do a dry run and check that the planned actions are reported but not applied.
+// 2. Sync the directory and check the remote tree.
+// 3. Sync again, which should find nothing to do.
+// 4. Change the contents of a file but not its size and remove a subdirectory locally, then sync
+//    again and check the changes are mirrored.
+#[test]
+fn dir_sync() {
+    let local = std::env::temp_dir().join(format!("safe_core_sync_{}", rand::random::<u64>()));
+    unwrap!(std::fs::create_dir_all(local.join("sub")));
+    unwrap!(std::fs::write(local.join("a.txt"), vec![1u8; ORIG_SIZE]));
+    unwrap!(std::fs::write(local.join("sub/b.txt"), vec![2u8; NEW_SIZE]));
+
+    let local2 = local.clone();
+
+    random_client(move |client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        let root = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let root2 = root.clone();
+
+        let expected = vec![
+            SyncAction::Upload("a.txt".to_string()),
+            SyncAction::CreateDir("sub".to_string()),
+            SyncAction::Upload("sub/b.txt".to_string()),
+        ];
+        let expected2 = expected.clone();
+
+        create_dir(client, &root, btree_map![], btree_map![])
+            .then(move |res| {
+                unwrap!(res);
+                sync::sync(c2, &local2, root.clone(), true).map(move |actions| (root, actions))
+            }).then(move |res| {
+                let (root, actions) = unwrap!(res);
+                assert_eq!(actions, expected);
+
+                path_helper::list(c3, root.clone(), "").map(move |entries| (root, entries))
+            }).then(move |res| {
+                let (root, entries) = unwrap!(res);
+                assert!(entries.is_empty());
+
+                sync::sync(c4, &local, root.clone(), false)
+                    .map(move |actions| (root, local, actions))
+            }).then(move |res| {
+                let (root, local, actions) = unwrap!(res);
+                assert_eq!(actions, expected2);
+
+                sync::sync(c5, &local, root.clone(), false)
+                    .map(move |actions| (root, local, actions))
+            }).then(move |res| {
+                let (root, local, actions) = unwrap!(res);
+                assert!(actions.is_empty());
+
+                unwrap!(std::fs::write(local.join("a.txt"), vec![3u8; ORIG_SIZE]));
+                unwrap!(std::fs::remove_dir_all(local.join("sub")));
+
+                sync::sync(c6, &local, root.clone(), false)
+                    .map(move |actions| (root, local, actions))
+            }).then(move |res| {
+                let (root, local, actions) = unwrap!(res);
+                assert_eq!(
+                    actions,
+                    vec![
+                        SyncAction::Delete("sub/b.txt".to_string()),
+                        SyncAction::RemoveDir("sub".to_string()),
+                        SyncAction::Update("a.txt".to_string()),
+                    ]
+                );
+                unwrap!(std::fs::remove_dir_all(&local));
+
+                path_helper::list(c7.clone(), root2.clone(), "")
+                    .join(path_helper::fetch_file(c7.clone(), root2.clone(), "a.txt"))
+                    .and_then(move |(entries, (_, file))| {
+                        assert_eq!(entries.len(), 1);
+                        file_helper::read(c7, &file, root2.enc_key().cloned())
+                    })
+            }).then(|res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                reader.read(0, size)
+            }).map(|data| {
+                assert_eq!(data, vec![3u8; ORIG_SIZE]);
+            })
+    });
+}
+
+// Test exporting a directory tree into an archive and importing it into another directory.
+#[test]
+fn dir_archive() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        let root2 = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let root3 = root2.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (root, file) = unwrap!(res);
+
+                // Store a file with user metadata in a subdirectory.
+                path_helper::ensure_dir(c2.clone(), root.clone(), "docs")
+                    .and_then(move |docs| {
+                        file_helper::write(
+                            c2.clone(),
+                            File::new(vec![7u8; 10]),
+                            Mode::Overwrite,
+                            docs.enc_key().cloned(),
+                        ).and_then(|writer| {
+                            writer
+                                .write(&[1u8; NEW_SIZE])
+                                .and_then(move |()| writer.close())
+                        }).and_then(move |notes| {
+                            file_helper::insert(c2, docs, "notes.txt", &notes)
+                                .map(move |()| notes)
+                        })
+                    }).map(move |notes| (root, file, notes))
+            }).then(move |res| {
+                let (root, file, notes) = unwrap!(res);
+                archive::export(c3, root, Vec::new()).map(move |data| (file, notes, data))
+            }).then(move |res| {
+                let (file, notes, data) = unwrap!(res);
+                assert_eq!(data.len() % 512, 0);
+
+                create_dir(&c4, &root2, btree_map![], btree_map![])
+                    .and_then(move |()| archive::import(c4, root2, Cursor::new(data)))
+                    .map(move |_| (file, notes))
+            }).then(move |res| {
+                let (file, notes) = unwrap!(res);
+
+                path_helper::fetch_file(c5.clone(), root3.clone(), "hello.txt")
+                    .join(path_helper::fetch_file(c5, root3.clone(), "docs/notes.txt"))
+                    .map(move |((_, imported_file), (_, imported_notes))| {
+                        assert_eq!(imported_file.size(), file.size());
+                        assert_eq!(imported_file.created_time(), file.created_time());
+                        assert_eq!(imported_file.modified_time(), file.modified_time());
+                        assert_eq!(imported_notes.user_metadata(), notes.user_metadata());
+                        assert_eq!(imported_notes.modified_time(), notes.modified_time());
+                        (root3, imported_notes)
+                    })
+            }).then(move |res| {
+                let (root, notes) = unwrap!(res);
+                path_helper::resolve(c6, root, "docs")
+                    .and_then(move |docs| file_helper::read(c7, &notes, docs.enc_key().cloned()))
+            }).then(|res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                reader.read(0, size)
+            }).map(|data| {
+                assert_eq!(data, vec![1u8; NEW_SIZE]);
+            })
+    });
+}
@@ -0,0 +1,252 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! The network doesn't notify clients about mutations made by others, so the watcher polls the
+//! versions of the watched directories instead. Whenever the version of a directory changes, its
+//! entries are fetched and compared with the previously seen ones, producing a change for every
+//! entry which has been created, modified or deleted since.
+//!
+//! Polling starts at the minimal interval of the `Backoff` and the interval doubles after every
+//! poll which found no changes, up to the maximal one. Any change resets it back to the minimum.
+
+use crate::client::mdata_info::is_hidden_entry;
+use crate::client::{Client, MDataInfo};
+use crate::nfs::history;
+use crate::nfs::{NfsError, NfsFuture};
+use crate::utils::FutureExt;
+use futures::future::{self, Future};
+use futures::stream::{self, Stream};
+use futures::Poll;
+use routing::Value;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Default interval between polls, in milliseconds, while the directories keep changing.
+pub const DEFAULT_MIN_INTERVAL_MS: u64 = 1000;
+/// Default interval between polls, in milliseconds, once the directories stopped changing.
+pub const DEFAULT_MAX_INTERVAL_MS: u64 = 60_000;
+
+/// Bounds of the interval between two polls.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Backoff {
+    /// Interval used after a change has been found.
+    pub min: Duration,
+    /// Upper bound of the interval while nothing changes.
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            min: Duration::from_millis(DEFAULT_MIN_INTERVAL_MS),
+            max: Duration::from_millis(DEFAULT_MAX_INTERVAL_MS),
+        }
+    }
+}
+
+/// Kind of a change made to a directory entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChangeKind {
+    /// The entry has been inserted.
+    Created,
+    /// The entry has been updated.
+    Modified,
+    /// The entry has been deleted.
+    Deleted,
+}
+
+/// Change made to an entry of one of the watched directories.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Change {
+    /// Index of the directory in the list passed to `Watcher::new`.
+    pub dir: usize,
+    /// Name of the entry. Names of subdirectory entries end with `/`.
+    pub name: String,
+    /// What happened to the entry.
+    pub kind: ChangeKind,
+}
+
+/// Stream of the changes made to the watched directories. The stream never ends on its own;
+/// polling stops when it is dropped or when an error occurs.
+pub struct Watcher {
+    inner: Box<Stream<Item = Change, Error = NfsError>>,
+}
+
+// Last seen state of a watched directory.
+struct Watched {
+    dir: MDataInfo,
+    version: Option<u64>,
+    entries: BTreeMap<String, u64>,
+}
+
+impl Watcher {
+    /// Start watching the directories. Their current state is fetched first, so only the changes
+    /// made afterwards are reported.
+    pub fn new(client: impl Client, dirs: Vec<MDataInfo>, backoff: Backoff) -> Self {
+        trace!("Watching {} directories", dirs.len());
+
+        let watched: Vec<_> = dirs
+            .into_iter()
+            .map(|dir| Watched {
+                dir,
+                version: None,
+                entries: BTreeMap::new(),
+            }).collect();
+
+        let inner = stream::unfold((watched, None), move |(watched, interval)| {
+            let client2 = client.clone();
+            let delay = match interval {
+                Some(interval) => client.delay(interval).map_err(NfsError::from).into_box(),
+                None => ok!(()),
+            };
+
+            let next = delay
+                .and_then(move |()| poll_dirs(&client2, watched))
+                .map(move |(watched, changes)| {
+                    let interval = match interval {
+                        Some(interval) if changes.is_empty() => cmp::min(interval * 2, backoff.max),
+                        _ => backoff.min,
+                    };
+                    (changes, (watched, Some(interval)))
+                });
+            Some(next)
+        }).map(stream::iter_ok::<_, NfsError>)
+        .flatten();
+
+        Watcher {
+            inner: Box::new(inner),
+        }
+    }
+}
+
+impl Stream for Watcher {
+    type Item = Change;
+    type Error = NfsError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll()
+    }
+}
+
+// Poll all the watched directories once.
+fn poll_dirs(
+    client: &impl Client,
+    watched: Vec<Watched>,
+) -> Box<NfsFuture<(Vec<Watched>, Vec<Change>)>> {
+    let polls = watched
+        .into_iter()
+        .enumerate()
+        .map(|(index, watched)| poll_dir(client, index, watched));
+
+    future::join_all(polls.collect::<Vec<_>>())
+        .map(|results| {
+            let mut watched = Vec::with_capacity(results.len());
+            let mut changes = Vec::new();
+
+            for (dir, dir_changes) in results {
+                watched.push(dir);
+                changes.extend(dir_changes);
+            }
+
+            (watched, changes)
+        }).into_box()
+}
+
+fn poll_dir(
+    client: &impl Client,
+    index: usize,
+    watched: Watched,
+) -> Box<NfsFuture<(Watched, Vec<Change>)>> {
+    let client2 = client.clone();
+
+    client
+        .get_mdata_version(watched.dir.name, watched.dir.type_tag)
+        .map_err(NfsError::from)
+        .and_then(move |version| {
+            if watched.version == Some(version) {
+                return ok!((watched, Vec::new()));
+            }
+
+            client2
+                .list_mdata_entries(watched.dir.name, watched.dir.type_tag)
+                .map_err(NfsError::from)
+                .map(move |entries| {
+                    let entries = visible_entries(&watched.dir, entries);
+                    // The first poll only records the initial state.
+                    let changes = if watched.version.is_some() {
+                        diff(index, &watched.entries, &entries)
+                    } else {
+                        Vec::new()
+                    };
+
+                    let watched = Watched {
+                        dir: watched.dir,
+                        version: Some(version),
+                        entries,
+                    };
+                    (watched, changes)
+                }).into_box()
+        }).into_box()
+}
+
+// Names and versions of the entries which are listed in the directory. File histories are
+// left out, as they change along with the files themselves. Entries which can't be decoded are
+// logged and skipped.
+fn visible_entries(dir: &MDataInfo, entries: BTreeMap<Vec<u8>, Value>) -> BTreeMap<String, u64> {
+    entries
+        .into_iter()
+        .filter(|&(ref key, ref value)| !is_hidden_entry(key, value))
+        .filter_map(|(key, value)| {
+            let name = match dir.decrypt(&key).map(String::from_utf8) {
+                Ok(Ok(name)) => name,
+                Ok(Err(error)) => {
+                    warn!("Skipping entry with a non UTF-8 name: {:?}", error);
+                    return None;
+                }
+                Err(error) => {
+                    warn!("Skipping entry which can't be decrypted: {:?}", error);
+                    return None;
+                }
+            };
+            if history::is_history_entry(&name) {
+                None
+            } else {
+                Some((name, value.entry_version))
+            }
+        }).collect()
+}
+
+fn diff(
+    index: usize,
+    previous: &BTreeMap<String, u64>,
+    current: &BTreeMap<String, u64>,
+) -> Vec<Change> {
+    let change = |name: &String, kind| Change {
+        dir: index,
+        name: name.clone(),
+        kind,
+    };
+
+    let mut changes: Vec<_> = current
+        .iter()
+        .filter_map(|(name, version)| match previous.get(name) {
+            None => Some(change(name, ChangeKind::Created)),
+            Some(previous) if previous != version => Some(change(name, ChangeKind::Modified)),
+            Some(_) => None,
+        }).collect();
+
+    changes.extend(
+        previous
+            .keys()
+            .filter(|name| !current.contains_key(*name))
+            .map(|name| change(name, ChangeKind::Deleted)),
+    );
+
+    changes
+}
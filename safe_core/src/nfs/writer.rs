@@ -10,10 +10,17 @@ use chrono::Utc;
 use crate::client::Client;
 use crate::crypto::shared_secretbox;
 use futures::Future;
-use crate::nfs::{data_map, File, NfsFuture};
-use self_encryption::SequentialEncryptor;
+use crate::immutable_data;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use crate::nfs::compression::Compressor;
+use crate::nfs::errors::io_error;
+use crate::nfs::{data_map, Compression, File, NfsError, NfsFuture};
+use self_encryption::{DataMap, SelfEncryptor, SequentialEncryptor};
 use crate::self_encryption_storage::SelfEncryptionStorage;
-use crate::utils::FutureExt;
+use crate::utils::{self, FutureExt};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 /// Mode of the writer
 #[derive(Clone, Copy, Debug)]
@@ -22,6 +29,39 @@ pub enum Mode {
     Overwrite,
     /// Will append content to the existing data
     Append,
+    /// Will modify the existing data at arbitrary positions
+    Modify,
+}
+
+enum Encryptor<C: Client> {
+    Sequential(SequentialEncryptor<SelfEncryptionStorage<C>>),
+    Positional(SelfEncryptor<SelfEncryptionStorage<C>>),
+}
+
+impl<C: Client> Encryptor<C> {
+    fn len(&self) -> u64 {
+        match *self {
+            Encryptor::Sequential(ref encryptor) => encryptor.len(),
+            Encryptor::Positional(ref encryptor) => encryptor.len(),
+        }
+    }
+
+    fn close(self) -> Box<NfsFuture<DataMap>> {
+        match self {
+            Encryptor::Sequential(encryptor) => encryptor.close(),
+            Encryptor::Positional(encryptor) => encryptor.close(),
+        }.map(|(data_map, _)| data_map)
+        .map_err(From::from)
+        .into_box()
+    }
+}
+
+// Writer state persisted by `Writer::checkpoint`. The data map references the chunks stored so
+// far, so it is encrypted with the writer's encryption key, if any.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    file: File,
+    data_map: DataMap,
 }
 
 /// Writer is used to write contents to a File and especially in chunks if the
@@ -29,8 +69,10 @@ pub enum Mode {
 pub struct Writer<C: Client> {
     client: C,
     file: File,
-    self_encryptor: SequentialEncryptor<SelfEncryptionStorage<C>>,
+    self_encryptor: Encryptor<C>,
+    compressor: RefCell<Compressor>,
     encryption_key: Option<shared_secretbox::Key>,
+    checkpoint_path: Option<PathBuf>,
 }
 
 impl<C: Client> Writer<C> {
@@ -43,36 +85,183 @@ impl<C: Client> Writer<C> {
         mode: Mode,
         encryption_key: Option<shared_secretbox::Key>,
     ) -> Box<NfsFuture<Writer<C>>> {
+        // Compressed contents can't be modified in place, only written anew.
+        let compression = match mode {
+            Mode::Overwrite => file.compression(),
+            Mode::Append | Mode::Modify if file.compression() == Compression::None => {
+                Compression::None
+            }
+            Mode::Append | Mode::Modify => {
+                return err!(NfsError::CompressedFile);
+            }
+        };
+
         let fut = match mode {
-            Mode::Append => data_map::get(client, file.data_map_name(), encryption_key.clone())
-                .map(Some)
-                .into_box(),
+            Mode::Append | Mode::Modify => {
+                data_map::get(client, file.data_map_name(), encryption_key.clone())
+                    .map(Some)
+                    .into_box()
+            }
             Mode::Overwrite => ok!(None),
         };
         let client = client.clone();
-        fut.and_then(move |data_map| {
-            SequentialEncryptor::new(storage, data_map).map_err(From::from)
+        fut.and_then(move |data_map| match (mode, data_map) {
+            (Mode::Modify, Some(data_map)) => {
+                let self_encryptor = fry!(SelfEncryptor::new(storage, data_map));
+                ok!(Encryptor::Positional(self_encryptor))
+            }
+            (_, data_map) => SequentialEncryptor::new(storage, data_map)
+                .map(Encryptor::Sequential)
+                .map_err(From::from)
+                .into_box(),
         })
         .map(move |self_encryptor| Writer {
             client,
             file,
             self_encryptor,
+            compressor: RefCell::new(Compressor::new(compression)),
             encryption_key,
+            checkpoint_path: None,
         })
         .map_err(From::from)
         .into_box()
     }
 
+    /// Resume writing from the checkpoint saved at `path` by `checkpoint`, e.g. after a restart.
+    /// The writer continues at the end of the data written before the checkpoint, see `size`.
+    /// The same `encryption_key` must be given as when the checkpoint was saved.
+    pub fn resume<P: AsRef<Path>>(
+        client: &C,
+        path: P,
+        encryption_key: Option<shared_secretbox::Key>,
+    ) -> Box<NfsFuture<Writer<C>>> {
+        let path = path.as_ref();
+        trace!("Resuming writer from checkpoint {}", path.display());
+
+        let content = fry!(fs::read(path).map_err(|error| io_error(path, &error)));
+        let content = match encryption_key {
+            Some(ref key) => fry!(utils::symmetric_decrypt(&content, key)),
+            None => content,
+        };
+        let Checkpoint { file, data_map } = fry!(deserialise(&content));
+
+        Self::reopen(client.clone(), file, data_map, encryption_key, path.to_path_buf())
+    }
+
+    /// Save the state of the writer to the local file at `path`, so that writing can be resumed
+    /// with `resume` if the process is interrupted. All the data written so far is stored on the
+    /// network first. Chunks which are already stored are not put again, neither by this writer
+    /// nor by writers resumed from the checkpoint. The checkpoint is removed when the writer is
+    /// closed. Only supported for uncompressed files in the sequential modes.
+    pub fn checkpoint<P: AsRef<Path>>(self, path: P) -> Box<NfsFuture<Writer<C>>> {
+        if self.compressor.borrow().compression() != Compression::None {
+            return err!(NfsError::CompressedFile);
+        }
+        if let Encryptor::Positional(_) = self.self_encryptor {
+            return err!(NfsError::from("Writers in modify mode can't be checkpointed"));
+        }
+
+        let path = path.as_ref().to_path_buf();
+        trace!("Saving writer checkpoint to {}", path.display());
+
+        let Writer {
+            client,
+            file,
+            self_encryptor,
+            encryption_key,
+            ..
+        } = self;
+
+        self_encryptor
+            .close()
+            .and_then(move |data_map| {
+                let checkpoint = Checkpoint { file, data_map };
+                fry!(save_checkpoint(&path, &checkpoint, encryption_key.as_ref()));
+
+                let Checkpoint { file, data_map } = checkpoint;
+                Self::reopen(client, file, data_map, encryption_key, path)
+            }).into_box()
+    }
+
+    // Continue writing after the data referenced by `data_map`.
+    fn reopen(
+        client: C,
+        file: File,
+        data_map: DataMap,
+        encryption_key: Option<shared_secretbox::Key>,
+        checkpoint_path: PathBuf,
+    ) -> Box<NfsFuture<Writer<C>>> {
+        let stored_chunks = immutable_data::data_map_chunk_names(&data_map)
+            .into_iter()
+            .collect();
+        let storage = SelfEncryptionStorage::with_stored_chunks(client.clone(), stored_chunks);
+
+        SequentialEncryptor::new(storage, Some(data_map))
+            .map(move |self_encryptor| Writer {
+                client,
+                file,
+                self_encryptor: Encryptor::Sequential(self_encryptor),
+                compressor: RefCell::new(Compressor::new(Compression::None)),
+                encryption_key,
+                checkpoint_path: Some(checkpoint_path),
+            }).map_err(From::from)
+            .into_box()
+    }
+
+    /// Returns the current size of the file/blob being written.
+    pub fn size(&self) -> u64 {
+        self.compressor
+            .borrow()
+            .total_in()
+            .unwrap_or_else(|| self.self_encryptor.len())
+    }
+
     /// Data of a file/blob can be written in smaller chunks
     pub fn write(&self, data: &[u8]) -> Box<NfsFuture<()>> {
         trace!(
             "Writer writing file data of size {} into self-encryptor.",
             data.len()
         );
-        self.self_encryptor
-            .write(data)
-            .map_err(From::from)
-            .into_box()
+        let data = fry!(self.compressor.borrow_mut().compress(data));
+
+        match self.self_encryptor {
+            Encryptor::Sequential(ref encryptor) => encryptor.write(&data),
+            Encryptor::Positional(ref encryptor) => encryptor.write(&data, encryptor.len()),
+        }.map_err(From::from)
+        .into_box()
+    }
+
+    /// Write data at the given position. Writing past the current end extends the file/blob,
+    /// filling any gap with zeros. Unless the writer was created in `Mode::Modify`, data can only
+    /// be written at the current end.
+    pub fn write_at(&self, position: u64, data: &[u8]) -> Box<NfsFuture<()>> {
+        trace!(
+            "Writer writing file data of size {} at position {} into self-encryptor.",
+            data.len(),
+            position
+        );
+        match self.self_encryptor {
+            Encryptor::Sequential(_) if position == self.size() => self.write(data),
+            Encryptor::Sequential(_) => err!(NfsError::InvalidRange),
+            Encryptor::Positional(ref encryptor) => encryptor
+                .write(data, position)
+                .map_err(From::from)
+                .into_box(),
+        }
+    }
+
+    /// Truncate or extend the file/blob to the given size. Extending fills the file/blob with
+    /// zeros. Only supported by writers created in `Mode::Modify`.
+    pub fn truncate(&self, size: u64) -> Box<NfsFuture<()>> {
+        trace!("Writer truncating file data to size {}.", size);
+        match self.self_encryptor {
+            Encryptor::Sequential(_) if size == self.size() => ok!(()),
+            Encryptor::Sequential(_) => err!(NfsError::InvalidRange),
+            Encryptor::Positional(ref encryptor) => encryptor
+                .truncate(size)
+                .map_err(From::from)
+                .into_box(),
+        }
     }
 
     /// close is invoked only after all the data is completely written. The
@@ -81,21 +270,61 @@ impl<C: Client> Writer<C> {
     pub fn close(self) -> Box<NfsFuture<File>> {
         trace!("Writer induced self-encryptor close.");
 
+        let size = self.size();
         let mut file = self.file;
-        let size = self.self_encryptor.len();
         let client = self.client;
         let encryption_key = self.encryption_key;
+        let self_encryptor = self.self_encryptor;
+        let checkpoint_path = self.checkpoint_path;
+        let compression = self.compressor.borrow().compression();
 
-        self.self_encryptor
-            .close()
-            .map_err(From::from)
-            .and_then(move |(data_map, _)| data_map::put(&client, &data_map, encryption_key))
+        // Write out whatever the compressor still holds before closing.
+        let remaining = fry!(self.compressor.into_inner().finish());
+        let fut = match self_encryptor {
+            Encryptor::Sequential(ref encryptor) if !remaining.is_empty() => {
+                encryptor.write(&remaining).map_err(From::from).into_box()
+            }
+            _ => ok!(()),
+        };
+
+        fut.and_then(move |()| self_encryptor.close())
+            .and_then(move |data_map| data_map::put(&client, &data_map, encryption_key))
             .map(move |data_map_name| {
                 file.set_data_map_name(data_map_name);
                 file.set_modified_time(Utc::now());
                 file.set_size(size);
+                file.set_compression(compression);
+
+                // The upload is complete, so there is nothing left to resume.
+                if let Some(path) = checkpoint_path {
+                    if let Err(error) = fs::remove_file(&path) {
+                        warn!("Failed to remove checkpoint {}: {}", path.display(), error);
+                    }
+                }
+
                 file
             })
             .into_box()
     }
 }
+
+// Write the checkpoint to a temporary file first, so an interruption never leaves a partially
+// written checkpoint behind.
+fn save_checkpoint(
+    path: &Path,
+    checkpoint: &Checkpoint,
+    encryption_key: Option<&shared_secretbox::Key>,
+) -> Result<(), NfsError> {
+    let content = serialise(checkpoint)?;
+    let content = match encryption_key {
+        Some(key) => utils::symmetric_encrypt(&content, key, None)?,
+        None => content,
+    };
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    fs::write(&temp_path, &content).map_err(|error| io_error(&temp_path, &error))?;
+    fs::rename(&temp_path, path).map_err(|error| io_error(path, &error))
+}
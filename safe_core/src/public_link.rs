@@ -0,0 +1,293 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A public file is a value stored unencrypted by `immutable_data::create`, so anyone knowing its
+//! name can read it. It is shared as a link of the form
+//!
+//! ```text
+//! safe-idata://<name>?type=<content type>&size=<size>
+//! ```
+//!
+//! where the name is the hex encoded name of the `ImmutableData`, and both the content type and the
+//! size of the content are optional. The content type is percent-encoded. Unknown query parameters
+//! are ignored, so later versions can add more of them.
+
+use crate::client::Client;
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::immutable_data;
+use crate::utils::FutureExt;
+use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+use futures::Future;
+use routing::{XorName, XOR_NAME_LEN};
+use std::fmt::{self, Display, Formatter};
+
+/// Scheme of the public file links.
+pub const SCHEME: &str = "safe-idata";
+
+const CONTENT_TYPE_PARAM: &str = "type";
+const SIZE_PARAM: &str = "size";
+
+/// Link to a public file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublicLink {
+    /// Name of the `ImmutableData` holding the file.
+    pub name: XorName,
+    /// MIME type of the content, if known.
+    pub content_type: Option<String>,
+    /// Size of the content in bytes, if known. Resolving the link fails if it doesn't match.
+    pub size: Option<u64>,
+}
+
+impl PublicLink {
+    /// Create a link to the public file stored under `name`.
+    pub fn new(name: XorName) -> Self {
+        PublicLink {
+            name,
+            content_type: None,
+            size: None,
+        }
+    }
+
+    /// Parse a link in the format produced by `to_uri`.
+    pub fn parse(uri: &str) -> Result<Self, CoreError> {
+        let prefix = format!("{}://", SCHEME);
+        let scheme = uri.get(..prefix.len()).unwrap_or("");
+        if !scheme.eq_ignore_ascii_case(&prefix) {
+            return Err(CoreError::InvalidLink(format!("Expected the {} scheme", SCHEME)));
+        }
+
+        let mut parts = uri[prefix.len()..].splitn(2, '?');
+        let name = parts.next().unwrap_or("");
+        let query = parts.next().unwrap_or("");
+
+        let name = HEXLOWER_PERMISSIVE
+            .decode(name.as_bytes())
+            .map_err(|_| CoreError::InvalidLink("Name is not hex encoded".to_owned()))?;
+        if name.len() != XOR_NAME_LEN {
+            return Err(CoreError::InvalidLink("Name has invalid length".to_owned()));
+        }
+        let mut link = PublicLink::new(XorName(array_from_slice(&name)));
+
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let mut param = param.splitn(2, '=');
+            let key = param.next().unwrap_or("");
+            let value = param.next().unwrap_or("");
+
+            match key {
+                CONTENT_TYPE_PARAM => link.content_type = Some(percent_decode(value)?),
+                SIZE_PARAM => {
+                    let size = value
+                        .parse()
+                        .map_err(|_| CoreError::InvalidLink(format!("Invalid size: {}", value)))?;
+                    link.size = Some(size);
+                }
+                _ => trace!("Ignoring unknown link parameter {}", key),
+            }
+        }
+
+        Ok(link)
+    }
+
+    /// Encode the link as a URI.
+    pub fn to_uri(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(ref content_type) = self.content_type {
+            params.push(format!(
+                "{}={}",
+                CONTENT_TYPE_PARAM,
+                percent_encode(content_type)
+            ));
+        }
+        if let Some(size) = self.size {
+            params.push(format!("{}={}", SIZE_PARAM, size));
+        }
+
+        let mut uri = format!("{}://{}", SCHEME, HEXLOWER.encode(&self.name.0));
+        if !params.is_empty() {
+            uri.push('?');
+            uri.push_str(&params.join("&"));
+        }
+        uri
+    }
+}
+
+impl Display for PublicLink {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.to_uri())
+    }
+}
+
+/// Store `content` on the network as a public file and return the link to it, including the
+/// size of the content and the given content type.
+pub fn publish(
+    client: &impl Client,
+    content: &[u8],
+    content_type: Option<String>,
+) -> Box<CoreFuture<PublicLink>> {
+    trace!("Publishing public file of size {}", content.len());
+
+    let client2 = client.clone();
+    let size = content.len() as u64;
+
+    immutable_data::create(client, content, None)
+        .and_then(move |data| {
+            let name = *data.name();
+            client2.put_idata(data).map(move |()| name)
+        }).map(move |name| PublicLink {
+            name,
+            content_type,
+            size: Some(size),
+        }).into_box()
+}
+
+/// Fetch the content of the public file the link refers to.
+pub fn resolve(client: &impl Client, link: &PublicLink) -> Box<CoreFuture<Vec<u8>>> {
+    trace!("Resolving public link {}", link);
+
+    let expected_size = link.size;
+
+    immutable_data::get_value(client, &link.name, None)
+        .and_then(move |content| match expected_size {
+            Some(size) if size != content.len() as u64 => Err(CoreError::InvalidLink(format!(
+                "Expected {} bytes, but the content has {}",
+                size,
+                content.len()
+            ))),
+            _ => Ok(content),
+        }).into_box()
+}
+
+fn array_from_slice(slice: &[u8]) -> [u8; XOR_NAME_LEN] {
+    let mut array = [0; XOR_NAME_LEN];
+    array.copy_from_slice(slice);
+    array
+}
+
+// Percent-encode all but the unreserved characters of RFC 3986.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn percent_decode(value: &str) -> Result<String, CoreError> {
+    let invalid = || CoreError::InvalidLink(format!("Invalid percent-encoding: {}", value));
+
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let high = bytes.next().and_then(hex_digit).ok_or_else(invalid)?;
+            let low = bytes.next().and_then(hex_digit).ok_or_else(invalid)?;
+            decoded.push(high << 4 | low);
+        } else {
+            decoded.push(byte);
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+    use crate::utils::test_utils::random_client;
+    use rand;
+
+    // Test encoding links and parsing them back.
+    #[test]
+    fn uri_roundtrip() {
+        let name: XorName = rand::random();
+
+        let link = PublicLink::new(name);
+        let uri = link.to_uri();
+        assert_eq!(uri, format!("safe-idata://{}", HEXLOWER.encode(&name.0)));
+        assert_eq!(unwrap!(PublicLink::parse(&uri)), link);
+
+        let link = PublicLink {
+            name,
+            content_type: Some("text/plain; charset=utf-8".to_owned()),
+            size: Some(1024),
+        };
+        let uri = link.to_uri();
+        assert!(uri.ends_with("?type=text%2Fplain%3B%20charset%3Dutf-8&size=1024"));
+        assert_eq!(unwrap!(PublicLink::parse(&uri)), link);
+
+        // The scheme and the name are case insensitive, and unknown parameters are ignored.
+        let uri = format!(
+            "SAFE-IDATA://{}?size=1024&foo=bar",
+            HEXLOWER.encode(&name.0).to_uppercase()
+        );
+        let parsed = unwrap!(PublicLink::parse(&uri));
+        assert_eq!(parsed.name, name);
+        assert_eq!(parsed.size, Some(1024));
+    }
+
+    // Test parsing malformed links.
+    #[test]
+    fn invalid_uri() {
+        let name = HEXLOWER.encode(&rand::random::<XorName>().0);
+        let invalid = vec![
+            format!("safe://{}", name),
+            "safe-idata://abcd".to_owned(),
+            "safe-idata://not hex".to_owned(),
+            format!("safe-idata://{}?size=large", name),
+            format!("safe-idata://{}?type=text%2", name),
+        ];
+
+        for uri in invalid {
+            match PublicLink::parse(&uri) {
+                Err(CoreError::InvalidLink(_)) => (),
+                res => panic!("Unexpected result for {}: {:?}", uri, res),
+            }
+        }
+    }
+
+    // Test publishing a file and resolving the link to it.
+    #[test]
+    fn publish_and_resolve() {
+        let content = unwrap!(utils::generate_random_vector(1024 * 1024));
+
+        random_client(move |client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let content2 = content.clone();
+
+            publish(client, &content, Some("image/png".to_owned()))
+                .and_then(move |link| {
+                    assert_eq!(link.size, Some(content2.len() as u64));
+
+                    let link = unwrap!(PublicLink::parse(&link.to_uri()));
+                    resolve(&client2, &link).map(move |resolved| {
+                        assert_eq!(resolved, content2);
+                        link
+                    })
+                }).and_then(move |mut link| {
+                    // A link with a wrong size is rejected.
+                    link.size = Some(1);
+                    resolve(&client3, &link).then(|res| match res {
+                        Err(CoreError::InvalidLink(_)) => Ok::<_, CoreError>(()),
+                        res => panic!("Unexpected result: {:?}", res),
+                    })
+                })
+        })
+    }
+}
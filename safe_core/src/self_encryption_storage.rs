@@ -10,19 +10,85 @@ use super::{Client, CoreError, FutureExt};
 use futures::{self, Future};
 use routing::{ImmutableData, XorName, XOR_NAME_LEN};
 use self_encryption::{Storage, StorageError};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
 
 /// Network storage is the concrete type which self-encryption crate will use
 /// to put or get data from the network.
 pub struct SelfEncryptionStorage<C: Client> {
     client: C,
+    // Shared with the pending puts, which record their chunks once they succeed.
+    stored_chunks: Option<Rc<RefCell<BTreeSet<XorName>>>>,
+    dedup_stats: Option<DedupStats>,
+}
+
+/// Counts the chunks, and their bytes, which a deduplicating `SelfEncryptionStorage` didn't put
+/// because they were stored already. Clones share the same counters, so a clone kept by the caller
+/// reflects the progress of the storage it was given to.
+#[derive(Clone, Debug, Default)]
+pub struct DedupStats {
+    counters: Rc<Cell<(u64, u64)>>,
+}
+
+impl DedupStats {
+    /// Create new counters, starting at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of chunks which were not put.
+    pub fn chunks(&self) -> u64 {
+        self.counters.get().0
+    }
+
+    /// Returns the total size of the chunks which were not put, in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.counters.get().1
+    }
+
+    fn record(&self, size: u64) {
+        let (chunks, bytes) = self.counters.get();
+        self.counters.set((chunks + 1, bytes + size));
+    }
 }
 
 impl<C: Client> SelfEncryptionStorage<C> {
     /// Create a new SelfEncryptionStorage instance.
     pub fn new(client: C) -> Self {
-        SelfEncryptionStorage { client }
+        SelfEncryptionStorage {
+            client,
+            stored_chunks: None,
+            dedup_stats: None,
+        }
+    }
+
+    /// Create a new SelfEncryptionStorage instance which skips putting the chunks known to be
+    /// stored on the network already. Chunks put successfully through this instance are
+    /// remembered as well.
+    pub fn with_stored_chunks(client: C, stored_chunks: BTreeSet<XorName>) -> Self {
+        SelfEncryptionStorage {
+            client,
+            stored_chunks: Some(Rc::new(RefCell::new(stored_chunks))),
+            dedup_stats: None,
+        }
+    }
+
+    /// Create a new SelfEncryptionStorage instance which checks whether every chunk is stored
+    /// already, either in the local caches or on the network, before putting it. Chunks which are
+    /// found are not put again, saving mutations when the same data is uploaded repeatedly. The
+    /// skipped chunks are counted in `dedup_stats`.
+    ///
+    /// Every check costs a GET, so this is only worth it when much of the data is expected to be
+    /// stored already.
+    pub fn deduplicating(client: C, dedup_stats: DedupStats) -> Self {
+        SelfEncryptionStorage {
+            client,
+            stored_chunks: None,
+            dedup_stats: Some(dedup_stats),
+        }
     }
 }
 
@@ -54,7 +120,49 @@ impl<C: Client> Storage for SelfEncryptionStorage<C> {
     fn put(&mut self, _: Vec<u8>, data: Vec<u8>) -> Box<Future<Item = (), Error = Self::Error>> {
         trace!("Self encrypt invoked PutIData.");
         let data = ImmutableData::new(data);
-        self.client.put_idata(data).map_err(From::from).into_box()
+
+        let name = *data.name();
+
+        if let Some(ref stored_chunks) = self.stored_chunks {
+            if stored_chunks.borrow().contains(&name) {
+                trace!("Chunk already stored, skipping PutIData.");
+                if let Some(ref dedup_stats) = self.dedup_stats {
+                    dedup_stats.record(data.value().len() as u64);
+                }
+                return futures::finished(()).into_box();
+            }
+        }
+
+        let put = match self.dedup_stats {
+            Some(ref dedup_stats) => {
+                let dedup_stats = dedup_stats.clone();
+                let client = self.client.clone();
+                let size = data.value().len() as u64;
+
+                self.client
+                    .get_idata(name)
+                    .then(move |result| match result {
+                        Ok(_) => {
+                            trace!("Chunk found, skipping PutIData.");
+                            dedup_stats.record(size);
+                            futures::finished(()).into_box()
+                        }
+                        // If the chunk can't be found for any reason, put it to be on the safe
+                        // side.
+                        Err(_) => client.put_idata(data),
+                    }).into_box()
+            }
+            None => self.client.put_idata(data),
+        };
+
+        // Only remember the chunk once it's stored, so a failed put is retried.
+        let stored_chunks = self.stored_chunks.clone();
+        put.map(move |()| {
+            if let Some(stored_chunks) = stored_chunks {
+                let _ = stored_chunks.borrow_mut().insert(name);
+            }
+        }).map_err(From::from)
+        .into_box()
     }
 }
 